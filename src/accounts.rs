@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+/// Names of local users and groups, looked up in the passwd and group databases once per id.
+/// Ids without an entry, and every id on platforms without those databases, show as numbers.
+#[derive(Debug, Default)]
+pub struct AccountNames {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+impl AccountNames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn user(&mut self, uid: u32) -> &str {
+        self.users
+            .entry(uid)
+            .or_insert_with(|| user_name(uid).unwrap_or_else(|| uid.to_string()))
+    }
+
+    pub fn group(&mut self, gid: u32) -> &str {
+        self.groups
+            .entry(gid)
+            .or_insert_with(|| group_name(gid).unwrap_or_else(|| gid.to_string()))
+    }
+}
+
+/// Starting size of the buffer `getpwuid_r` and `getgrgid_r` fill with strings; doubled while
+/// they report `ERANGE`, up to `MAX_BUFFER`.
+#[cfg(target_os = "linux")]
+const INITIAL_BUFFER: usize = 1024;
+#[cfg(target_os = "linux")]
+const MAX_BUFFER: usize = 1 << 20;
+
+#[cfg(target_os = "linux")]
+fn user_name(uid: u32) -> Option<String> {
+    with_buffer(|buffer| {
+        // SAFETY: `passwd` is plain data; `getpwuid_r` fills it with pointers into `buffer`,
+        // which outlives the read of the name.
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let status = unsafe {
+            libc::getpwuid_r(
+                uid,
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        let name = (!result.is_null()).then(|| unsafe { c_string(entry.pw_name) });
+        (status, name.flatten())
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn group_name(gid: u32) -> Option<String> {
+    with_buffer(|buffer| {
+        // SAFETY: as for `user_name`.
+        let mut entry: libc::group = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let status = unsafe {
+            libc::getgrgid_r(
+                gid,
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        let name = (!result.is_null()).then(|| unsafe { c_string(entry.gr_name) });
+        (status, name.flatten())
+    })
+}
+
+/// Runs a reentrant lookup, growing the string buffer while it is too small.
+#[cfg(target_os = "linux")]
+fn with_buffer(
+    mut call: impl FnMut(&mut [libc::c_char]) -> (libc::c_int, Option<String>),
+) -> Option<String> {
+    let mut size = INITIAL_BUFFER;
+    loop {
+        let mut buffer = vec![0; size];
+        match call(&mut buffer) {
+            (libc::ERANGE, _) if size < MAX_BUFFER => size *= 2,
+            (0, name) => return name,
+            _ => return None,
+        }
+    }
+}
+
+/// # Safety
+/// `ptr` must be null or point to a NUL-terminated string.
+#[cfg(target_os = "linux")]
+unsafe fn c_string(ptr: *const libc::c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(ptr) };
+    name.to_str().ok().map(str::to_string)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn group_name(_gid: u32) -> Option<String> {
+    None
+}
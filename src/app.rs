@@ -1,6 +1,6 @@
 use std::collections::{BTreeMap, BTreeSet};
-use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use crossbeam_channel::Receiver;
@@ -8,18 +8,31 @@ use eframe::egui::{self, Align, Layout};
 use egui_extras::{Column, TableBuilder};
 use serde::{Deserialize, Serialize};
 
+use crate::accounts::AccountNames;
 use crate::cache::{self, Cache, RootCache};
-use crate::fs::{FileEntry, FileKind};
+use crate::duplicates::{DuplicateGroup, DuplicateStats, LinkMethod, LinkReport};
+use crate::filesystem::FileSystem;
+use crate::fs::{Allocation, FileCategory, FileEntry, FileKind, ScanError, ScanErrorKind};
 use crate::query::{SearchQuery, parse_input};
-use crate::scanner::{CacheContext, ScanMessage, ScanStats, ScannerHandle, spawn};
-use crate::tree::TreeStore;
+use crate::scanner::{
+    CacheContext, RootScan, ScanMessage, ScanProgress, ScanStats, ScannerConfig, ScannerHandle,
+    spawn_with_config,
+};
+use crate::throttle::LowImpact;
+use crate::tree::{SizeMetric, TreeNode, TreeStore};
 use crate::util::{format_size, format_system_time};
 use crate::watcher::{self, WatchEventKind, WatchHandle};
 
-const COLUMN_COUNT: usize = 6;
-const DEFAULT_COLUMN_WIDTHS: [f32; COLUMN_COUNT] = [32.0, 260.0, 110.0, 130.0, 150.0, 150.0];
-const COLUMN_LABELS: [&str; COLUMN_COUNT] =
-    ["Stage", "Name", "Size", "Total", "Modified", "Created"];
+/// Smallest file the duplicate finder considers; empty files are all alike and free nothing.
+const DUPLICATE_MIN_SIZE: u64 = 1;
+/// Path of the group node heading the tree when several roots are scanned together.
+const ALL_ROOTS: &str = "";
+const COLUMN_COUNT: usize = 8;
+const DEFAULT_COLUMN_WIDTHS: [f32; COLUMN_COUNT] =
+    [32.0, 260.0, 110.0, 130.0, 150.0, 150.0, 140.0, 100.0];
+const COLUMN_LABELS: [&str; COLUMN_COUNT] = [
+    "Stage", "Name", "Size", "Total", "Modified", "Created", "Owner", "Mode",
+];
 
 pub struct DiskSpaceApp {
     scanner: ScannerHandle,
@@ -28,43 +41,82 @@ pub struct DiskSpaceApp {
     search_input: String,
     status_text: Option<String>,
     last_error: Option<String>,
+    /// Every error reported for the tree's scan, or loaded with it from the cache.
+    scan_errors: Vec<ScanError>,
+    show_errors_panel: bool,
+    /// Kind the errors panel is narrowed to; `None` lists all of them.
+    error_filter: Option<ScanErrorKind>,
     active_job_id: Option<u64>,
+    /// Scan whose results the tree shows; subtree scans are issued under it.
+    tree_job_id: Option<u64>,
     pending_job_id: Option<u64>,
     pending_clear_job_id: Option<u64>,
     active_root: Option<PathBuf>,
     expanded: BTreeSet<PathBuf>,
+    /// Directories with a subtree rescan queued or running.
+    loading_subtrees: BTreeSet<PathBuf>,
+    /// Subtrees being rescanned, with the tree generation their refresh started at.
+    refreshing: BTreeMap<PathBuf, u64>,
+    max_depth: Option<usize>,
     entries_seen: usize,
     current_query: SearchQuery,
     staged: BTreeSet<PathBuf>,
     show_commit_modal: bool,
+    show_link_modal: bool,
+    /// Result of the last duplicate search, largest savings first.
+    duplicates: Vec<DuplicateGroup>,
+    duplicate_stats: Option<DuplicateStats>,
+    duplicates_searching: bool,
+    /// A link request is running on the scanner thread.
+    linking: bool,
+    show_duplicates: bool,
+    /// Directory the breakdown panel describes; `None` falls back to the active root.
+    selected_dir: Option<PathBuf>,
+    show_breakdown: bool,
     cache: Cache,
+    /// Filesystem staged deletions go through, shared with the scanner.
+    filesystem: Arc<dyn FileSystem>,
+    low_impact: Option<LowImpact>,
     cache_root_id: i64,
     canonical_root: PathBuf,
+    /// Every root of a multi-root session, the working directory first; empty for a single root.
+    scan_roots: Vec<ScanRoot>,
     last_stats: Option<ScanStats>,
+    progress: Option<ScanProgress>,
+    scan_running: bool,
+    scan_paused: bool,
     watch_enabled: bool,
     watch_handle: Option<WatchHandle>,
     watch_rescan_due: bool,
+    /// Directories the watcher saw change, rescanned in place once no scan is running.
+    watch_rescan_dirs: BTreeSet<PathBuf>,
     ui_state_dirty: bool,
     ui_state_next_save: Option<Instant>,
     watcher_config: watcher::WatcherConfig,
     sort_mode: SortMode,
+    size_metric: SizeMetric,
     column_widths: [f32; COLUMN_COUNT],
     show_layout_modal: bool,
+    accounts: AccountNames,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct AppConfig {
     pub enable_watchers: bool,
     pub watcher_config: watcher::WatcherConfig,
+    pub scanner_config: ScannerConfig,
+    /// Walk only this many levels up front and scan deeper directories when they are expanded.
+    pub max_depth: Option<usize>,
+    /// Canonical roots shown together under one top node, the working directory first. With
+    /// fewer than two, only the working directory is scanned.
+    pub roots: Vec<PathBuf>,
 }
 
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            enable_watchers: false,
-            watcher_config: watcher::WatcherConfig::default(),
-        }
-    }
+/// A root of a multi-root session and the cache row its results are stored under.
+#[derive(Debug, Clone)]
+struct ScanRoot {
+    path: PathBuf,
+    cache_root_id: i64,
 }
 
 const UI_STATE_VERSION: i64 = 2;
@@ -84,6 +136,8 @@ struct PersistedUiState {
     watch_enabled: bool,
     sort_mode: SortMode,
     column_widths: Vec<f32>,
+    #[serde(default)]
+    size_metric: SizeMetric,
 }
 
 impl DiskSpaceApp {
@@ -92,7 +146,7 @@ impl DiskSpaceApp {
     }
 
     pub fn with_config(_cc: &eframe::CreationContext<'_>, config: AppConfig) -> Self {
-        let (scanner, scan_rx) = spawn();
+        let (scanner, scan_rx) = spawn_with_config(config.scanner_config.clone());
         let mut initial_query = SearchQuery::default();
         let cache = Cache::open().expect("failed to open cache");
         let canonical_root = std::env::current_dir()
@@ -104,6 +158,7 @@ impl DiskSpaceApp {
             .expect("failed to load cache entries");
 
         initial_query.root = canonical_root.clone();
+        initial_query.max_depth = config.max_depth;
 
         let mut app = Self {
             scanner,
@@ -112,40 +167,74 @@ impl DiskSpaceApp {
             search_input: initial_query.raw.clone(),
             status_text: None,
             last_error: None,
+            scan_errors: Vec::new(),
+            show_errors_panel: false,
+            error_filter: None,
             active_job_id: None,
+            tree_job_id: None,
             pending_job_id: None,
             pending_clear_job_id: None,
             active_root: None,
             expanded: BTreeSet::new(),
+            loading_subtrees: BTreeSet::new(),
+            refreshing: BTreeMap::new(),
+            max_depth: config.max_depth,
             entries_seen: 0,
             current_query: initial_query.clone(),
             staged: BTreeSet::new(),
             show_commit_modal: false,
+            show_link_modal: false,
+            duplicates: Vec::new(),
+            duplicate_stats: None,
+            duplicates_searching: false,
+            linking: false,
+            show_duplicates: false,
+            selected_dir: None,
+            show_breakdown: false,
             cache,
+            filesystem: config.scanner_config.filesystem.clone(),
+            low_impact: config.scanner_config.low_impact,
             cache_root_id: root_cache.root_id,
             canonical_root: canonical_root.clone(),
+            scan_roots: Vec::new(),
             last_stats: None,
+            progress: None,
+            scan_running: false,
+            scan_paused: false,
             watch_enabled: config.enable_watchers,
             watch_handle: None,
             watch_rescan_due: false,
+            watch_rescan_dirs: BTreeSet::new(),
             ui_state_dirty: false,
             ui_state_next_save: None,
             watcher_config: config.watcher_config.clone(),
             sort_mode: SortMode::default(),
+            size_metric: SizeMetric::default(),
             column_widths: DEFAULT_COLUMN_WIDTHS,
             show_layout_modal: false,
+            accounts: AccountNames::new(),
         };
 
         app.expanded.insert(canonical_root.clone());
-        app.populate_tree_from_cache(root_cache);
-        app.load_persisted_state();
-        app.active_root = Some(canonical_root.clone());
+        if config.roots.len() > 1 {
+            app.load_persisted_state();
+            app.open_roots(&config.roots);
+        } else {
+            app.populate_tree_from_cache(&canonical_root, root_cache);
+            app.load_persisted_state();
+            app.active_root = Some(canonical_root.clone());
+        }
 
-        let job_id = app
-            .scanner
-            .request_scan(initial_query.clone(), Some(app.cache_context()));
+        let job_id = if app.is_multi_root() {
+            let scans = app.root_scans(&initial_query);
+            app.scanner.request_scan_roots(scans)
+        } else {
+            app.scanner
+                .request_scan(initial_query.clone(), Some(app.cache_context()))
+        };
         app.pending_job_id = Some(job_id);
-        app.status_text = Some(format!("Scanning {}…", initial_query.root.display()));
+        let label = app.describe_root(app.active_root.as_deref().unwrap_or(&canonical_root));
+        app.status_text = Some(format!("Scanning {label}…"));
 
         app
     }
@@ -162,6 +251,8 @@ impl eframe::App for DiskSpaceApp {
             self.render_top_bar(ui, ctx);
         });
 
+        self.render_breakdown(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             if let Some(root) = self.active_root.clone() {
                 self.render_tree(ui, &root);
@@ -175,8 +266,11 @@ impl eframe::App for DiskSpaceApp {
         });
 
         self.render_commit_modal(ctx);
+        self.render_link_modal(ctx);
         self.persist_ui_state();
         self.render_layout_modal(ctx);
+        self.render_errors_panel(ctx);
+        self.render_duplicates(ctx);
     }
 }
 
@@ -189,6 +283,92 @@ impl DiskSpaceApp {
         }
     }
 
+    fn is_multi_root(&self) -> bool {
+        !self.scan_roots.is_empty()
+    }
+
+    /// Loads every root's cache into a tree headed by the `ALL_ROOTS` group node.
+    fn open_roots(&mut self, roots: &[PathBuf]) {
+        for root in roots {
+            match self.cache.load_root(root) {
+                Ok(root_cache) => {
+                    self.scan_roots.push(ScanRoot {
+                        path: root.clone(),
+                        cache_root_id: root_cache.root_id,
+                    });
+                    self.populate_tree_from_cache(root, root_cache);
+                }
+                Err(err) => {
+                    self.last_error =
+                        Some(format!("{}: failed to open cache: {err}", root.display()));
+                }
+            }
+        }
+
+        let group = PathBuf::from(ALL_ROOTS);
+        let paths: Vec<PathBuf> = self
+            .scan_roots
+            .iter()
+            .map(|root| root.path.clone())
+            .collect();
+        self.tree.insert_group(&group, "All roots", &paths);
+        self.expanded.insert(group.clone());
+        self.expanded.extend(paths);
+        self.active_root = Some(group);
+    }
+
+    /// One scan per root of a multi-root session, each sharing `query`'s filters.
+    fn root_scans(&self, query: &SearchQuery) -> Vec<RootScan> {
+        self.scan_roots
+            .iter()
+            .map(|root| RootScan {
+                query: SearchQuery {
+                    root: root.path.clone(),
+                    ..query.clone()
+                },
+                cache: Some(CacheContext {
+                    cache: self.cache.clone(),
+                    root_id: root.cache_root_id,
+                    canonical_root: root.path.clone(),
+                }),
+            })
+            .collect()
+    }
+
+    /// Root of the current scan that contains `path`, with the cache context for it if the
+    /// scan is cached.
+    fn scan_root_for(&self, path: &Path) -> Option<(PathBuf, Option<CacheContext>)> {
+        if self.is_multi_root() {
+            return self
+                .scan_roots
+                .iter()
+                .find(|root| path.starts_with(&root.path))
+                .map(|root| {
+                    let ctx = CacheContext {
+                        cache: self.cache.clone(),
+                        root_id: root.cache_root_id,
+                        canonical_root: root.path.clone(),
+                    };
+                    (root.path.clone(), Some(ctx))
+                });
+        }
+
+        let root = &self.current_query.root;
+        if !path.starts_with(root) {
+            return None;
+        }
+        let ctx = (*root == self.canonical_root).then(|| self.cache_context());
+        Some((root.clone(), ctx))
+    }
+
+    fn describe_root(&self, root: &Path) -> String {
+        if self.is_multi_root() && root == Path::new(ALL_ROOTS) {
+            format!("{} roots", self.scan_roots.len())
+        } else {
+            root.display().to_string()
+        }
+    }
+
     fn clear_active_cache(&mut self) {
         if self.pending_clear_job_id.is_some() {
             return;
@@ -204,6 +384,7 @@ impl DiskSpaceApp {
 
     fn sync_watcher(&mut self) {
         let should_run = self.watch_enabled
+            && !self.is_multi_root()
             && self
                 .active_root
                 .as_ref()
@@ -254,6 +435,14 @@ impl DiskSpaceApp {
         self.schedule_ui_state_save();
     }
 
+    fn toggle_size_metric(&mut self) {
+        self.size_metric = match self.size_metric {
+            SizeMetric::Apparent => SizeMetric::Allocated,
+            SizeMetric::Allocated => SizeMetric::Apparent,
+        };
+        self.schedule_ui_state_save();
+    }
+
     fn handle_watch_event(&mut self, path: PathBuf, kind: WatchEventKind) {
         match kind {
             WatchEventKind::Dirty => {
@@ -263,7 +452,12 @@ impl DiskSpaceApp {
                         .parent_relative(&relative)
                         .unwrap_or_else(|| PathBuf::from("."));
                     let _ = self.cache.mark_ancestors_dirty(self.cache_root_id, &target);
-                    self.watch_rescan_due = true;
+                    let absolute = if target == Path::new(".") {
+                        self.canonical_root.clone()
+                    } else {
+                        self.canonical_root.join(&target)
+                    };
+                    self.watch_rescan_dirs.insert(absolute);
                 }
             }
             WatchEventKind::Rescan => {
@@ -277,22 +471,27 @@ impl DiskSpaceApp {
     }
 
     fn relative_to_root(&self, path: &Path) -> Option<PathBuf> {
-        path.strip_prefix(&self.canonical_root)
-            .map(|rel| {
-                if rel.as_os_str().is_empty() {
-                    PathBuf::from(".")
-                } else {
-                    rel.to_path_buf()
-                }
-            })
-            .ok()
+        path.starts_with(&self.canonical_root)
+            .then(|| relative_to(&self.canonical_root, path))
     }
 
     fn maybe_trigger_watch_rescan(&mut self) {
-        if self.watch_rescan_due && self.pending_job_id.is_none() && self.active_job_id.is_none() {
+        if self.pending_job_id.is_some() || self.active_job_id.is_some() {
+            return;
+        }
+        if self.watch_rescan_due {
             self.watch_rescan_due = false;
             self.status_text = Some("Watcher detected changes; rescanning…".to_string());
             self.trigger_scan();
+        } else if !self.watch_rescan_dirs.is_empty() {
+            let dirs = outermost_dirs(std::mem::take(&mut self.watch_rescan_dirs));
+            self.status_text = Some(format!(
+                "Watcher detected changes; rescanning {} folder(s)…",
+                dirs.len()
+            ));
+            for dir in dirs {
+                self.rescan_subtree(dir);
+            }
         }
     }
 
@@ -358,7 +557,9 @@ impl DiskSpaceApp {
 
         if state.version >= 2 {
             self.sort_mode = state.sort_mode;
-            if state.column_widths.len() == COLUMN_COUNT {
+            self.size_metric = state.size_metric;
+            // Layouts saved before a column was added keep their widths for the older columns.
+            if state.column_widths.len() <= COLUMN_COUNT {
                 for (idx, value) in state.column_widths.iter().enumerate() {
                     self.column_widths[idx] = *value;
                 }
@@ -373,10 +574,10 @@ impl DiskSpaceApp {
             return;
         }
 
-        if let Some(deadline) = self.ui_state_next_save {
-            if Instant::now() < deadline {
-                return;
-            }
+        if let Some(deadline) = self.ui_state_next_save
+            && Instant::now() < deadline
+        {
+            return;
         }
 
         let expanded: Vec<String> = self
@@ -405,7 +606,8 @@ impl DiskSpaceApp {
             staged,
             watch_enabled: self.watch_enabled,
             sort_mode: self.sort_mode,
-            column_widths: self.column_widths.iter().copied().collect(),
+            column_widths: self.column_widths.to_vec(),
+            size_metric: self.size_metric,
         };
 
         match serde_json::to_string(&state) {
@@ -474,6 +676,40 @@ impl DiskSpaceApp {
                 self.cycle_sort_mode();
             }
 
+            ui.add_space(8.0);
+            let metric_label = match self.size_metric {
+                SizeMetric::Apparent => "Size: Apparent",
+                SizeMetric::Allocated => "Size: On disk",
+            };
+            if ui
+                .button(metric_label)
+                .on_hover_text("Switch between apparent length and allocated disk usage")
+                .clicked()
+            {
+                self.toggle_size_metric();
+            }
+
+            ui.add_space(8.0);
+            if ui
+                .button("Duplicates")
+                .on_hover_text("Find files with identical contents")
+                .clicked()
+            {
+                self.show_duplicates = true;
+                if self.duplicate_stats.is_none() && !self.duplicates_searching {
+                    self.find_duplicates();
+                }
+            }
+
+            ui.add_space(8.0);
+            if ui
+                .selectable_label(self.show_breakdown, "Breakdown")
+                .on_hover_text("Show how the selected folder splits by file type and owner")
+                .clicked()
+            {
+                self.show_breakdown = !self.show_breakdown;
+            }
+
             ui.add_space(8.0);
             if ui.button("Layout").clicked() {
                 self.show_layout_modal = true;
@@ -510,7 +746,9 @@ impl DiskSpaceApp {
                     .column(Column::exact(self.column_widths[2]).clip(false))
                     .column(Column::exact(self.column_widths[3]).clip(false))
                     .column(Column::exact(self.column_widths[4]).clip(false))
-                    .column(Column::exact(self.column_widths[5]).clip(false));
+                    .column(Column::exact(self.column_widths[5]).clip(false))
+                    .column(Column::exact(self.column_widths[6]).clip(true))
+                    .column(Column::exact(self.column_widths[7]).clip(false));
 
                 table
                     .header(24.0, |mut header| {
@@ -532,6 +770,12 @@ impl DiskSpaceApp {
                         header.col(|ui| {
                             ui.strong("Created");
                         });
+                        header.col(|ui| {
+                            ui.strong("Owner");
+                        });
+                        header.col(|ui| {
+                            ui.strong("Mode");
+                        });
                     })
                     .body(|body| {
                         let row_count = rows.len();
@@ -544,14 +788,27 @@ impl DiskSpaceApp {
                                 node_ref.clone()
                             };
                             let path_buf = path.clone();
-                            let is_directory = node.kind == FileKind::Directory;
+                            let is_directory = node.kind == FileKind::Directory
+                                && !node.mount_point
+                                && !node.excluded;
+                            // Archives listed with `--archives` open like folders.
+                            let expandable = is_directory || !node.children.is_empty();
+                            let unknown_size = node.mount_point || node.excluded;
                             let is_expanded_initial = self.expanded.contains(&path_buf);
+                            let loading = self.loading_subtrees.contains(&path_buf);
                             let is_staged_initial = self.staged.contains(&path_buf);
 
                             let mut staged_action = None;
                             row.col(|ui| {
                                 let mut staged_state = is_staged_initial;
-                                if ui.add(egui::Checkbox::new(&mut staged_state, "")).changed() {
+                                // Deleting through a mount point would reach into another filesystem,
+                                // and archive members do not exist on disk.
+                                let stageable = !node.mount_point && !node.in_archive;
+                                let checkbox = ui.add_enabled(
+                                    stageable,
+                                    egui::Checkbox::new(&mut staged_state, ""),
+                                );
+                                if checkbox.changed() {
                                     staged_action = Some(staged_state);
                                 }
                             });
@@ -569,7 +826,7 @@ impl DiskSpaceApp {
                             row.col(|ui| {
                                 let _ = ui.horizontal(|ui| {
                                     ui.add_space((*depth as f32) * 16.0);
-                                    if is_directory {
+                                    if expandable {
                                         let icon = if is_expanded_initial { "▾" } else { "▸" };
                                         let button = egui::Button::new(icon)
                                             .frame(false)
@@ -579,25 +836,72 @@ impl DiskSpaceApp {
                                         }
                                         ui.add_space(4.0);
                                     } else {
-                                        ui.add_space(20.0);
+                                        ui.add_sized(
+                                            egui::vec2(16.0, 16.0),
+                                            egui::Label::new(kind_icon(&node)),
+                                        );
+                                        ui.add_space(4.0);
                                     }
-                                    let response = ui.label(node.name.clone());
+                                    let response = if node.excluded {
+                                        ui.weak(node.name.clone())
+                                    } else if is_directory || path == root {
+                                        let selected = self.selected_dir.as_ref() == Some(path);
+                                        let response =
+                                            ui.selectable_label(selected, node.name.clone());
+                                        if response.clicked() {
+                                            self.selected_dir = Some(path_buf.clone());
+                                        }
+                                        response
+                                    } else {
+                                        ui.label(node.name.clone())
+                                    };
                                     label_response = Some(response);
+                                    render_badges(ui, &node);
+                                    if node.kind == FileKind::File && !node.children.is_empty() {
+                                        let unpacked: u64 = node
+                                            .children
+                                            .iter()
+                                            .map(|child| {
+                                                self.tree.aggregated_size_with_cache(
+                                                    child,
+                                                    SizeMetric::Apparent,
+                                                    &mut BTreeMap::new(),
+                                                )
+                                            })
+                                            .sum();
+                                        ui.weak(format!("{} unpacked", format_size(unpacked)));
+                                    }
+                                    if loading {
+                                        ui.spinner();
+                                    }
                                 });
 
-                                if path == root {
-                                    if let Some(resp) = label_response.take() {
-                                        resp.context_menu(|ui| {
-                                            if ui.button("Clear Cache").clicked() {
-                                                self.clear_active_cache();
-                                                ui.close_menu();
-                                            }
-                                        });
-                                    }
+                                if (path == root || is_directory)
+                                    && let Some(resp) = label_response.take()
+                                {
+                                    resp.context_menu(|ui| {
+                                        if is_directory
+                                            && !node.in_archive
+                                            && ui.button("Rescan this folder").clicked()
+                                        {
+                                            self.rescan_folder(path_buf.clone());
+                                            ui.close_menu();
+                                        }
+                                        if path == root
+                                            && !self.is_multi_root()
+                                            && ui.button("Clear Cache").clicked()
+                                        {
+                                            self.clear_active_cache();
+                                            ui.close_menu();
+                                        }
+                                    });
                                 }
                             });
                             if let Some(open) = expand_action {
                                 if open {
+                                    if node.unscanned {
+                                        self.rescan_subtree(path_buf.clone());
+                                    }
                                     self.expanded.insert(path_buf.clone());
                                 } else {
                                     self.expanded.remove(&path_buf);
@@ -606,13 +910,36 @@ impl DiskSpaceApp {
                             }
 
                             row.col(|ui| {
-                                ui.label(format_size(node.direct_size));
+                                if unknown_size {
+                                    ui.weak("—");
+                                } else {
+                                    ui.label(format_size(node.size(self.size_metric)));
+                                }
                             });
 
-                            let aggregated =
-                                self.tree.aggregated_size_with_cache(path, &mut size_cache);
+                            let aggregated = self.tree.aggregated_size_with_cache(
+                                path,
+                                self.size_metric,
+                                &mut size_cache,
+                            );
+                            let estimated = self.tree.contains_estimate(path);
                             row.col(|ui| {
-                                ui.label(format_size(aggregated));
+                                if unknown_size {
+                                    ui.weak("—");
+                                } else if node.unscanned && node.estimate.is_none() {
+                                    ui.weak("?").on_hover_text(
+                                        "Not scanned yet and not in the cache; expand to scan",
+                                    );
+                                } else if estimated {
+                                    let text = format!("≈ {}", format_size(aggregated));
+                                    ui.label(egui::RichText::new(text).italics().weak())
+                                        .on_hover_text(
+                                            "Includes totals from an earlier scan for folders \
+                                             below the depth limit; expand them to scan",
+                                        );
+                                } else {
+                                    ui.label(format_size(aggregated));
+                                }
                             });
 
                             row.col(|ui| {
@@ -622,6 +949,32 @@ impl DiskSpaceApp {
                             row.col(|ui| {
                                 ui.label(format_system_time(node.created));
                             });
+
+                            row.col(|ui| match node.owner {
+                                Some(owner) => {
+                                    let user = self.accounts.user(owner.uid).to_string();
+                                    let group = self.accounts.group(owner.gid);
+                                    ui.label(format!("{user}:{group}")).on_hover_text(format!(
+                                        "uid {}, gid {}",
+                                        owner.uid, owner.gid
+                                    ));
+                                }
+                                None => {
+                                    ui.weak("—");
+                                }
+                            });
+
+                            row.col(|ui| match node.owner {
+                                Some(owner) => {
+                                    let mode =
+                                        format!("{}{}", node.kind.type_char(), owner.mode_string());
+                                    ui.monospace(mode)
+                                        .on_hover_text(format!("{:04o}", owner.mode));
+                                }
+                                None => {
+                                    ui.weak("—");
+                                }
+                            });
                         });
                     });
             });
@@ -644,8 +997,8 @@ impl DiskSpaceApp {
 
         let is_root = path == root;
         let should_show = match node.kind {
-            FileKind::File => true,
             FileKind::Directory => is_root || node.contains_match,
+            _ => true,
         };
 
         if !should_show {
@@ -654,7 +1007,8 @@ impl DiskSpaceApp {
 
         rows.push((path.to_path_buf(), depth));
 
-        if node.kind == FileKind::Directory && self.expanded.contains(path) {
+        let has_children = node.kind == FileKind::Directory || !node.children.is_empty();
+        if has_children && self.expanded.contains(path) {
             let mut children = self.tree.children(path);
             match self.sort_mode {
                 SortMode::NameAsc => {
@@ -662,8 +1016,12 @@ impl DiskSpaceApp {
                 }
                 SortMode::SizeDesc => {
                     children.sort_by(|lhs, rhs| {
-                        let lhs_size = self.tree.aggregated_size_with_cache(lhs, size_cache);
-                        let rhs_size = self.tree.aggregated_size_with_cache(rhs, size_cache);
+                        let lhs_size =
+                            self.tree
+                                .aggregated_size_with_cache(lhs, self.size_metric, size_cache);
+                        let rhs_size =
+                            self.tree
+                                .aggregated_size_with_cache(rhs, self.size_metric, size_cache);
                         rhs_size
                             .cmp(&lhs_size)
                             .then_with(|| compare_paths(&self.tree, lhs, rhs))
@@ -684,17 +1042,91 @@ impl DiskSpaceApp {
                 ui.label("Ready");
             }
 
+            if let Some(low_impact) = &self.low_impact {
+                ui.add_space(12.0);
+                let mut label = "Low impact".to_string();
+                if let Some(rate) = self.progress.as_ref().and_then(|p| p.throttled_rate)
+                    && rate < f64::from(low_impact.max_ops_per_sec)
+                {
+                    label.push_str(&format!(" (backed off to {rate:.0} ops/s)"));
+                }
+                ui.colored_label(egui::Color32::LIGHT_GREEN, label)
+                    .on_hover_text(format!(
+                        "About {} metadata operations per second at most, slowing down further \
+                         when the disk responds slowly; the scanner runs at lowered CPU and I/O \
+                         priority. The cap is best effort: directory entries are paced in \
+                         batches of up to 64, and reading a file to sniff its type or list an \
+                         archive counts as one operation",
+                        low_impact.max_ops_per_sec
+                    ));
+            }
+
+            if self.scan_running
+                && let Some(job_id) = self.active_job_id
+            {
+                ui.add_space(12.0);
+                if self.scan_paused {
+                    if ui
+                        .button("Resume")
+                        .on_hover_text("Continue the scan where it stopped")
+                        .clicked()
+                    {
+                        self.scanner.resume(job_id);
+                    }
+                } else if ui
+                    .button("Pause")
+                    .on_hover_text("Stop reading the disk without losing progress")
+                    .clicked()
+                {
+                    self.scanner.pause(job_id);
+                }
+                if ui
+                    .button("Stop")
+                    .on_hover_text("Cancel the scan and keep what it found so far")
+                    .clicked()
+                {
+                    self.scanner.cancel(job_id);
+                }
+            }
+
+            if let Some(progress) = &self.progress {
+                ui.add_space(12.0);
+                render_progress(ui, progress);
+            }
+
             if let Some(error) = &self.last_error {
                 ui.add_space(12.0);
                 ui.colored_label(egui::Color32::from_rgb(200, 64, 64), error);
             }
 
+            if !self.scan_errors.is_empty() {
+                ui.add_space(8.0);
+                let label = format!("Errors ({})", self.scan_errors.len());
+                if ui
+                    .button(label)
+                    .on_hover_text("List every path this scan could not read")
+                    .clicked()
+                {
+                    self.show_errors_panel = true;
+                }
+            }
+
             if !self.staged.is_empty() {
                 ui.add_space(16.0);
                 let label = format!("Commit staged ({})", self.staged.len());
                 if ui.button(label).clicked() {
                     self.show_commit_modal = true;
                 }
+                if self.staged.len() > 1
+                    && ui
+                        .add_enabled(!self.linking, egui::Button::new("Link copies"))
+                        .on_hover_text(
+                            "Keep every staged path but make identical files share one copy",
+                        )
+                        .clicked()
+                {
+                    self.show_link_modal = true;
+                }
             }
 
             if let Some(stats) = &self.last_stats {
@@ -710,6 +1142,18 @@ impl DiskSpaceApp {
                         stats.fs_errors, stats.cache_validation_errors
                     ));
                 }
+                if stats.hardlink_duplicates > 0 {
+                    label.push_str(&format!(
+                        "; {} extra hard links counted once",
+                        stats.hardlink_duplicates
+                    ));
+                }
+                if stats.excluded_dirs > 0 {
+                    label.push_str(&format!("; {} dirs excluded", stats.excluded_dirs));
+                }
+                if stats.dirs_estimated > 0 {
+                    label.push_str(&format!("; {} dirs estimated", stats.dirs_estimated));
+                }
                 ui.label(label);
             }
         });
@@ -767,6 +1211,288 @@ impl DiskSpaceApp {
         self.show_commit_modal = open && self.show_commit_modal;
     }
 
+    fn render_errors_panel(&mut self, ctx: &egui::Context) {
+        if !self.show_errors_panel {
+            return;
+        }
+
+        let mut open = true;
+        egui::Window::new("Scan Errors")
+            .collapsible(false)
+            .default_width(640.0)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    let all_label = format!("All ({})", self.scan_errors.len());
+                    if ui
+                        .selectable_label(self.error_filter.is_none(), all_label)
+                        .clicked()
+                    {
+                        self.error_filter = None;
+                    }
+                    for kind in ScanErrorKind::ALL {
+                        let count = self
+                            .scan_errors
+                            .iter()
+                            .filter(|error| error.kind == kind)
+                            .count();
+                        if count == 0 {
+                            continue;
+                        }
+                        let label = format!("{} ({count})", kind.label());
+                        if ui
+                            .selectable_label(self.error_filter == Some(kind), label)
+                            .clicked()
+                        {
+                            self.error_filter = Some(kind);
+                        }
+                    }
+                });
+
+                ui.add_space(8.0);
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(360.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        egui::Grid::new("scan-errors")
+                            .num_columns(3)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                let visible = self.scan_errors.iter().filter(|error| {
+                                    self.error_filter.is_none_or(|kind| error.kind == kind)
+                                });
+                                for error in visible {
+                                    ui.label(error.kind.label());
+                                    ui.label(error.path.display().to_string());
+                                    ui.label(egui::RichText::new(&error.message).weak());
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+
+        self.show_errors_panel = open;
+    }
+
+    fn render_duplicates(&mut self, ctx: &egui::Context) {
+        if !self.show_duplicates {
+            return;
+        }
+
+        let mut open = true;
+        egui::Window::new("Duplicates")
+            .collapsible(false)
+            .default_width(640.0)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if self.duplicates_searching {
+                        ui.spinner();
+                        ui.label("Hashing candidates…");
+                    } else {
+                        if ui
+                            .button("Search again")
+                            .on_hover_text("Hash files changed since the last search")
+                            .clicked()
+                        {
+                            self.find_duplicates();
+                        }
+                        if self.duplicate_stats.is_some() {
+                            let reclaimable: u64 = self
+                                .duplicates
+                                .iter()
+                                .map(DuplicateGroup::reclaimable)
+                                .sum();
+                            ui.label(format!(
+                                "{} groups; {} reclaimable",
+                                self.duplicates.len(),
+                                format_size(reclaimable)
+                            ));
+                        }
+                    }
+                });
+                ui.label(
+                    egui::RichText::new(
+                        "Stage the copies to delete; the last copy of a group cannot be staged.",
+                    )
+                    .weak(),
+                );
+
+                ui.add_space(8.0);
+                ui.separator();
+                let mut toggled: Vec<(PathBuf, bool)> = Vec::new();
+                egui::ScrollArea::vertical()
+                    .max_height(420.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        for group in &self.duplicates {
+                            let header = format!(
+                                "{} copies of {} — {} reclaimable",
+                                group.paths.len(),
+                                format_size(group.size),
+                                format_size(group.reclaimable())
+                            );
+                            ui.label(egui::RichText::new(header).strong());
+                            let kept = group
+                                .paths
+                                .iter()
+                                .filter(|path| !is_staged(&self.staged, path))
+                                .count();
+                            for path in &group.paths {
+                                let mut staged = self.staged.contains(path);
+                                let last_copy = kept == 1 && !is_staged(&self.staged, path);
+                                let checkbox =
+                                    egui::Checkbox::new(&mut staged, path.display().to_string());
+                                if ui
+                                    .add_enabled(!last_copy, checkbox)
+                                    .on_disabled_hover_text("The last copy of a group is kept")
+                                    .changed()
+                                {
+                                    toggled.push((path.clone(), staged));
+                                }
+                            }
+                            ui.add_space(6.0);
+                        }
+                    });
+                for (path, staged) in toggled {
+                    if staged {
+                        self.staged.insert(path);
+                    } else {
+                        self.staged.remove(&path);
+                    }
+                    self.schedule_ui_state_save();
+                }
+            });
+
+        self.show_duplicates = open;
+    }
+
+    fn render_breakdown(&mut self, ctx: &egui::Context) {
+        if !self.show_breakdown {
+            return;
+        }
+        let Some(dir) = self
+            .selected_dir
+            .clone()
+            .filter(|dir| self.tree.get(dir).is_some())
+            .or_else(|| self.active_root.clone())
+        else {
+            return;
+        };
+
+        let totals =
+            self.tree
+                .category_totals_with_cache(&dir, self.size_metric, &mut BTreeMap::new());
+        let total = totals.total();
+        let mut rows: Vec<(&str, u64)> = FileCategory::ALL
+            .iter()
+            .map(|category| (category.label(), totals.get(*category)))
+            .filter(|(_, bytes)| *bytes > 0)
+            .collect();
+        rows.sort_by_key(|(_, bytes)| std::cmp::Reverse(*bytes));
+        if totals.unclassified > 0 {
+            rows.push(("Not scanned", totals.unclassified));
+        }
+
+        let usage = self.tree.owner_usage(&dir, self.size_metric);
+        let owned_total = usage.users.values().sum::<u64>() + usage.unknown;
+        let mut user_rows: Vec<(String, u64)> = usage
+            .top_users()
+            .into_iter()
+            .map(|(uid, bytes)| (self.accounts.user(uid).to_string(), bytes))
+            .collect();
+        let mut group_rows: Vec<(String, u64)> = usage
+            .top_groups()
+            .into_iter()
+            .map(|(gid, bytes)| (self.accounts.group(gid).to_string(), bytes))
+            .collect();
+        if usage.unknown > 0 {
+            user_rows.push(("Unknown".to_string(), usage.unknown));
+            group_rows.push(("Unknown".to_string(), usage.unknown));
+        }
+
+        egui::SidePanel::right("breakdown")
+            .default_width(280.0)
+            .show(ctx, |ui| {
+                ui.heading("Breakdown");
+                let name = self
+                    .tree
+                    .get(&dir)
+                    .map_or_else(|| dir.display().to_string(), |node| node.name.clone());
+                ui.label(egui::RichText::new(name).strong())
+                    .on_hover_text(dir.display().to_string());
+                ui.label(format!("{} in files", format_size(total)));
+                ui.label(
+                    egui::RichText::new("Click a folder name to inspect it.")
+                        .weak()
+                        .small(),
+                );
+                ui.separator();
+
+                if rows.is_empty() {
+                    ui.weak("No files yet.");
+                    return;
+                }
+                usage_grid(ui, "breakdown-grid", &rows, total);
+
+                // Owners are counted over everything, folders included, so the shares add up to
+                // the folder's total rather than to the bytes in files.
+                ui.add_space(12.0);
+                ui.strong("By user");
+                usage_grid(ui, "breakdown-users", &user_rows, owned_total);
+                ui.add_space(12.0);
+                ui.strong("By group");
+                usage_grid(ui, "breakdown-groups", &group_rows, owned_total);
+            });
+    }
+
+    fn render_link_modal(&mut self, ctx: &egui::Context) {
+        if !self.show_link_modal {
+            return;
+        }
+
+        let staged_paths: Vec<PathBuf> = self.staged.iter().cloned().collect();
+        let mut open = self.show_link_modal;
+
+        egui::Window::new("Link Identical Copies")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Staged files with identical contents will share one copy. Each is re-hashed \
+                     first; reflinks are used where the filesystem supports them, hard links \
+                     otherwise.",
+                );
+                ui.add_space(8.0);
+                egui::ScrollArea::vertical()
+                    .max_height(220.0)
+                    .show(ui, |scroll| {
+                        for path in &staged_paths {
+                            scroll.label(path.display().to_string());
+                        }
+                    });
+
+                ui.add_space(12.0);
+                ui.separator();
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        self.show_link_modal = false;
+                    }
+
+                    ui.add_space(12.0);
+                    if ui.button("Link copies").clicked() {
+                        self.execute_link(&staged_paths);
+                        self.show_link_modal = false;
+                    }
+                });
+            });
+
+        self.show_link_modal = open && self.show_link_modal;
+    }
+
     fn render_layout_modal(&mut self, ctx: &egui::Context) {
         if !self.show_layout_modal {
             return;
@@ -804,18 +1530,18 @@ impl DiskSpaceApp {
                 ui.label("Sort order:");
                 ui.horizontal(|ui| {
                     let name_selected = self.sort_mode == SortMode::NameAsc;
-                    if ui.selectable_label(name_selected, "Name (A→Z)").clicked() {
-                        if self.sort_mode != SortMode::NameAsc {
-                            self.sort_mode = SortMode::NameAsc;
-                            self.schedule_ui_state_save();
-                        }
+                    if ui.selectable_label(name_selected, "Name (A→Z)").clicked()
+                        && self.sort_mode != SortMode::NameAsc
+                    {
+                        self.sort_mode = SortMode::NameAsc;
+                        self.schedule_ui_state_save();
                     }
                     let size_selected = self.sort_mode == SortMode::SizeDesc;
-                    if ui.selectable_label(size_selected, "Size (desc)").clicked() {
-                        if self.sort_mode != SortMode::SizeDesc {
-                            self.sort_mode = SortMode::SizeDesc;
-                            self.schedule_ui_state_save();
-                        }
+                    if ui.selectable_label(size_selected, "Size (desc)").clicked()
+                        && self.sort_mode != SortMode::SizeDesc
+                    {
+                        self.sort_mode = SortMode::SizeDesc;
+                        self.schedule_ui_state_save();
                     }
                 });
 
@@ -845,30 +1571,50 @@ impl DiskSpaceApp {
         let mut deleted = Vec::new();
         let mut errors = Vec::new();
 
+        // Paths staged elsewhere, such as folders staged in the tree, may still cover every copy
+        // of a duplicate group; the one covering its first copy is left in place.
+        let mut kept = BTreeSet::new();
+        for group in &self.duplicates {
+            let covering: Vec<&PathBuf> = group
+                .paths
+                .iter()
+                .filter_map(|path| {
+                    path.ancestors()
+                        .find_map(|ancestor| staged_paths.iter().find(|staged| *staged == ancestor))
+                })
+                .collect();
+            if covering.len() == group.paths.len() {
+                kept.insert(covering[0].clone());
+            }
+        }
+
         for path in staged_paths {
-            if !path.starts_with(&self.current_query.root) {
-                errors.push((path.clone(), "outside current root".to_string()));
+            if kept.contains(path) {
+                errors.push((
+                    path.clone(),
+                    "would delete every copy of a duplicate group".to_string(),
+                ));
                 continue;
             }
-
-            let result = match fs::metadata(path) {
-                Ok(metadata) => {
-                    if metadata.is_dir() {
-                        fs::remove_dir_all(path)
-                    } else {
-                        fs::remove_file(path)
-                    }
-                }
-                Err(err) => Err(err),
+            let Some((_, cache_ctx)) = self.scan_root_for(path) else {
+                errors.push((path.clone(), "outside current root".to_string()));
+                continue;
             };
+            if self.tree.get(path).is_some_and(|node| node.in_archive) {
+                errors.push((path.clone(), "inside an archive".to_string()));
+                continue;
+            }
 
-            match result {
+            // Never follow a staged symlink: removing it must not touch its target.
+            match self.filesystem.remove(path) {
                 Ok(()) => {
                     deleted.push(path.clone());
-                    if let Ok(relative) = path.strip_prefix(&self.canonical_root) {
-                        let _ = self.cache.remove_entry(self.cache_root_id, relative);
+                    if let Some(ctx) = cache_ctx
+                        && let Ok(relative) = path.strip_prefix(&ctx.canonical_root)
+                    {
+                        let _ = ctx.cache.remove_entry(ctx.root_id, relative);
                         if let Some(parent) = self.parent_relative(relative) {
-                            let _ = self.cache.mark_ancestors_dirty(self.cache_root_id, &parent);
+                            let _ = ctx.cache.mark_ancestors_dirty(ctx.root_id, &parent);
                         }
                     }
                 }
@@ -881,13 +1627,27 @@ impl DiskSpaceApp {
         for path in &deleted {
             self.staged.remove(path);
         }
+        if !deleted.is_empty() && !self.duplicates.is_empty() {
+            for group in &mut self.duplicates {
+                group
+                    .paths
+                    .retain(|path| !deleted.iter().any(|gone| path.starts_with(gone)));
+            }
+            self.duplicates.retain(|group| group.paths.len() > 1);
+        }
         if !deleted.is_empty() {
             self.schedule_ui_state_save();
         }
 
         if !deleted.is_empty() {
             let count = deleted.len();
-            self.trigger_scan();
+            let parents = deleted
+                .iter()
+                .filter_map(|path| path.parent().map(Path::to_path_buf))
+                .collect();
+            for parent in outermost_dirs(parents) {
+                self.rescan_subtree(parent);
+            }
             self.status_text = Some(format!("Deleted {count} item(s); rescanning…"));
         }
 
@@ -900,77 +1660,249 @@ impl DiskSpaceApp {
         }
     }
 
+    /// Asks the scanner thread to replace identical copies among `staged_paths` with links;
+    /// `apply_link_report` handles the outcome.
+    fn execute_link(&mut self, staged_paths: &[PathBuf]) {
+        let Some(job_id) = self.tree_job_id else {
+            return;
+        };
+        let inside: Vec<PathBuf> = staged_paths
+            .iter()
+            .filter(|path| self.scan_root_for(path).is_some())
+            .cloned()
+            .collect();
+        self.linking = true;
+        self.status_text = Some(format!("Linking {} staged files…", inside.len()));
+        self.scanner
+            .request_link(job_id, inside, self.cache.undo_dir());
+    }
+
+    /// Unstages the linked files and rescans the folders of every file involved so the cache
+    /// picks up the new link counts.
+    fn apply_link_report(&mut self, report: LinkReport) {
+        let mut parents = BTreeSet::new();
+        for linked in &report.linked {
+            self.staged.remove(&linked.path);
+            self.staged.remove(&linked.source);
+            for path in [&linked.path, &linked.source] {
+                if let Some((_, Some(ctx))) = self.scan_root_for(path)
+                    && let Some(parent) = path.parent()
+                {
+                    let relative = relative_to(&ctx.canonical_root, parent);
+                    let _ = ctx.cache.mark_ancestors_dirty(ctx.root_id, &relative);
+                }
+                if let Some(parent) = path.parent() {
+                    parents.insert(parent.to_path_buf());
+                }
+            }
+        }
+        if !report.linked.is_empty() {
+            self.schedule_ui_state_save();
+            for parent in outermost_dirs(parents) {
+                self.rescan_subtree(parent);
+            }
+        }
+
+        let reflinks = report
+            .linked
+            .iter()
+            .filter(|linked| linked.method == LinkMethod::Reflink)
+            .count();
+        let mut status = format!(
+            "Linked {} copies ({} reflinks, {} hard links); saved {}",
+            report.linked.len(),
+            reflinks,
+            report.linked.len() - reflinks,
+            format_size(report.bytes_saved)
+        );
+        if let Some(record) = &report.undo_record {
+            status.push_str(&format!("; undo record: {}", record.display()));
+        }
+        self.status_text = Some(status);
+
+        if !report.skipped.is_empty() {
+            let mut message = String::from("Not linked:\n");
+            for (path, reason) in &report.skipped {
+                message.push_str(&format!("{}: {reason}\n", path.display()));
+            }
+            self.last_error = Some(message.trim_end().to_string());
+        }
+    }
+
+    fn find_duplicates(&mut self) {
+        let Some(job_id) = self.tree_job_id else {
+            self.status_text = Some("Duplicate search waits for the first scan".to_string());
+            return;
+        };
+        let roots: Vec<CacheContext> = if self.is_multi_root() {
+            self.root_scans(&self.current_query)
+                .into_iter()
+                .filter_map(|scan| scan.cache)
+                .collect()
+        } else if self.current_query.root == self.canonical_root {
+            vec![self.cache_context()]
+        } else {
+            Vec::new()
+        };
+        if roots.is_empty() {
+            self.last_error = Some("Duplicate search needs a cached scan of the root".to_string());
+            return;
+        }
+
+        self.duplicates_searching = true;
+        self.scanner
+            .request_duplicates(job_id, roots, DUPLICATE_MIN_SIZE);
+    }
+
     fn trigger_scan(&mut self) {
-        let query = parse_input(&self.search_input);
+        let mut query = parse_input(&self.search_input);
+        query.max_depth = self.max_depth;
         self.current_query = query.clone();
         self.entries_seen = 0;
         self.tree.clear();
         self.expanded.clear();
+        self.loading_subtrees.clear();
+        self.refreshing.clear();
         self.last_stats = None;
+        self.progress = None;
         self.watch_rescan_due = false;
+        self.watch_rescan_dirs.clear();
         self.schedule_ui_state_save();
-        let cache_ctx = if query.root == self.canonical_root {
-            Some(self.cache_context())
+        let job_id = if self.is_multi_root() {
+            let scans = self.root_scans(&query);
+            self.scanner.request_scan_roots(scans)
         } else {
-            None
+            let cache_ctx = if query.root == self.canonical_root {
+                Some(self.cache_context())
+            } else {
+                None
+            };
+            self.scanner.request_scan(query.clone(), cache_ctx)
         };
-        let job_id = self.scanner.request_scan(query.clone(), cache_ctx);
         self.pending_job_id = Some(job_id);
-        self.status_text = Some(format!("Scanning {}…", query.root.display()));
+        let label = if self.is_multi_root() {
+            self.describe_root(Path::new(ALL_ROOTS))
+        } else {
+            query.root.display().to_string()
+        };
+        self.status_text = Some(format!("Scanning {label}…"));
         self.active_root = None;
         self.last_error = None;
     }
 
+    /// Rescans the directory at `path`, or its closest ancestor that still exists, splicing the
+    /// results into the tree and cache. Falls back to a full scan when no scan has run yet.
+    fn rescan_subtree(&mut self, path: PathBuf) {
+        let Some(job_id) = self.tree_job_id else {
+            self.trigger_scan();
+            return;
+        };
+        let Some((root, cache_ctx)) = self.scan_root_for(&path) else {
+            return;
+        };
+        let mut target = path;
+        while !self
+            .filesystem
+            .metadata(&target)
+            .is_ok_and(|metadata| metadata.is_dir())
+            && target != root
+        {
+            target = match target.parent() {
+                Some(parent) => parent.to_path_buf(),
+                None => root.clone(),
+            };
+        }
+        if !self.loading_subtrees.insert(target.clone()) {
+            return;
+        }
+
+        let query = SearchQuery {
+            root,
+            ..self.current_query.clone()
+        };
+        self.scanner
+            .request_subtree_rescan(job_id, query, target.clone(), cache_ctx);
+        if !self.scan_running {
+            self.status_text = Some(format!("Scanning {}…", target.display()));
+        }
+    }
+
+    /// "Rescan this folder": walks everything below `path` again, ignoring cached directories.
+    fn rescan_folder(&mut self, path: PathBuf) {
+        if self.is_multi_root() && path == Path::new(ALL_ROOTS) {
+            self.trigger_scan();
+            return;
+        }
+        if let Some((_, Some(ctx))) = self.scan_root_for(&path) {
+            let relative = relative_to(&ctx.canonical_root, &path);
+            let _ = ctx.cache.mark_subtree_dirty(ctx.root_id, &relative);
+        }
+        self.rescan_subtree(path);
+    }
+
     fn drain_messages(&mut self, ctx: &egui::Context) {
         let mut updated = false;
         while let Ok(message) = self.scan_rx.try_recv() {
             updated = true;
             match message {
                 ScanMessage::Begin { job_id, root } => {
-                    let is_newer = self
-                        .active_job_id
-                        .map(|active| job_id >= active)
-                        .unwrap_or(true);
-
-                    if !is_newer {
-                        continue;
+                    self.begin_job(job_id, root);
+                }
+                ScanMessage::BeginRoots { job_id, roots } => {
+                    let group = PathBuf::from(ALL_ROOTS);
+                    if self.begin_job(job_id, group.clone()) {
+                        self.tree.insert_group(&group, "All roots", &roots);
+                        self.expanded.extend(roots);
                     }
-
-                    if self.pending_job_id == Some(job_id) {
-                        self.pending_job_id = None;
+                }
+                ScanMessage::Entries { job_id, entries } => {
+                    if Some(job_id) == self.tree_job_id {
+                        for mut entry in entries {
+                            // Roots under the group node are labelled with their full path.
+                            if self.scan_roots.iter().any(|root| root.path == entry.path) {
+                                entry.file_name = entry.path.display().to_string();
+                            }
+                            if entry.file_name.is_empty() {
+                                entry.file_name = entry
+                                    .path
+                                    .file_name()
+                                    .and_then(|f| f.to_str())
+                                    .map(|s| s.to_string())
+                                    .unwrap_or_else(|| entry.path.display().to_string());
+                            }
+                            self.tree.upsert(entry);
+                            self.entries_seen += 1;
+                        }
+                    }
+                }
+                ScanMessage::Error { job_id, error } => {
+                    if Some(job_id) == self.tree_job_id {
+                        self.last_error =
+                            Some(format!("{}: {}", error.path.display(), error.message));
+                        self.scan_errors.push(error);
+                    }
+                }
+                ScanMessage::Progress { job_id, progress } => {
+                    if Some(job_id) == self.active_job_id {
+                        self.progress = Some(progress);
                     }
-
-                    self.active_job_id = Some(job_id);
-                    self.active_root = Some(root.clone());
-                    self.entries_seen = 0;
-                    self.tree.clear();
-                    self.expanded.clear();
-                    self.expanded.insert(root.clone());
-                    self.last_error = None;
-                    self.status_text = Some(format!("Scanning {}…", root.display()));
-                    self.last_stats = None;
                 }
-                ScanMessage::Entry { job_id, mut entry } => {
+                ScanMessage::Paused { job_id } => {
                     if Some(job_id) == self.active_job_id {
-                        if entry.file_name.is_empty() {
-                            entry.file_name = entry
-                                .path
-                                .file_name()
-                                .and_then(|f| f.to_str())
-                                .map(|s| s.to_string())
-                                .unwrap_or_else(|| entry.path.display().to_string());
+                        self.scan_paused = true;
+                        if let Some(root) = &self.active_root {
+                            self.status_text =
+                                Some(format!("Paused scan of {}", self.describe_root(root)));
                         }
-                        self.tree.upsert(entry);
-                        self.entries_seen += 1;
                     }
                 }
-                ScanMessage::Error {
-                    job_id,
-                    path,
-                    message,
-                } => {
+                ScanMessage::Resumed { job_id } => {
                     if Some(job_id) == self.active_job_id {
-                        self.last_error = Some(format!("{}: {message}", path.display()));
+                        self.scan_paused = false;
+                        if let Some(root) = &self.active_root {
+                            self.status_text =
+                                Some(format!("Scanning {}…", self.describe_root(root)));
+                        }
                     }
                 }
                 ScanMessage::Stats { job_id, stats } => {
@@ -989,9 +1921,10 @@ impl DiskSpaceApp {
                             Ok(root_cache) => {
                                 self.cache_root_id = root_cache.root_id;
                                 self.tree.clear();
+                                self.scan_errors.clear();
                                 self.expanded.clear();
                                 self.expanded.insert(root.clone());
-                                self.populate_tree_from_cache(root_cache);
+                                self.populate_tree_from_cache(&root, root_cache);
                                 let status = if cleared {
                                     "Cache cleared; rescanning…"
                                 } else {
@@ -1010,19 +1943,22 @@ impl DiskSpaceApp {
                 }
                 ScanMessage::Complete { job_id } => {
                     if Some(job_id) == self.active_job_id {
+                        self.progress = None;
+                        self.scan_running = false;
+                        self.scan_paused = false;
                         if self.pending_job_id.is_none() {
                             if let Some(root) = self.active_root.as_ref() {
                                 let status = if let Some(stats) = self.last_stats {
                                     format!(
                                         "Scan complete for {} ({} entries; reused {} cached dirs)",
-                                        root.display(),
+                                        self.describe_root(root),
                                         self.entries_seen,
                                         stats.cached_dirs
                                     )
                                 } else {
                                     format!(
                                         "Scan complete for {} ({} entries)",
-                                        root.display(),
+                                        self.describe_root(root),
                                         self.entries_seen
                                     )
                                 };
@@ -1040,6 +1976,79 @@ impl DiskSpaceApp {
                         self.pending_job_id = None;
                     }
                 }
+                ScanMessage::Cancelled { job_id } => {
+                    if Some(job_id) == self.tree_job_id {
+                        // Queued subtree rescans and duplicate searches were dropped with the job.
+                        self.loading_subtrees.clear();
+                        self.refreshing.clear();
+                        self.duplicates_searching = false;
+                        self.status_text = Some(format!(
+                            "Scan stopped ({} entries found so far)",
+                            self.entries_seen
+                        ));
+                    }
+                }
+                ScanMessage::Duplicates {
+                    job_id,
+                    groups,
+                    stats,
+                } => {
+                    if Some(job_id) == self.tree_job_id {
+                        let reclaimable: u64 = groups.iter().map(DuplicateGroup::reclaimable).sum();
+                        self.status_text = Some(format!(
+                            "Found {} duplicate groups; {} reclaimable ({} hashes computed, {} reused)",
+                            groups.len(),
+                            format_size(reclaimable),
+                            stats.head_hashes + stats.full_hashes,
+                            stats.hashes_reused
+                        ));
+                        self.duplicates = groups;
+                        self.duplicate_stats = Some(stats);
+                        self.duplicates_searching = false;
+                    }
+                }
+                ScanMessage::Linked { result, .. } => {
+                    // Applied whatever the current job: the files changed on disk either way.
+                    self.linking = false;
+                    match result {
+                        Ok(report) => self.apply_link_report(report),
+                        Err(err) => self.last_error = Some(format!("Linking failed: {err}")),
+                    }
+                }
+                ScanMessage::SubtreeBegin { job_id, root } => {
+                    if Some(job_id) == self.tree_job_id {
+                        // The rescan reports the subtree's errors afresh.
+                        self.scan_errors
+                            .retain(|error| !error.path.starts_with(&root));
+                        self.refreshing.insert(root, self.tree.begin_refresh());
+                    }
+                }
+                ScanMessage::SubtreeComplete {
+                    job_id,
+                    root,
+                    stats,
+                } => {
+                    if Some(job_id) == self.tree_job_id {
+                        self.loading_subtrees.remove(&root);
+                        if let Some(generation) = self.refreshing.remove(&root) {
+                            let removed = self.tree.prune_stale(&root, generation);
+                            for path in &removed {
+                                self.staged.remove(path);
+                                self.expanded.remove(path);
+                            }
+                            if !removed.is_empty() {
+                                self.schedule_ui_state_save();
+                            }
+                        }
+                        if !self.scan_running {
+                            self.status_text = Some(format!(
+                                "Scanned {} (reused {} cached dirs)",
+                                root.display(),
+                                stats.cached_dirs
+                            ));
+                        }
+                    }
+                }
             }
         }
 
@@ -1048,7 +2057,53 @@ impl DiskSpaceApp {
         }
     }
 
-    fn populate_tree_from_cache(&mut self, mut root_cache: RootCache) {
+    /// Starts showing the results of job `job_id` under `root`, unless a newer job already runs.
+    fn begin_job(&mut self, job_id: u64, root: PathBuf) -> bool {
+        let is_newer = self
+            .active_job_id
+            .map(|active| job_id >= active)
+            .unwrap_or(true);
+
+        if !is_newer {
+            return false;
+        }
+
+        if self.pending_job_id == Some(job_id) {
+            self.pending_job_id = None;
+        }
+
+        self.active_job_id = Some(job_id);
+        self.tree_job_id = Some(job_id);
+        self.active_root = Some(root.clone());
+        self.entries_seen = 0;
+        self.tree.clear();
+        self.expanded.clear();
+        self.loading_subtrees.clear();
+        self.refreshing.clear();
+        self.scan_errors.clear();
+        self.duplicates.clear();
+        self.duplicate_stats = None;
+        self.duplicates_searching = false;
+        self.expanded.insert(root.clone());
+        self.last_error = None;
+        self.status_text = Some(format!("Scanning {}…", self.describe_root(&root)));
+        self.last_stats = None;
+        self.progress = None;
+        self.scan_running = true;
+        self.scan_paused = false;
+        true
+    }
+
+    fn populate_tree_from_cache(&mut self, root: &Path, mut root_cache: RootCache) {
+        self.scan_errors
+            .extend(root_cache.errors.drain(..).map(|error| ScanError {
+                path: if error.path == Path::new(".") {
+                    root.to_path_buf()
+                } else {
+                    root.join(&error.path)
+                },
+                ..error
+            }));
         if root_cache.entries.is_empty() {
             return;
         }
@@ -1058,45 +2113,215 @@ impl DiskSpaceApp {
             .sort_by_key(|entry| entry.path.components().count());
 
         for entry in root_cache.entries {
-            let absolute = if entry.path.as_os_str().is_empty() || entry.path == PathBuf::from(".")
-            {
-                self.canonical_root.clone()
+            let absolute = if entry.path.as_os_str().is_empty() || entry.path == Path::new(".") {
+                root.to_path_buf()
             } else {
-                self.canonical_root.join(&entry.path)
+                root.join(&entry.path)
             };
 
-            let file_name = absolute
-                .file_name()
-                .and_then(|f| f.to_str())
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| absolute.display().to_string());
+            let file_name = if self.is_multi_root() && absolute == root {
+                absolute.display().to_string()
+            } else {
+                absolute
+                    .file_name()
+                    .and_then(|f| f.to_str())
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| absolute.display().to_string())
+            };
 
-            let file_entry = FileEntry::new(
+            let mut file_entry = FileEntry::new(
                 absolute,
                 file_name,
                 entry.kind,
                 entry.direct_size,
+                entry.allocated_size,
                 cache::timestamp_to_system(entry.modified),
                 cache::timestamp_to_system(entry.created),
             );
+            file_entry.hardlink_duplicate = entry.flags & cache::FLAG_HARDLINK_DUPLICATE != 0;
+            file_entry.mount_point = entry.flags & cache::FLAG_MOUNT_POINT != 0;
+            file_entry.dangling_link = entry.flags & cache::FLAG_DANGLING_LINK != 0;
+            file_entry.category = entry.file_category();
+            file_entry.allocation = entry.allocation();
+            file_entry.owner = entry.owner;
+            file_entry.link_target = entry.link_target;
+            file_entry.excluded = entry.flags & cache::FLAG_EXCLUDED != 0;
 
             self.tree.upsert(file_entry);
         }
     }
 }
 
+/// `path` relative to `root`, with `.` for the root itself, as the cache stores it.
+fn relative_to(root: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(root) {
+        Ok(rel) if !rel.as_os_str().is_empty() => rel.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Drops directories that lie below another one in `dirs`; rescanning the outer one covers them.
+fn outermost_dirs(dirs: BTreeSet<PathBuf>) -> Vec<PathBuf> {
+    let mut outermost: Vec<PathBuf> = Vec::new();
+    // Ancestors sort before their descendants, so only the last kept directory can contain `dir`.
+    for dir in dirs {
+        if !outermost.last().is_some_and(|last| dir.starts_with(last)) {
+            outermost.push(dir);
+        }
+    }
+    outermost
+}
+
+fn render_badges(ui: &mut egui::Ui, node: &TreeNode) {
+    if node.unscanned {
+        ui.weak("not scanned")
+            .on_hover_text("Below the --max-depth limit; expand to scan it");
+    }
+    if node.hardlink_duplicate {
+        ui.weak("hard link").on_hover_text(
+            "Another link to this file was already counted; it does not add to the totals",
+        );
+    }
+    if node.mount_point {
+        ui.weak("mount point")
+            .on_hover_text("On another filesystem; skipped by --one-file-system");
+    }
+    if node.excluded {
+        ui.weak("excluded")
+            .on_hover_text("Matched by a .duskignore or .gitignore rule; not scanned");
+    }
+    if node.in_archive {
+        let hover = match node.compressed_size {
+            Some(compressed) => format!(
+                "Inside an archive, taking up {} of it; it cannot be deleted on its own",
+                format_size(compressed)
+            ),
+            None => "Inside a compressed archive; it cannot be deleted on its own".to_string(),
+        };
+        ui.colored_label(egui::Color32::LIGHT_BLUE, "in archive")
+            .on_hover_text(hover);
+    }
+    match node.allocation {
+        Some(Allocation::Sparse) => {
+            ui.colored_label(egui::Color32::LIGHT_GREEN, "sparse")
+                .on_hover_text(format!(
+                    "Only {} of its {} length is allocated on disk",
+                    format_size(node.allocated_size),
+                    format_size(node.direct_size)
+                ));
+        }
+        Some(Allocation::Preallocated) => {
+            ui.colored_label(egui::Color32::LIGHT_YELLOW, "preallocated")
+                .on_hover_text(format!(
+                    "{} is reserved on disk for {} of content",
+                    format_size(node.allocated_size),
+                    format_size(node.direct_size)
+                ));
+        }
+        None => {}
+    }
+    if let Some(target) = &node.link_target {
+        ui.weak(format!("→ {}", target.display()));
+    }
+    if node.dangling_link {
+        ui.colored_label(egui::Color32::LIGHT_RED, "dangling")
+            .on_hover_text("The link target does not exist");
+    }
+}
+
+fn render_progress(ui: &mut egui::Ui, progress: &ScanProgress) {
+    let mut text = String::new();
+    if let Some(fraction) = progress.fraction {
+        text.push_str(&format!("{:.0}% · ", fraction * 100.0));
+    }
+    text.push_str(&format!(
+        "{} files, {}, {:.0} entries/s",
+        progress.files,
+        format_size(progress.bytes),
+        progress.entries_per_sec
+    ));
+    if let Some(eta) = progress.eta {
+        text.push_str(&format!(", ~{} left", format_eta(eta)));
+    }
+
+    // Without a previous total there is nothing to measure against; animate instead.
+    let bar = match progress.fraction {
+        Some(fraction) => egui::ProgressBar::new(fraction),
+        None => egui::ProgressBar::new(0.0).animate(true),
+    };
+    ui.add(bar.desired_width(320.0).text(text))
+        .on_hover_text(progress.current_dir.display().to_string());
+}
+
+fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    if secs >= 3600 {
+        format!("{}h {:02}m", secs / 3600, (secs % 3600) / 60)
+    } else if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// One row per label with its bytes and share of `total`.
+fn usage_grid<S: AsRef<str>>(ui: &mut egui::Ui, id: &str, rows: &[(S, u64)], total: u64) {
+    egui::Grid::new(id)
+        .num_columns(3)
+        .striped(true)
+        .show(ui, |ui| {
+            for (label, bytes) in rows {
+                let fraction = if total == 0 {
+                    0.0
+                } else {
+                    *bytes as f32 / total as f32
+                };
+                ui.label(label.as_ref());
+                ui.label(format_size(*bytes));
+                ui.add(
+                    egui::ProgressBar::new(fraction)
+                        .desired_width(90.0)
+                        .text(format!("{:.1}%", fraction * 100.0)),
+                );
+                ui.end_row();
+            }
+        });
+}
+
+/// Whether `path` or one of its ancestors is staged for deletion.
+fn is_staged(staged: &BTreeSet<PathBuf>, path: &Path) -> bool {
+    path.ancestors().any(|ancestor| staged.contains(ancestor))
+}
+
+fn kind_icon(node: &TreeNode) -> egui::RichText {
+    let icon = match node.kind {
+        FileKind::File => "",
+        FileKind::Directory if node.excluded => "⊘",
+        FileKind::Directory => "⛃",
+        FileKind::Symlink => "↪",
+        FileKind::Fifo => "⇶",
+        FileKind::Socket => "⚡",
+        FileKind::CharDevice => "⌨",
+        FileKind::BlockDevice => "🖴",
+    };
+    let text = egui::RichText::new(icon).weak();
+    if node.dangling_link {
+        text.color(egui::Color32::LIGHT_RED)
+    } else {
+        text
+    }
+}
+
 fn compare_paths(store: &TreeStore, lhs: &Path, rhs: &Path) -> std::cmp::Ordering {
     let lhs_node = store.get(lhs);
     let rhs_node = store.get(rhs);
 
     match (lhs_node, rhs_node) {
         (Some(a), Some(b)) => {
-            if a.kind != b.kind {
-                return match (a.kind, b.kind) {
-                    (FileKind::Directory, FileKind::File) => std::cmp::Ordering::Less,
-                    (FileKind::File, FileKind::Directory) => std::cmp::Ordering::Greater,
-                    _ => std::cmp::Ordering::Equal,
-                };
+            let a_dir = a.kind == FileKind::Directory;
+            let b_dir = b.kind == FileKind::Directory;
+            if a_dir != b_dir {
+                return b_dir.cmp(&a_dir);
             }
             a.name.to_lowercase().cmp(&b.name.to_lowercase())
         }
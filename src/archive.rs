@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+
+use crate::fs::FileKind;
+
+/// Archive formats whose members can be listed as virtual children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// Recognises an archive by its file name.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else {
+            None
+        }
+    }
+}
+
+/// One file or directory inside an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveMember {
+    /// Path inside the archive, relative and without `..` components.
+    pub path: PathBuf,
+    pub kind: FileKind,
+    /// Uncompressed length.
+    pub size: u64,
+    /// Bytes the member takes up inside the archive. Compressed tarballs compress the stream as a
+    /// whole, so their members have none.
+    pub compressed_size: Option<u64>,
+}
+
+/// Lists the members of the archive read from `file`, parents before children. Directories that
+/// are only implied by their members' paths are listed too.
+pub fn list<R: Read + Seek>(file: R, format: ArchiveFormat) -> io::Result<Vec<ArchiveMember>> {
+    let members = match format {
+        ArchiveFormat::Zip => list_zip(file)?,
+        ArchiveFormat::Tar => list_tar(BufReader::new(file), true)?,
+        ArchiveFormat::TarGz => {
+            list_tar(flate2::read::GzDecoder::new(BufReader::new(file)), false)?
+        }
+        ArchiveFormat::TarZst => list_tar(zstd::Decoder::new(file)?, false)?,
+    };
+    Ok(with_parents(members))
+}
+
+fn list_zip<R: Read + Seek>(file: R) -> io::Result<Vec<ArchiveMember>> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).map_err(io::Error::other)?;
+    let mut members = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        // Raw access reads the central directory record without decompressing anything.
+        let member = archive.by_index_raw(index).map_err(io::Error::other)?;
+        let Some(path) = member.enclosed_name().and_then(|path| sanitize(&path)) else {
+            continue;
+        };
+        let kind = if member.is_dir() {
+            FileKind::Directory
+        } else if member.is_symlink() {
+            FileKind::Symlink
+        } else {
+            FileKind::File
+        };
+        let stored = kind != FileKind::Directory;
+        members.push(ArchiveMember {
+            path,
+            kind,
+            size: if stored { member.size() } else { 0 },
+            compressed_size: stored.then(|| member.compressed_size()),
+        });
+    }
+    Ok(members)
+}
+
+/// Reads every header of a tar stream. Plain tarballs store members uncompressed, so their
+/// compressed size is their size.
+fn list_tar<R: Read>(reader: R, uncompressed: bool) -> io::Result<Vec<ArchiveMember>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut members = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        let Some(path) = entry.path().ok().and_then(|path| sanitize(&path)) else {
+            continue;
+        };
+        let entry_type = header.entry_type();
+        let kind = if entry_type.is_dir() {
+            FileKind::Directory
+        } else if entry_type.is_symlink() {
+            FileKind::Symlink
+        } else if entry_type.is_file() || entry_type.is_hard_link() {
+            FileKind::File
+        } else {
+            continue;
+        };
+        let size = if kind == FileKind::File {
+            entry.size()
+        } else {
+            0
+        };
+        members.push(ArchiveMember {
+            path,
+            kind,
+            size,
+            compressed_size: (uncompressed && kind == FileKind::File).then_some(size),
+        });
+    }
+    Ok(members)
+}
+
+/// Drops `.` components and rejects absolute paths and `..`, which would escape the archive.
+fn sanitize(path: &Path) -> Option<PathBuf> {
+    let mut clean = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!clean.as_os_str().is_empty()).then_some(clean)
+}
+
+/// Adds directories that only appear as prefixes of other members, keeps the last record of a
+/// path that is listed twice, and orders the result so parents precede their children.
+fn with_parents(members: Vec<ArchiveMember>) -> Vec<ArchiveMember> {
+    let mut by_path = BTreeMap::new();
+    for member in members {
+        for parent in member.path.ancestors().skip(1) {
+            if parent.as_os_str().is_empty() {
+                break;
+            }
+            by_path
+                .entry(parent.to_path_buf())
+                .or_insert_with(|| ArchiveMember {
+                    path: parent.to_path_buf(),
+                    kind: FileKind::Directory,
+                    size: 0,
+                    compressed_size: None,
+                });
+        }
+        by_path.insert(member.path.clone(), member);
+    }
+    by_path.into_values().collect()
+}
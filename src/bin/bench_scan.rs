@@ -2,8 +2,7 @@ use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 use disk_space_inspect::cache::Cache;
-use disk_space_inspect::query::SearchQuery;
-use disk_space_inspect::scanner::{self, ScanMessage, ScanStats};
+use disk_space_inspect::scanner::{self, ScanEvent, ScanOptions, ScanStats};
 use pico_args::Arguments;
 use serde::Serialize;
 
@@ -51,45 +50,19 @@ fn run() -> Result<(), String> {
     let cache = Cache::open().map_err(|err| err.to_string())?;
     let root_cache = cache.load_root(&canonical).map_err(|err| err.to_string())?;
 
-    let (scanner, rx) = scanner::spawn();
-
-    let mut query = SearchQuery::default();
-    query.root = canonical.clone();
-
-    let cache_ctx = scanner::CacheContext {
-        cache: cache.clone(),
-        root_id: root_cache.root_id,
-        canonical_root: canonical.clone(),
-    };
-
     let start = Instant::now();
-    let job_id = scanner.request_scan(query, Some(cache_ctx));
-
-    let mut stats: Option<ScanStats> = None;
+    let mut stats = ScanStats::default();
     let mut entries = 0usize;
 
-    while let Ok(message) = rx.recv() {
-        match message {
-            ScanMessage::Entry {
-                job_id: msg_job, ..
-            } if msg_job == job_id => {
-                entries += 1;
-            }
-            ScanMessage::Stats {
-                job_id: msg_job,
-                stats: s,
-            } if msg_job == job_id => {
-                stats = Some(s);
-            }
-            ScanMessage::Complete { job_id: msg_job } if msg_job == job_id => {
-                break;
-            }
+    for event in scanner::scan_stream(&canonical, ScanOptions::new().cache(cache.clone())) {
+        match event {
+            ScanEvent::Entries(batch) => entries += batch.len(),
+            ScanEvent::Finished(finished) => stats = finished,
             _ => {}
         }
     }
 
     let elapsed = start.elapsed();
-    let stats = stats.unwrap_or_default();
 
     println!(
         "Scan complete: {} entries, {} files, {} dirs (cached dirs: {}, cached entries: {}) in {:?}",
@@ -116,10 +89,10 @@ fn run() -> Result<(), String> {
             elapsed_ms: elapsed.as_millis(),
         };
         let json = serde_json::to_string_pretty(&snapshot).map_err(|err| err.to_string())?;
-        if let Some(parent) = path.parent() {
-            if !parent.as_os_str().is_empty() {
-                std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
-            }
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
         }
         std::fs::write(&path, json).map_err(|err| err.to_string())?;
         println!("Snapshot written to {}", path.display());
@@ -6,11 +6,132 @@ use chrono::Utc;
 use dirs::cache_dir;
 use rusqlite::{Connection, OptionalExtension, params};
 
-use crate::fs::FileKind;
+use crate::archive::ArchiveMember;
+use crate::fs::{Allocation, FileCategory, FileKind, Ownership, ScanError, ScanErrorKind};
 
 const CACHE_SCHEMA_VERSION: i64 = 1;
-const CACHE_USER_VERSION: i32 = 1;
-const CACHE_MIGRATIONS: &[(i32, &str)] = &[];
+const CACHE_USER_VERSION: i32 = 12;
+const CACHE_MIGRATIONS: &[(i32, &str)] = &[
+    (
+        2,
+        // Rows written before allocated sizes existed are marked dirty so the next scan refills them.
+        "ALTER TABLE entries ADD COLUMN allocated_size INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE entries ADD COLUMN aggregate_allocated INTEGER NOT NULL DEFAULT 0;
+         UPDATE entries SET flags = flags | 1;",
+    ),
+    (
+        3,
+        "ALTER TABLE entries ADD COLUMN device INTEGER;
+         ALTER TABLE entries ADD COLUMN inode INTEGER;
+         UPDATE entries SET flags = flags | 1;",
+    ),
+    (
+        4,
+        // Earlier scans dropped symlinks and special files, so cached directories are incomplete.
+        "ALTER TABLE entries ADD COLUMN link_target TEXT;
+         UPDATE entries SET flags = flags | 1;",
+    ),
+    // Rows without an ignore key never match the current rules, so no dirty marking is needed.
+    (5, "ALTER TABLE entries ADD COLUMN ignore_key INTEGER;"),
+    (
+        6,
+        "CREATE TABLE IF NOT EXISTS scan_errors (
+            root_id INTEGER NOT NULL,
+            path TEXT NOT NULL,
+            kind INTEGER NOT NULL,
+            message TEXT NOT NULL,
+            last_seen_utc INTEGER NOT NULL,
+            PRIMARY KEY(root_id, path, kind),
+            FOREIGN KEY(root_id) REFERENCES roots(id)
+        );",
+    ),
+    (
+        7,
+        // Content hashes are only trusted while the file's mtime and size match `hash_mtime` and
+        // `hash_size`, so rows rewritten by later scans keep them until the file changes.
+        "ALTER TABLE entries ADD COLUMN hash_mtime INTEGER;
+         ALTER TABLE entries ADD COLUMN hash_size INTEGER;
+         ALTER TABLE entries ADD COLUMN head_hash TEXT;
+         ALTER TABLE entries ADD COLUMN content_hash TEXT;",
+    ),
+    // Files cached without a category are classified by extension when they are replayed.
+    (8, "ALTER TABLE entries ADD COLUMN category INTEGER;"),
+    (
+        9,
+        // A listing is only reused while the archive's mtime and size match the ones it was read at.
+        "CREATE TABLE IF NOT EXISTS archive_listings (
+            root_id INTEGER NOT NULL,
+            path TEXT NOT NULL,
+            mtime_utc INTEGER,
+            size INTEGER NOT NULL,
+            PRIMARY KEY(root_id, path),
+            FOREIGN KEY(root_id) REFERENCES roots(id)
+        );
+        CREATE TABLE IF NOT EXISTS archive_members (
+            root_id INTEGER NOT NULL,
+            archive TEXT NOT NULL,
+            path TEXT NOT NULL,
+            kind INTEGER NOT NULL,
+            size INTEGER NOT NULL,
+            compressed_size INTEGER,
+            PRIMARY KEY(root_id, archive, path),
+            FOREIGN KEY(root_id) REFERENCES roots(id)
+        );",
+    ),
+    (
+        10,
+        // Second-resolution mtimes miss changes made within the same second. Rows without the
+        // nanosecond stamps never match the disk, so each directory is walked once more.
+        "ALTER TABLE entries ADD COLUMN mtime_ns INTEGER;
+         ALTER TABLE entries ADD COLUMN ctime_ns INTEGER;",
+    ),
+    (
+        11,
+        // Rows cached before ownership was recorded are marked dirty so the next scan fills it in.
+        "ALTER TABLE entries ADD COLUMN uid INTEGER;
+         ALTER TABLE entries ADD COLUMN gid INTEGER;
+         ALTER TABLE entries ADD COLUMN mode INTEGER;
+         UPDATE entries SET flags = flags | 1;",
+    ),
+    (
+        12,
+        // A file rewritten within the same second at the same size kept its second-resolution
+        // `hash_mtime`. Hashes are now keyed on the nanosecond stamps and the inode; rows hashed
+        // before have none of them, so their files are hashed once more.
+        "ALTER TABLE entries ADD COLUMN hash_mtime_ns INTEGER;
+         ALTER TABLE entries ADD COLUMN hash_ctime_ns INTEGER;
+         ALTER TABLE entries ADD COLUMN hash_inode INTEGER;",
+    ),
+];
+const ENTRY_COLUMNS: &str = "path, parent, kind, direct_size, aggregate_size, mtime_utc, ctime_utc, \
+     flags, allocated_size, aggregate_allocated, device, inode, link_target, ignore_key, category, \
+     mtime_ns, ctime_ns, uid, gid, mode";
+/// Matches the row at `?2` and every row below it; `.` covers the whole root.
+const SUBTREE_CONDITION: &str =
+    "(?2 = '.' OR path = ?2 OR substr(path, 1, length(?2) + 1) = ?2 || '/')";
+
+/// The entry (or one of its descendants) changed and cannot be reused for cache skips.
+pub const FLAG_DIRTY: i64 = 1;
+/// A file that can be reached more than once (hard links or followed symlinks); `device`/`inode`
+/// identify it.
+pub const FLAG_MULTI_LINK: i64 = 1 << 1;
+/// An additional hard link whose bytes were already counted elsewhere in the scan.
+pub const FLAG_HARDLINK_DUPLICATE: i64 = 1 << 2;
+/// A directory on another filesystem that one-filesystem scans did not descend into.
+pub const FLAG_MOUNT_POINT: i64 = 1 << 3;
+/// A symlink whose target did not exist when it was scanned.
+pub const FLAG_DANGLING_LINK: i64 = 1 << 4;
+/// A symlink that was followed; the entry describes its target and `link_target` the link.
+pub const FLAG_FOLLOWED_LINK: i64 = 1 << 5;
+/// A symlink left unfollowed by a link-following scan (target inside the root, a loop, or
+/// already counted through another link).
+pub const FLAG_LINK_NOT_FOLLOWED: i64 = 1 << 6;
+/// A directory excluded by ignore rules; it was not walked and its size is unknown.
+pub const FLAG_EXCLUDED: i64 = 1 << 7;
+/// A regular file whose allocated blocks cover much less than its length (see `Allocation`).
+pub const FLAG_SPARSE: i64 = 1 << 8;
+/// A regular file with more blocks allocated than its length needs.
+pub const FLAG_PREALLOCATED: i64 = 1 << 9;
 const CACHE_MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24 * 30); // 30 days
 const CACHE_MAX_BYTES: u64 = 512 * 1024 * 1024; // 512 MB safety ceiling
 
@@ -24,6 +145,67 @@ pub struct CachedEntry {
     pub modified: Option<i64>,
     pub created: Option<i64>,
     pub flags: i64,
+    pub allocated_size: u64,
+    pub aggregate_allocated: u64,
+    pub device: Option<u64>,
+    pub inode: Option<u64>,
+    pub link_target: Option<PathBuf>,
+    /// Fingerprint of the ignore rules a directory was walked under (see `ExcludeRules::key`).
+    pub ignore_key: Option<u64>,
+    pub category: Option<FileCategory>,
+    /// Modification time in nanoseconds since the epoch.
+    pub modified_ns: Option<i64>,
+    /// Inode change time (`st_ctime`) in nanoseconds since the epoch. Unlike `created`, it moves
+    /// whenever the entry's contents, links or timestamps are touched.
+    pub changed_ns: Option<i64>,
+    pub owner: Option<Ownership>,
+}
+
+impl CachedEntry {
+    /// Content category of a cached file. Rows cached before categories existed are classified
+    /// by extension rather than by reading the file.
+    pub fn file_category(&self) -> Option<FileCategory> {
+        (self.kind == FileKind::File).then(|| {
+            self.category
+                .or_else(|| FileCategory::from_extension(&self.path))
+                .unwrap_or(FileCategory::Other)
+        })
+    }
+
+    /// Allocation anomaly recorded for this entry when it was scanned.
+    pub fn allocation(&self) -> Option<Allocation> {
+        if self.flags & FLAG_SPARSE != 0 {
+            Some(Allocation::Sparse)
+        } else if self.flags & FLAG_PREALLOCATED != 0 {
+            Some(Allocation::Preallocated)
+        } else {
+            None
+        }
+    }
+
+    /// Bytes this entry contributes to its parent's aggregate, excluding descendants.
+    pub fn counted_size(&self) -> u64 {
+        if self.flags & FLAG_HARDLINK_DUPLICATE != 0 {
+            0
+        } else {
+            self.direct_size
+        }
+    }
+
+    pub fn counted_allocated(&self) -> u64 {
+        if self.flags & FLAG_HARDLINK_DUPLICATE != 0 {
+            0
+        } else {
+            self.allocated_size
+        }
+    }
+
+    pub fn link_key(&self) -> Option<(u64, u64)> {
+        if self.flags & FLAG_MULTI_LINK == 0 {
+            return None;
+        }
+        Some((self.device?, self.inode?))
+    }
 }
 
 #[derive(Clone)]
@@ -31,9 +213,27 @@ pub struct Cache {
     db_path: PathBuf,
 }
 
+/// A cached regular file considered by the duplicate finder, with the content hashes recorded for
+/// its current mtime, ctime, inode and size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashRecord {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Nanosecond mtime and ctime the file was scanned with.
+    pub modified_ns: Option<i64>,
+    pub changed_ns: Option<i64>,
+    pub device: Option<u64>,
+    pub inode: Option<u64>,
+    /// Hash of the first `duplicates::HEAD_BYTES` bytes.
+    pub head_hash: Option<String>,
+    pub content_hash: Option<String>,
+}
+
 pub struct RootCache {
     pub root_id: i64,
     pub entries: Vec<CachedEntry>,
+    /// Errors from the scans that produced `entries`, with root-relative paths.
+    pub errors: Vec<ScanError>,
 }
 
 pub struct ScanSession {
@@ -58,6 +258,10 @@ pub enum CacheValidationError {
         expected: u64,
         cached: u64,
     },
+    /// The cached entry was recorded under scan options that no longer apply.
+    OptionsChanged(PathBuf),
+    /// A followed symlink now resolves to a different or modified target.
+    LinkTargetChanged(PathBuf),
     Sqlite(rusqlite::Error),
 }
 
@@ -80,6 +284,16 @@ impl std::fmt::Display for CacheValidationError {
                     cached
                 )
             }
+            CacheValidationError::OptionsChanged(path) => {
+                write!(
+                    f,
+                    "cache entry for {} was recorded with different scan options",
+                    path.display()
+                )
+            }
+            CacheValidationError::LinkTargetChanged(path) => {
+                write!(f, "followed symlink target for {} changed", path.display())
+            }
             CacheValidationError::Sqlite(err) => write!(f, "sqlite error: {err}"),
         }
     }
@@ -110,6 +324,11 @@ impl Cache {
         Ok(cache)
     }
 
+    /// Directory for undo records of actions that rewrite files, next to the database.
+    pub fn undo_dir(&self) -> PathBuf {
+        self.db_path.with_file_name("undo")
+    }
+
     pub fn resolve_root(&self, canonical_root: &Path) -> rusqlite::Result<i64> {
         let root_str = canonical_root.to_string_lossy();
         let conn = self.connection()?;
@@ -134,42 +353,28 @@ impl Cache {
     pub fn load_root(&self, canonical_root: &Path) -> rusqlite::Result<RootCache> {
         let root_id = self.resolve_root(canonical_root)?;
         let conn = self.connection()?;
-        let mut stmt = conn.prepare(
-            "SELECT path, parent, kind, direct_size, aggregate_size, mtime_utc, ctime_utc, flags \
-             FROM entries WHERE root_id = ?1",
-        )?;
-        let rows = stmt.query_map(params![root_id], |row| {
-            let path: String = row.get(0)?;
-            let parent: Option<String> = row.get(1)?;
-            let kind: i64 = row.get(2)?;
-            let direct_size: i64 = row.get(3)?;
-            let aggregate_size: i64 = row.get(4)?;
-            let modified: Option<i64> = row.get(5)?;
-            let created: Option<i64> = row.get(6)?;
-            let flags: i64 = row.get(7)?;
-
-            Ok(CachedEntry {
-                path: PathBuf::from(path),
-                parent: parent.map(PathBuf::from),
-                kind: if kind == 0 {
-                    FileKind::File
-                } else {
-                    FileKind::Directory
-                },
-                direct_size: direct_size as u64,
-                aggregate_size: aggregate_size as u64,
-                modified,
-                created,
-                flags,
-            })
-        })?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ENTRY_COLUMNS} FROM entries WHERE root_id = ?1"
+        ))?;
+        let rows = stmt.query_map(params![root_id], Self::map_cached_entry)?;
 
         let mut entries = Vec::new();
         for entry in rows {
             entries.push(entry?);
         }
 
-        Ok(RootCache { root_id, entries })
+        let mut stmt = conn.prepare(
+            "SELECT path, kind, message FROM scan_errors WHERE root_id = ?1 ORDER BY path",
+        )?;
+        let errors = stmt
+            .query_map(params![root_id], Self::map_scan_error)?
+            .collect::<Result<_, _>>()?;
+
+        Ok(RootCache {
+            root_id,
+            entries,
+            errors,
+        })
     }
 
     pub fn clear_root_path(&self, canonical_root: &Path) -> rusqlite::Result<bool> {
@@ -185,6 +390,18 @@ impl Cache {
         };
 
         conn.execute("DELETE FROM entries WHERE root_id = ?1", params![root_id])?;
+        conn.execute(
+            "DELETE FROM scan_errors WHERE root_id = ?1",
+            params![root_id],
+        )?;
+        conn.execute(
+            "DELETE FROM archive_members WHERE root_id = ?1",
+            params![root_id],
+        )?;
+        conn.execute(
+            "DELETE FROM archive_listings WHERE root_id = ?1",
+            params![root_id],
+        )?;
         conn.execute("DELETE FROM ui_state WHERE root_id = ?1", params![root_id])?;
         let affected = conn.execute("DELETE FROM roots WHERE id = ?1", params![root_id])?;
         Ok(affected > 0)
@@ -204,16 +421,73 @@ impl Cache {
         Ok(())
     }
 
+    /// Marks `relative`, everything below it and its ancestors dirty, so the next scan of that
+    /// subtree walks all of it instead of replaying clean directories.
+    pub fn mark_subtree_dirty(&self, root_id: i64, relative: &Path) -> rusqlite::Result<()> {
+        let conn = self.connection()?;
+        let rel = relative.to_string_lossy();
+        conn.execute(
+            &format!(
+                "UPDATE entries SET flags = flags | 1 WHERE root_id = ?1 AND {SUBTREE_CONDITION}"
+            ),
+            params![root_id, rel.as_ref()],
+        )?;
+        drop(conn);
+        self.mark_ancestors_dirty(root_id, relative)
+    }
+
+    /// `(device, inode)` of the multiply-linked files outside `relative` whose bytes are counted
+    /// where they are, so a rescan of `relative` counts further links to them as duplicates.
+    pub fn counted_links_outside(
+        &self,
+        root_id: i64,
+        relative: &Path,
+    ) -> rusqlite::Result<Vec<(u64, u64)>> {
+        let conn = self.connection()?;
+        let rel = relative.to_string_lossy();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT device, inode FROM entries
+            WHERE root_id = ?1 AND flags & ?3 <> 0 AND flags & ?4 = 0
+                AND device IS NOT NULL AND inode IS NOT NULL AND NOT {SUBTREE_CONDITION}"
+        ))?;
+        let rows = stmt.query_map(
+            params![
+                root_id,
+                rel.as_ref(),
+                FLAG_MULTI_LINK,
+                FLAG_HARDLINK_DUPLICATE
+            ],
+            |row| {
+                let device: i64 = row.get(0)?;
+                let inode: i64 = row.get(1)?;
+                Ok((device as u64, inode as u64))
+            },
+        )?;
+        rows.collect()
+    }
+
     pub fn validate_aggregate(
         &self,
         root_id: i64,
         relative: &Path,
     ) -> Result<AggregateSummary, CacheValidationError> {
+        let summary = self.validate_aggregate_until(root_id, relative, &|| false)?;
+        Ok(summary.expect("validation that is never superseded finishes"))
+    }
+
+    /// Like `validate_aggregate`, but gives up with `None` as soon as `superseded` returns true,
+    /// which is checked once per entry.
+    pub fn validate_aggregate_until(
+        &self,
+        root_id: i64,
+        relative: &Path,
+        superseded: &dyn Fn() -> bool,
+    ) -> Result<Option<AggregateSummary>, CacheValidationError> {
         let conn = self.connection()?;
         let entry = Self::fetch_entry(&conn, root_id, relative)?
             .ok_or_else(|| CacheValidationError::MissingEntry(relative.to_path_buf()))?;
 
-        self.verify_entry_with_conn(&conn, root_id, entry)
+        self.verify_entry_with_conn(&conn, root_id, entry, superseded)
     }
 
     fn verify_entry_with_conn(
@@ -221,18 +495,26 @@ impl Cache {
         conn: &Connection,
         root_id: i64,
         entry: CachedEntry,
-    ) -> Result<AggregateSummary, CacheValidationError> {
+        superseded: &dyn Fn() -> bool,
+    ) -> Result<Option<AggregateSummary>, CacheValidationError> {
+        if superseded() {
+            return Ok(None);
+        }
         let mut summary = AggregateSummary {
             entry_count: 1,
             directory_count: usize::from(entry.kind == FileKind::Directory),
-            total_size: entry.direct_size,
+            total_size: entry.counted_size(),
         };
 
         if entry.kind == FileKind::Directory {
             let children = Self::fetch_children(conn, root_id, &entry.path)?;
             let mut child_total = 0;
             for child in children {
-                let child_summary = self.verify_entry_with_conn(conn, root_id, child)?;
+                let Some(child_summary) =
+                    self.verify_entry_with_conn(conn, root_id, child, superseded)?
+                else {
+                    return Ok(None);
+                };
                 summary.entry_count += child_summary.entry_count;
                 summary.directory_count += child_summary.directory_count;
                 child_total += child_summary.total_size;
@@ -249,7 +531,7 @@ impl Cache {
         }
 
         summary.total_size = entry.aggregate_size;
-        Ok(summary)
+        Ok(Some(summary))
     }
 
     pub fn load_ui_state(&self, root_id: i64) -> rusqlite::Result<Option<(String, i64)>> {
@@ -306,6 +588,173 @@ impl Cache {
         Ok(())
     }
 
+    /// Regular files of at least `min_size` bytes, leaving out extra hard links and followed
+    /// symlinks whose bytes are not reclaimable. Hashes recorded for another mtime, ctime, inode or
+    /// size are dropped.
+    pub fn hash_candidates(
+        &self,
+        root_id: i64,
+        min_size: u64,
+    ) -> rusqlite::Result<Vec<HashRecord>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT path, direct_size, mtime_ns, ctime_ns, device, inode, hash_mtime_ns,
+                hash_ctime_ns, hash_inode, hash_size, head_hash, content_hash
+            FROM entries
+            WHERE root_id = ?1 AND kind = ?2 AND direct_size >= ?3 AND flags & ?4 = 0",
+        )?;
+        let skipped = FLAG_HARDLINK_DUPLICATE | FLAG_FOLLOWED_LINK;
+        let rows = stmt.query_map(
+            params![
+                root_id,
+                kind_to_column(FileKind::File),
+                min_size as i64,
+                skipped
+            ],
+            |row| {
+                let path: String = row.get(0)?;
+                let size: i64 = row.get(1)?;
+                let modified_ns: Option<i64> = row.get(2)?;
+                let changed_ns: Option<i64> = row.get(3)?;
+                let device: Option<i64> = row.get(4)?;
+                let inode: Option<i64> = row.get(5)?;
+                let hash_key: (Option<i64>, Option<i64>, Option<i64>) =
+                    (row.get(6)?, row.get(7)?, row.get(8)?);
+                let hash_size: Option<i64> = row.get(9)?;
+                let current = modified_ns.is_some()
+                    && hash_key == (modified_ns, changed_ns, inode)
+                    && hash_size == Some(size);
+                Ok(HashRecord {
+                    path: PathBuf::from(path),
+                    size: size as u64,
+                    modified_ns,
+                    changed_ns,
+                    device: device.map(|value| value as u64),
+                    inode: inode.map(|value| value as u64),
+                    head_hash: if current { row.get(10)? } else { None },
+                    content_hash: if current { row.get(11)? } else { None },
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    /// Stores the hashes of `records`, keyed on the mtime, ctime, inode and size they were
+    /// computed for.
+    pub fn store_hashes(&self, root_id: i64, records: &[HashRecord]) -> rusqlite::Result<()> {
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "UPDATE entries SET hash_mtime_ns = ?3, hash_ctime_ns = ?4, hash_inode = ?5,
+                    hash_size = ?6, head_hash = ?7, content_hash = ?8
+                WHERE root_id = ?1 AND path = ?2",
+            )?;
+            for record in records {
+                let path = record.path.to_string_lossy();
+                stmt.execute(params![
+                    root_id,
+                    path.as_ref(),
+                    record.modified_ns,
+                    record.changed_ns,
+                    record.inode.map(|value| value as i64),
+                    record.size as i64,
+                    record.head_hash,
+                    record.content_hash,
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Members of the archive at `relative` as listed for this `modified` and `size`, or `None`
+    /// when it was not listed at them.
+    pub fn archive_listing(
+        &self,
+        root_id: i64,
+        relative: &Path,
+        modified: Option<i64>,
+        size: u64,
+    ) -> rusqlite::Result<Option<Vec<ArchiveMember>>> {
+        let conn = self.connection()?;
+        let rel = relative.to_string_lossy();
+        let key: Option<(Option<i64>, i64)> = conn
+            .query_row(
+                "SELECT mtime_utc, size FROM archive_listings WHERE root_id = ?1 AND path = ?2",
+                params![root_id, rel.as_ref()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        if key != Some((modified, size as i64)) {
+            return Ok(None);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT path, kind, size, compressed_size FROM archive_members
+            WHERE root_id = ?1 AND archive = ?2
+            ORDER BY rowid",
+        )?;
+        let members = stmt
+            .query_map(params![root_id, rel.as_ref()], |row| {
+                let path: String = row.get(0)?;
+                let size: i64 = row.get(2)?;
+                let compressed_size: Option<i64> = row.get(3)?;
+                Ok(ArchiveMember {
+                    path: PathBuf::from(path),
+                    kind: kind_from_column(row.get(1)?),
+                    size: size as u64,
+                    compressed_size: compressed_size.map(|value| value as u64),
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(Some(members))
+    }
+
+    /// Replaces the listing stored for the archive at `relative`, keyed on its `modified` and
+    /// `size`.
+    pub fn store_archive_listing(
+        &self,
+        root_id: i64,
+        relative: &Path,
+        modified: Option<i64>,
+        size: u64,
+        members: &[ArchiveMember],
+    ) -> rusqlite::Result<()> {
+        let mut conn = self.connection()?;
+        let rel = relative.to_string_lossy();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM archive_members WHERE root_id = ?1 AND archive = ?2",
+            params![root_id, rel.as_ref()],
+        )?;
+        tx.execute(
+            "INSERT INTO archive_listings (root_id, path, mtime_utc, size) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(root_id, path) DO UPDATE SET
+                mtime_utc = excluded.mtime_utc,
+                size = excluded.size",
+            params![root_id, rel.as_ref(), modified, size as i64],
+        )?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO archive_members
+                    (root_id, archive, path, kind, size, compressed_size)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for member in members {
+                let path = member.path.to_string_lossy();
+                stmt.execute(params![
+                    root_id,
+                    rel.as_ref(),
+                    path.as_ref(),
+                    kind_to_column(member.kind),
+                    member.size as i64,
+                    member.compressed_size.map(|value| value as i64),
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
     pub fn entry(&self, root_id: i64, relative: &Path) -> rusqlite::Result<Option<CachedEntry>> {
         let conn = self.connection()?;
         Self::fetch_entry(&conn, root_id, relative)
@@ -323,10 +772,9 @@ impl Cache {
     ) -> rusqlite::Result<Option<CachedEntry>> {
         let rel = relative.to_string_lossy();
         conn.query_row(
-            "SELECT path, parent, kind, direct_size, aggregate_size, mtime_utc, ctime_utc, flags \
-             FROM entries WHERE root_id = ?1 AND path = ?2",
+            &format!("SELECT {ENTRY_COLUMNS} FROM entries WHERE root_id = ?1 AND path = ?2"),
             params![root_id, rel.as_ref()],
-            |row| Self::map_cached_entry(row),
+            Self::map_cached_entry,
         )
         .optional()
     }
@@ -341,10 +789,9 @@ impl Cache {
         } else {
             Some(parent.to_string_lossy().to_string())
         };
-        let mut stmt = conn.prepare(
-            "SELECT path, parent, kind, direct_size, aggregate_size, mtime_utc, ctime_utc, flags \
-             FROM entries WHERE root_id = ?1 AND parent IS ?2",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ENTRY_COLUMNS} FROM entries WHERE root_id = ?1 AND parent IS ?2"
+        ))?;
         let rows = stmt.query_map(params![root_id, parent_str], |row| {
             Self::map_cached_entry(row)
         })?;
@@ -365,20 +812,55 @@ impl Cache {
         let modified: Option<i64> = row.get(5)?;
         let created: Option<i64> = row.get(6)?;
         let flags: i64 = row.get(7)?;
+        let allocated_size: i64 = row.get(8)?;
+        let aggregate_allocated: i64 = row.get(9)?;
+        let device: Option<i64> = row.get(10)?;
+        let inode: Option<i64> = row.get(11)?;
+        let link_target: Option<String> = row.get(12)?;
+        let ignore_key: Option<i64> = row.get(13)?;
+        let category: Option<i64> = row.get(14)?;
+        let modified_ns: Option<i64> = row.get(15)?;
+        let changed_ns: Option<i64> = row.get(16)?;
+        let uid: Option<i64> = row.get(17)?;
+        let gid: Option<i64> = row.get(18)?;
+        let mode: Option<i64> = row.get(19)?;
 
         Ok(CachedEntry {
             path: PathBuf::from(path),
             parent: parent.map(PathBuf::from),
-            kind: if kind == 0 {
-                FileKind::File
-            } else {
-                FileKind::Directory
-            },
+            kind: kind_from_column(kind),
             direct_size: direct_size as u64,
             aggregate_size: aggregate_size as u64,
             modified,
             created,
             flags,
+            allocated_size: allocated_size as u64,
+            aggregate_allocated: aggregate_allocated as u64,
+            device: device.map(|value| value as u64),
+            inode: inode.map(|value| value as u64),
+            link_target: link_target.map(PathBuf::from),
+            ignore_key: ignore_key.map(|value| value as u64),
+            category: category.and_then(category_from_column),
+            modified_ns,
+            changed_ns,
+            owner: match (uid, gid, mode) {
+                (Some(uid), Some(gid), Some(mode)) => Some(Ownership {
+                    uid: uid as u32,
+                    gid: gid as u32,
+                    mode: mode as u32,
+                }),
+                _ => None,
+            },
+        })
+    }
+
+    fn map_scan_error(row: &rusqlite::Row<'_>) -> rusqlite::Result<ScanError> {
+        let path: String = row.get(0)?;
+        let kind: i64 = row.get(1)?;
+        Ok(ScanError {
+            path: PathBuf::from(path),
+            kind: error_kind_from_column(kind),
+            message: row.get(2)?,
         })
     }
 
@@ -495,38 +977,49 @@ impl Cache {
 
     pub fn begin_scan(&self, root_id: i64) -> rusqlite::Result<ScanSession> {
         let conn = self.connection()?;
+        // Rows not seen by this scan are deleted by `finish`, so back-to-back scans within the
+        // same second still need distinct timestamps.
+        let last_scan: i64 = conn
+            .query_row(
+                "SELECT last_scan_utc FROM roots WHERE id = ?1",
+                params![root_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or(0);
         Ok(ScanSession {
             conn,
             root_id,
-            scan_ts: Utc::now().timestamp(),
+            scan_ts: Utc::now().timestamp().max(last_scan + 1),
             db_path: self.db_path.clone(),
         })
     }
 }
 
 impl ScanSession {
-    pub fn upsert_entry(
-        &mut self,
-        relative: &Path,
-        parent: Option<&Path>,
-        kind: FileKind,
-        direct_size: u64,
-        aggregate_size: u64,
-        modified: Option<i64>,
-        created: Option<i64>,
-    ) -> rusqlite::Result<()> {
-        let path = relative.to_string_lossy();
-        let parent = parent.map(|p| p.to_string_lossy().to_string());
-        let kind_val = match kind {
-            FileKind::File => 0,
-            FileKind::Directory => 1,
-        };
+    /// Writes `entry` for the current scan. The dirty bit is always cleared; other flag bits are
+    /// stored as given.
+    pub fn upsert_entry(&mut self, entry: &CachedEntry) -> rusqlite::Result<()> {
+        let path = entry.path.to_string_lossy();
+        let parent = entry
+            .parent
+            .as_ref()
+            .map(|p| p.to_string_lossy().to_string());
+        let link_target = entry
+            .link_target
+            .as_ref()
+            .map(|target| target.to_string_lossy().to_string());
 
         self.conn.execute(
             "INSERT INTO entries (
                 root_id, path, parent, kind, direct_size, aggregate_size,
-                mtime_utc, ctime_utc, last_seen_utc
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                mtime_utc, ctime_utc, last_seen_utc, flags, allocated_size, aggregate_allocated,
+                device, inode, link_target, ignore_key, category, mtime_ns, ctime_ns, uid, gid,
+                mode
+            ) VALUES (
+                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+                ?19, ?20, ?21, ?22
+            )
             ON CONFLICT(root_id, path) DO UPDATE SET
                 parent = excluded.parent,
                 kind = excluded.kind,
@@ -535,17 +1028,42 @@ impl ScanSession {
                 mtime_utc = excluded.mtime_utc,
                 ctime_utc = excluded.ctime_utc,
                 last_seen_utc = excluded.last_seen_utc,
-                flags = 0",
+                flags = excluded.flags,
+                allocated_size = excluded.allocated_size,
+                aggregate_allocated = excluded.aggregate_allocated,
+                device = excluded.device,
+                inode = excluded.inode,
+                link_target = excluded.link_target,
+                ignore_key = excluded.ignore_key,
+                category = excluded.category,
+                mtime_ns = excluded.mtime_ns,
+                ctime_ns = excluded.ctime_ns,
+                uid = excluded.uid,
+                gid = excluded.gid,
+                mode = excluded.mode",
             params![
                 self.root_id,
                 path.as_ref(),
                 parent,
-                kind_val,
-                direct_size as i64,
-                aggregate_size as i64,
-                modified,
-                created,
+                kind_to_column(entry.kind),
+                entry.direct_size as i64,
+                entry.aggregate_size as i64,
+                entry.modified,
+                entry.created,
                 self.scan_ts,
+                entry.flags & !FLAG_DIRTY,
+                entry.allocated_size as i64,
+                entry.aggregate_allocated as i64,
+                entry.device.map(|value| value as i64),
+                entry.inode.map(|value| value as i64),
+                link_target,
+                entry.ignore_key.map(|value| value as i64),
+                entry.category.map(category_to_column),
+                entry.modified_ns,
+                entry.changed_ns,
+                entry.owner.map(|owner| owner.uid),
+                entry.owner.map(|owner| owner.gid),
+                entry.owner.map(|owner| owner.mode),
             ],
         )?;
         Ok(())
@@ -563,6 +1081,11 @@ impl ScanSession {
             "DELETE FROM entries WHERE root_id = ?1 AND last_seen_utc <> ?2",
             params![root_id, scan_ts],
         )?;
+        conn.execute(
+            "DELETE FROM scan_errors WHERE root_id = ?1 AND last_seen_utc <> ?2",
+            params![root_id, scan_ts],
+        )?;
+        Self::drop_orphaned_listings(&conn, root_id)?;
         conn.execute(
             "UPDATE roots SET last_scan_utc = ?1, scan_count = scan_count + 1 WHERE id = ?2",
             params![scan_ts, root_id],
@@ -571,6 +1094,137 @@ impl ScanSession {
         Ok(())
     }
 
+    /// Keeps `relative` and everything below it as seen by this scan without rewriting the rows,
+    /// for subtrees a depth-limited scan reports from the cache instead of walking.
+    pub fn retain_subtree(&mut self, relative: &Path) -> rusqlite::Result<()> {
+        let rel = relative.to_string_lossy();
+        self.conn.execute(
+            &format!(
+                "UPDATE entries SET last_seen_utc = ?3 WHERE root_id = ?1 AND {SUBTREE_CONDITION}"
+            ),
+            params![self.root_id, rel.as_ref(), self.scan_ts],
+        )?;
+        self.retain_errors(relative)?;
+        Ok(())
+    }
+
+    /// Stores `error` (with a root-relative path) as seen by this scan.
+    pub fn record_error(&mut self, error: &ScanError) -> rusqlite::Result<()> {
+        let path = error.path.to_string_lossy();
+        self.conn.execute(
+            "INSERT INTO scan_errors (root_id, path, kind, message, last_seen_utc)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(root_id, path, kind) DO UPDATE SET
+                message = excluded.message,
+                last_seen_utc = excluded.last_seen_utc",
+            params![
+                self.root_id,
+                path.as_ref(),
+                error_kind_to_column(error.kind),
+                error.message,
+                self.scan_ts,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Keeps the errors recorded at or below `relative` for a subtree that is reused from the
+    /// cache, returning them so they can be reported again.
+    pub fn retain_errors(&mut self, relative: &Path) -> rusqlite::Result<Vec<ScanError>> {
+        let rel = relative.to_string_lossy();
+        self.conn.execute(
+            &format!(
+                "UPDATE scan_errors SET last_seen_utc = ?3 WHERE root_id = ?1 AND {SUBTREE_CONDITION}"
+            ),
+            params![self.root_id, rel.as_ref(), self.scan_ts],
+        )?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT path, kind, message FROM scan_errors WHERE root_id = ?1 AND {SUBTREE_CONDITION}
+             ORDER BY path"
+        ))?;
+        let errors = stmt
+            .query_map(params![self.root_id, rel.as_ref()], Cache::map_scan_error)?
+            .collect::<Result<_, _>>()?;
+        Ok(errors)
+    }
+
+    /// Completes a scan that only walked `relative`. Unseen rows are removed below it alone, and
+    /// the aggregates of every ancestor are shifted by how much the subtree changed compared to
+    /// `previous`, its `(aggregate_size, aggregate_allocated)` before the scan.
+    pub fn finish_subtree(self, relative: &Path, previous: (u64, u64)) -> rusqlite::Result<()> {
+        if relative == Path::new(".") {
+            return self.finish();
+        }
+
+        let Self {
+            mut conn,
+            root_id,
+            scan_ts,
+            ..
+        } = self;
+        let rel = relative.to_string_lossy();
+        let tx = conn.transaction()?;
+        tx.execute(
+            &format!(
+                "DELETE FROM entries WHERE root_id = ?1 AND last_seen_utc <> ?3 AND {SUBTREE_CONDITION}"
+            ),
+            params![root_id, rel.as_ref(), scan_ts],
+        )?;
+        tx.execute(
+            &format!(
+                "DELETE FROM scan_errors WHERE root_id = ?1 AND last_seen_utc <> ?3 AND {SUBTREE_CONDITION}"
+            ),
+            params![root_id, rel.as_ref(), scan_ts],
+        )?;
+        Self::drop_orphaned_listings(&tx, root_id)?;
+
+        let (size, allocated): (i64, i64) = tx
+            .query_row(
+                "SELECT aggregate_size, aggregate_allocated FROM entries WHERE root_id = ?1 AND path = ?2",
+                params![root_id, rel.as_ref()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .unwrap_or((0, 0));
+        let size_delta = size - previous.0 as i64;
+        let allocated_delta = allocated - previous.1 as i64;
+
+        if size_delta != 0 || allocated_delta != 0 {
+            let mut current = parent_relative(relative);
+            while let Some(path) = current {
+                let ancestor = path.to_string_lossy();
+                tx.execute(
+                    "UPDATE entries SET aggregate_size = aggregate_size + ?3,
+                        aggregate_allocated = aggregate_allocated + ?4
+                    WHERE root_id = ?1 AND path = ?2",
+                    params![root_id, ancestor.as_ref(), size_delta, allocated_delta],
+                )?;
+                current = parent_relative(&path);
+            }
+        }
+
+        tx.execute(
+            "UPDATE roots SET last_scan_utc = ?1 WHERE id = ?2",
+            params![scan_ts, root_id],
+        )?;
+        tx.commit()
+    }
+
+    /// Removes archive listings whose archive no longer has a cache row.
+    fn drop_orphaned_listings(conn: &Connection, root_id: i64) -> rusqlite::Result<()> {
+        conn.execute(
+            "DELETE FROM archive_members WHERE root_id = ?1
+                AND archive NOT IN (SELECT path FROM entries WHERE root_id = ?1)",
+            params![root_id],
+        )?;
+        conn.execute(
+            "DELETE FROM archive_listings WHERE root_id = ?1
+                AND path NOT IN (SELECT path FROM entries WHERE root_id = ?1)",
+            params![root_id],
+        )?;
+        Ok(())
+    }
+
     fn prune_if_needed(
         conn: &mut Connection,
         root_id: i64,
@@ -627,11 +1281,93 @@ impl ScanSession {
     }
 }
 
+fn kind_to_column(kind: FileKind) -> i64 {
+    match kind {
+        FileKind::File => 0,
+        FileKind::Directory => 1,
+        FileKind::Symlink => 2,
+        FileKind::Fifo => 3,
+        FileKind::Socket => 4,
+        FileKind::CharDevice => 5,
+        FileKind::BlockDevice => 6,
+    }
+}
+
+fn kind_from_column(value: i64) -> FileKind {
+    match value {
+        0 => FileKind::File,
+        2 => FileKind::Symlink,
+        3 => FileKind::Fifo,
+        4 => FileKind::Socket,
+        5 => FileKind::CharDevice,
+        6 => FileKind::BlockDevice,
+        _ => FileKind::Directory,
+    }
+}
+
+fn category_to_column(category: FileCategory) -> i64 {
+    match category {
+        FileCategory::Video => 0,
+        FileCategory::Image => 1,
+        FileCategory::Audio => 2,
+        FileCategory::Archive => 3,
+        FileCategory::Document => 4,
+        FileCategory::Code => 5,
+        FileCategory::Binary => 6,
+        FileCategory::Log => 7,
+        FileCategory::DiskImage => 8,
+        FileCategory::Other => 9,
+    }
+}
+
+fn category_from_column(value: i64) -> Option<FileCategory> {
+    let category = match value {
+        0 => FileCategory::Video,
+        1 => FileCategory::Image,
+        2 => FileCategory::Audio,
+        3 => FileCategory::Archive,
+        4 => FileCategory::Document,
+        5 => FileCategory::Code,
+        6 => FileCategory::Binary,
+        7 => FileCategory::Log,
+        8 => FileCategory::DiskImage,
+        9 => FileCategory::Other,
+        _ => return None,
+    };
+    Some(category)
+}
+
+fn error_kind_to_column(kind: ScanErrorKind) -> i64 {
+    match kind {
+        ScanErrorKind::PermissionDenied => 0,
+        ScanErrorKind::Vanished => 1,
+        ScanErrorKind::Io => 2,
+        ScanErrorKind::PathTooLong => 3,
+        ScanErrorKind::Cache => 4,
+    }
+}
+
+fn error_kind_from_column(value: i64) -> ScanErrorKind {
+    match value {
+        0 => ScanErrorKind::PermissionDenied,
+        1 => ScanErrorKind::Vanished,
+        3 => ScanErrorKind::PathTooLong,
+        4 => ScanErrorKind::Cache,
+        _ => ScanErrorKind::Io,
+    }
+}
+
 pub fn timestamp_from_system(time: Option<std::time::SystemTime>) -> Option<i64> {
     time.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
         .map(|d| d.as_secs() as i64)
 }
 
+/// Nanoseconds since the epoch, for change detection finer than `timestamp_from_system`.
+pub fn timestamp_nanos_from_system(time: Option<std::time::SystemTime>) -> Option<i64> {
+    time.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .and_then(|d| i64::try_from(d.as_nanos()).ok())
+}
+
 pub fn timestamp_to_system(ts: Option<i64>) -> Option<std::time::SystemTime> {
     ts.map(|secs| std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs as u64))
 }
@@ -657,6 +1393,35 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    fn record(
+        path: &Path,
+        parent: Option<&Path>,
+        kind: FileKind,
+        direct_size: u64,
+        aggregate_size: u64,
+    ) -> CachedEntry {
+        CachedEntry {
+            path: path.to_path_buf(),
+            parent: parent.map(Path::to_path_buf),
+            kind,
+            direct_size,
+            aggregate_size,
+            modified: None,
+            created: None,
+            flags: 0,
+            allocated_size: 0,
+            aggregate_allocated: 0,
+            device: None,
+            inode: None,
+            link_target: None,
+            ignore_key: None,
+            category: None,
+            modified_ns: None,
+            changed_ns: None,
+            owner: None,
+        }
+    }
+
     fn temp_cache() -> (Cache, TempDir, i64) {
         let dir = tempfile::tempdir().expect("tempdir");
         let db_path = dir.path().join("cache.sqlite");
@@ -672,40 +1437,34 @@ mod tests {
         let mut session = cache.begin_scan(root_id).expect("begin scan");
 
         session
-            .upsert_entry(Path::new("."), None, FileKind::Directory, 0, 0, None, None)
+            .upsert_entry(&record(Path::new("."), None, FileKind::Directory, 0, 0))
             .expect("root upsert");
         session
-            .upsert_entry(
+            .upsert_entry(&record(
                 Path::new("dir"),
                 Some(Path::new(".")),
                 FileKind::Directory,
                 0,
                 0,
-                None,
-                None,
-            )
+            ))
             .expect("dir upsert");
         session
-            .upsert_entry(
+            .upsert_entry(&record(
                 Path::new("dir/sub"),
                 Some(Path::new("dir")),
                 FileKind::Directory,
                 0,
                 0,
-                None,
-                None,
-            )
+            ))
             .expect("sub upsert");
         session
-            .upsert_entry(
+            .upsert_entry(&record(
                 Path::new("dir/sub/file.txt"),
                 Some(Path::new("dir/sub")),
                 FileKind::File,
                 42,
                 42,
-                None,
-                None,
-            )
+            ))
             .expect("file upsert");
         session.finish().expect("finish");
 
@@ -742,29 +1501,25 @@ mod tests {
         let mut session = cache.begin_scan(root_id).expect("begin scan");
 
         session
-            .upsert_entry(Path::new("."), None, FileKind::Directory, 0, 0, None, None)
+            .upsert_entry(&record(Path::new("."), None, FileKind::Directory, 0, 0))
             .expect("root upsert");
         session
-            .upsert_entry(
+            .upsert_entry(&record(
                 Path::new("dir"),
                 Some(Path::new(".")),
                 FileKind::Directory,
                 0,
                 0,
-                None,
-                None,
-            )
+            ))
             .expect("dir upsert");
         session
-            .upsert_entry(
+            .upsert_entry(&record(
                 Path::new("dir/sub"),
                 Some(Path::new("dir")),
                 FileKind::Directory,
                 0,
                 0,
-                None,
-                None,
-            )
+            ))
             .expect("sub upsert");
         session.finish().expect("finish");
 
@@ -795,29 +1550,25 @@ mod tests {
         let mut session = cache.begin_scan(root_id).expect("begin scan");
 
         session
-            .upsert_entry(Path::new("."), None, FileKind::Directory, 0, 0, None, None)
+            .upsert_entry(&record(Path::new("."), None, FileKind::Directory, 0, 0))
             .expect("root upsert");
         session
-            .upsert_entry(
+            .upsert_entry(&record(
                 Path::new("dir"),
                 Some(Path::new(".")),
                 FileKind::Directory,
                 0,
                 0,
-                None,
-                None,
-            )
+            ))
             .expect("dir upsert");
         session
-            .upsert_entry(
+            .upsert_entry(&record(
                 Path::new("dir/file.txt"),
                 Some(Path::new("dir")),
                 FileKind::File,
                 10,
                 10,
-                None,
-                None,
-            )
+            ))
             .expect("file upsert");
         session.finish().expect("finish");
 
@@ -845,37 +1596,25 @@ mod tests {
         let mut session = cache.begin_scan(root_id).expect("begin scan");
 
         session
-            .upsert_entry(
-                Path::new("."),
-                None,
-                FileKind::Directory,
-                0,
-                999,
-                None,
-                None,
-            )
+            .upsert_entry(&record(Path::new("."), None, FileKind::Directory, 0, 999))
             .expect("root upsert");
         session
-            .upsert_entry(
+            .upsert_entry(&record(
                 Path::new("dir"),
                 Some(Path::new(".")),
                 FileKind::Directory,
                 0,
                 100,
-                None,
-                None,
-            )
+            ))
             .expect("dir upsert");
         session
-            .upsert_entry(
+            .upsert_entry(&record(
                 Path::new("dir/file.bin"),
                 Some(Path::new("dir")),
                 FileKind::File,
                 100,
                 100,
-                None,
-                None,
-            )
+            ))
             .expect("file upsert");
         session.finish().expect("finish");
 
@@ -890,4 +1629,42 @@ mod tests {
             other => panic!("unexpected error: {other:?}"),
         }
     }
+
+    #[test]
+    fn scan_errors_persist_until_a_scan_no_longer_sees_them() {
+        let (cache, dir, root_id) = temp_cache();
+        let canonical_root = dir.path().canonicalize().expect("canonical root");
+        let denied = ScanError {
+            path: PathBuf::from("private"),
+            kind: ScanErrorKind::PermissionDenied,
+            message: "Permission denied".to_string(),
+        };
+        let vanished = ScanError {
+            path: PathBuf::from("tmp/gone.txt"),
+            kind: ScanErrorKind::Vanished,
+            message: "No such file or directory".to_string(),
+        };
+
+        let mut session = cache.begin_scan(root_id).expect("begin scan");
+        session.record_error(&denied).expect("record denied");
+        session.record_error(&vanished).expect("record vanished");
+        session.finish().expect("finish");
+        let loaded = cache.load_root(&canonical_root).expect("load root");
+        assert_eq!(loaded.errors, vec![denied.clone(), vanished.clone()]);
+
+        // A scan that reuses `private` from the cache keeps its error; `tmp` was walked again
+        // without failing, so its error is gone.
+        let mut session = cache.begin_scan(root_id).expect("begin scan");
+        let retained = session
+            .retain_errors(Path::new("private"))
+            .expect("retain errors");
+        assert_eq!(retained, vec![denied.clone()]);
+        session.finish().expect("finish");
+        let loaded = cache.load_root(&canonical_root).expect("load root");
+        assert_eq!(loaded.errors, vec![denied]);
+
+        assert!(cache.clear_root_path(&canonical_root).expect("clear root"));
+        let loaded = cache.load_root(&canonical_root).expect("load root");
+        assert!(loaded.errors.is_empty());
+    }
 }
@@ -0,0 +1,530 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{self, HashRecord};
+use crate::fs as dusk_fs;
+use crate::scanner::CacheContext;
+
+/// Bytes read for the first hashing pass. Files no larger than this are fully hashed by it.
+pub const HEAD_BYTES: usize = 4096;
+/// Bytes read from each file at a time when comparing a copy with the one kept.
+const COMPARE_CHUNK: usize = 64 * 1024;
+
+/// Files with identical contents. `paths` is sorted and holds at least two entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    pub content_hash: String,
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// Bytes freed by keeping a single copy.
+    pub fn reclaimable(&self) -> u64 {
+        self.size * (self.paths.len() as u64).saturating_sub(1)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DuplicateStats {
+    /// Files sharing their size with at least one other file.
+    pub candidates: u64,
+    pub head_hashes: u64,
+    pub full_hashes: u64,
+    /// Hashes taken from the cache instead of reading the file.
+    pub hashes_reused: u64,
+    pub bytes_hashed: u64,
+    /// Files that changed or disappeared since they were scanned.
+    pub stale_files: u64,
+}
+
+struct Candidate {
+    root: usize,
+    absolute: PathBuf,
+    record: HashRecord,
+    /// Hashes were computed (not reused) and need to be written back.
+    updated: bool,
+}
+
+/// Finds duplicate files among the cached files of `roots` of at least `min_size` bytes.
+///
+/// Files are grouped by size, then by a hash of their first `HEAD_BYTES`, then by a hash of their
+/// whole content, so only files that may still be duplicates are read in full. Hashes are reused
+/// from the cache while a file's nanosecond mtime and ctime, inode and size are unchanged, and new
+/// ones are stored back. A file whose directory was replayed from the cache may have been
+/// rewritten since its row was stored, so reused hashes are checked against the disk too.
+/// Returns `None` when `cancelled` reports true before the search is done.
+pub fn find_duplicates(
+    roots: &[CacheContext],
+    min_size: u64,
+    cancelled: &dyn Fn() -> bool,
+) -> rusqlite::Result<Option<(Vec<DuplicateGroup>, DuplicateStats)>> {
+    let mut stats = DuplicateStats::default();
+    let mut by_size: BTreeMap<u64, Vec<Candidate>> = BTreeMap::new();
+    for (index, ctx) in roots.iter().enumerate() {
+        for record in ctx.cache.hash_candidates(ctx.root_id, min_size.max(1))? {
+            by_size.entry(record.size).or_default().push(Candidate {
+                root: index,
+                absolute: absolute_path(&ctx.canonical_root, &record.path),
+                record,
+                updated: false,
+            });
+        }
+    }
+
+    let mut groups = Vec::new();
+    let mut hashed: Vec<Candidate> = Vec::new();
+    for (size, candidates) in by_size {
+        let mut candidates = distinct_files(candidates);
+        if candidates.len() < 2 {
+            continue;
+        }
+        stats.candidates += candidates.len() as u64;
+
+        let mut by_head: HashMap<String, Vec<Candidate>> = HashMap::new();
+        for mut candidate in candidates.drain(..) {
+            if cancelled() {
+                return Ok(None);
+            }
+            if candidate.record.head_hash.is_some() {
+                if !matches_disk(&candidate) {
+                    stats.stale_files += 1;
+                    continue;
+                }
+                stats.hashes_reused += 1;
+            } else {
+                match hash_file(&candidate, Some(HEAD_BYTES)) {
+                    Ok(Some(hash)) => {
+                        stats.head_hashes += 1;
+                        stats.bytes_hashed += size.min(HEAD_BYTES as u64);
+                        // The head of a small file is all of it.
+                        if size <= HEAD_BYTES as u64 {
+                            candidate.record.content_hash = Some(hash.clone());
+                        }
+                        candidate.record.head_hash = Some(hash);
+                        candidate.updated = true;
+                    }
+                    Ok(None) | Err(_) => {
+                        stats.stale_files += 1;
+                        continue;
+                    }
+                }
+            }
+            let head = candidate.record.head_hash.clone().unwrap_or_default();
+            by_head.entry(head).or_default().push(candidate);
+        }
+
+        for (_, same_head) in by_head {
+            if same_head.len() < 2 {
+                hashed.extend(same_head);
+                continue;
+            }
+
+            let mut by_content: HashMap<String, Vec<Candidate>> = HashMap::new();
+            for mut candidate in same_head {
+                if cancelled() {
+                    return Ok(None);
+                }
+                if candidate.record.content_hash.is_some() {
+                    if !candidate.updated {
+                        stats.hashes_reused += 1;
+                    }
+                } else {
+                    match hash_file(&candidate, None) {
+                        Ok(Some(hash)) => {
+                            stats.full_hashes += 1;
+                            stats.bytes_hashed += size;
+                            candidate.record.content_hash = Some(hash);
+                            candidate.updated = true;
+                        }
+                        Ok(None) | Err(_) => {
+                            stats.stale_files += 1;
+                            continue;
+                        }
+                    }
+                }
+                let content = candidate.record.content_hash.clone().unwrap_or_default();
+                by_content.entry(content).or_default().push(candidate);
+            }
+
+            for (content_hash, same_content) in by_content {
+                if same_content.len() > 1 {
+                    let mut paths: Vec<PathBuf> = same_content
+                        .iter()
+                        .map(|candidate| candidate.absolute.clone())
+                        .collect();
+                    paths.sort();
+                    groups.push(DuplicateGroup {
+                        size,
+                        content_hash,
+                        paths,
+                    });
+                }
+                hashed.extend(same_content);
+            }
+        }
+    }
+
+    for (index, ctx) in roots.iter().enumerate() {
+        let updated: Vec<HashRecord> = hashed
+            .iter()
+            .filter(|candidate| candidate.root == index && candidate.updated)
+            .map(|candidate| candidate.record.clone())
+            .collect();
+        if !updated.is_empty() {
+            ctx.cache.store_hashes(ctx.root_id, &updated)?;
+        }
+    }
+
+    groups.sort_by(|a, b| {
+        b.reclaimable()
+            .cmp(&a.reclaimable())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+    Ok(Some((groups, stats)))
+}
+
+/// Keeps one path per inode, since further hard links share its bytes.
+fn distinct_files(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let mut seen = HashSet::new();
+    candidates
+        .into_iter()
+        .filter(
+            |candidate| match (candidate.record.device, candidate.record.inode) {
+                (Some(device), Some(inode)) => seen.insert((device, inode)),
+                _ => true,
+            },
+        )
+        .collect()
+}
+
+/// Whether the candidate's file still has the size, nanosecond mtime and ctime, and inode it was
+/// scanned with.
+fn unchanged(candidate: &Candidate, metadata: &fs::Metadata) -> bool {
+    let record = &candidate.record;
+    metadata.is_file()
+        && metadata.len() == record.size
+        && record.modified_ns.is_some()
+        && cache::timestamp_nanos_from_system(metadata.modified().ok()) == record.modified_ns
+        && cache::timestamp_nanos_from_system(dusk_fs::changed_time(metadata)) == record.changed_ns
+        && dusk_fs::file_id(metadata).map(|(_, inode)| inode) == record.inode
+}
+
+fn matches_disk(candidate: &Candidate) -> bool {
+    fs::metadata(&candidate.absolute).is_ok_and(|metadata| unchanged(candidate, &metadata))
+}
+
+/// Hashes the first `limit` bytes of the candidate's file (all of it without a limit). Returns
+/// `None` when the file changed since it was scanned.
+fn hash_file(candidate: &Candidate, limit: Option<usize>) -> io::Result<Option<String>> {
+    let file = File::open(&candidate.absolute)?;
+    let metadata = file.metadata()?;
+    if !unchanged(candidate, &metadata) {
+        return Ok(None);
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    match limit {
+        Some(limit) => {
+            io::copy(&mut file.take(limit as u64), &mut hasher)?;
+        }
+        None => {
+            io::copy(&mut { file }, &mut hasher)?;
+        }
+    }
+    Ok(Some(hasher.finalize().to_hex().to_string()))
+}
+
+/// How a copy was made to share its source's data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkMethod {
+    /// Copy-on-write clone: a separate file sharing the source's extents.
+    Reflink,
+    HardLink,
+}
+
+/// A copy replaced by a link to `source`, as written to the undo record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkedCopy {
+    pub path: PathBuf,
+    pub source: PathBuf,
+    pub method: LinkMethod,
+    pub size: u64,
+    pub content_hash: String,
+    /// The copy's own mtime, permission bits and owner. A reflink keeps them; a hard link is only
+    /// made when the source has the same mode and owner, and takes the source's mtime.
+    pub modified: Option<i64>,
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+pub struct LinkReport {
+    pub linked: Vec<LinkedCopy>,
+    /// Paths left alone, with the reason.
+    pub skipped: Vec<(PathBuf, String)>,
+    pub bytes_saved: u64,
+    /// JSON file listing `linked`, written when anything was replaced.
+    pub undo_record: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct UndoRecord<'a> {
+    created_utc: i64,
+    linked: &'a [LinkedCopy],
+}
+
+/// Replaces identical copies among `paths` with links to one of them, a reflink where the
+/// filesystem supports it and a hard link otherwise.
+///
+/// Files are grouped by filesystem, size and full content hash; the first path of each group is
+/// kept. Every copy is compared byte for byte with its source right before it is swapped for the
+/// link, which is created next to it and renamed over it. Replaced copies are listed in an undo
+/// record written to `undo_dir`.
+pub fn link_identical(paths: &[PathBuf], undo_dir: &Path) -> io::Result<LinkReport> {
+    let mut report = LinkReport::default();
+    let mut by_size: BTreeMap<(u64, u64), Vec<PathBuf>> = BTreeMap::new();
+    for path in paths {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_file() && metadata.len() > 0 => {
+                let device = dusk_fs::file_id(&metadata).map_or(0, |(device, _)| device);
+                by_size
+                    .entry((device, metadata.len()))
+                    .or_default()
+                    .push(path.clone());
+            }
+            Ok(_) => report
+                .skipped
+                .push((path.clone(), "not a non-empty regular file".to_string())),
+            Err(err) => report.skipped.push((path.clone(), err.to_string())),
+        }
+    }
+
+    for ((_, size), mut group) in by_size {
+        if group.len() < 2 {
+            report.skipped.extend(
+                group
+                    .into_iter()
+                    .map(|path| (path, "no identical copy selected".into())),
+            );
+            continue;
+        }
+        group.sort();
+
+        let mut by_hash: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for path in group {
+            match hash_path(&path) {
+                Ok(hash) => by_hash.entry(hash).or_default().push(path),
+                Err(err) => report.skipped.push((path, err.to_string())),
+            }
+        }
+
+        for (hash, copies) in by_hash {
+            let mut copies = copies.into_iter();
+            let Some(source) = copies.next() else {
+                continue;
+            };
+            let mut any = false;
+            for path in copies {
+                any = true;
+                match replace_with_link(&source, &path, size, &hash) {
+                    Ok(linked) => {
+                        report.bytes_saved += size;
+                        report.linked.push(linked);
+                    }
+                    Err(err) => report.skipped.push((path, err.to_string())),
+                }
+            }
+            if !any {
+                report
+                    .skipped
+                    .push((source, "no identical copy selected".to_string()));
+            }
+        }
+    }
+
+    if !report.linked.is_empty() {
+        fs::create_dir_all(undo_dir)?;
+        let created_utc = Utc::now().timestamp();
+        let mut record_path = undo_dir.join(format!("links-{created_utc}.json"));
+        let mut suffix = 1;
+        while record_path.exists() {
+            record_path = undo_dir.join(format!("links-{created_utc}-{suffix}.json"));
+            suffix += 1;
+        }
+        let record = UndoRecord {
+            created_utc,
+            linked: &report.linked,
+        };
+        let json = serde_json::to_vec_pretty(&record).map_err(io::Error::other)?;
+        fs::write(&record_path, json)?;
+        report.undo_record = Some(record_path);
+    }
+
+    Ok(report)
+}
+
+fn replace_with_link(source: &Path, path: &Path, size: u64, hash: &str) -> io::Result<LinkedCopy> {
+    let source_meta = fs::symlink_metadata(source)?;
+    let metadata = fs::symlink_metadata(path)?;
+    if dusk_fs::file_id(&source_meta).is_some()
+        && dusk_fs::file_id(&source_meta) == dusk_fs::file_id(&metadata)
+    {
+        return Err(io::Error::other("already a hard link to the kept copy"));
+    }
+    if !metadata.is_file() || metadata.len() != size || source_meta.len() != size {
+        return Err(io::Error::other("changed since it was selected"));
+    }
+    if !same_contents(source, path)? {
+        return Err(io::Error::other("contents differ from the kept copy"));
+    }
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp = path.with_file_name(format!(".{file_name}.dusk-link-{}", std::process::id()));
+    let method = match link_to_temp(source, &temp, &source_meta, &metadata)
+        .and_then(|method| fs::rename(&temp, path).map(|()| method))
+    {
+        Ok(method) => method,
+        Err(err) => {
+            let _ = fs::remove_file(&temp);
+            return Err(err);
+        }
+    };
+
+    let owner = dusk_fs::ownership(&metadata);
+    Ok(LinkedCopy {
+        path: path.to_path_buf(),
+        source: source.to_path_buf(),
+        method,
+        size,
+        content_hash: hash.to_string(),
+        modified: cache::timestamp_from_system(metadata.modified().ok()),
+        mode: file_mode(&metadata),
+        uid: owner.map(|owner| owner.uid),
+        gid: owner.map(|owner| owner.gid),
+    })
+}
+
+/// Creates `temp` sharing the data of `source`, preferring a reflink that keeps the copy's owner,
+/// permissions and mtime. Falls back to a hard link only when `source` has the copy's owner, group
+/// and mode, since a hard link shares them.
+fn link_to_temp(
+    source: &Path,
+    temp: &Path,
+    source_meta: &fs::Metadata,
+    metadata: &fs::Metadata,
+) -> io::Result<LinkMethod> {
+    if reflink(source, temp).is_ok() {
+        // A clone is a new file, so it can take the copy's attributes. The owner goes first since
+        // changing it may clear setuid and setgid bits.
+        set_owner(temp, metadata)?;
+        fs::set_permissions(temp, metadata.permissions())?;
+        if let Ok(modified) = metadata.modified() {
+            File::options()
+                .write(true)
+                .open(temp)
+                .and_then(|file| file.set_modified(modified))?;
+        }
+        return Ok(LinkMethod::Reflink);
+    }
+
+    let _ = fs::remove_file(temp);
+    if dusk_fs::ownership(source_meta) != dusk_fs::ownership(metadata) {
+        return Err(io::Error::other(
+            "owner, group or mode differs from the kept copy",
+        ));
+    }
+    fs::hard_link(source, temp)?;
+    Ok(LinkMethod::HardLink)
+}
+
+/// Clones `source` to the new file `dest` with `FICLONE`, sharing its extents.
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, dest: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let source = File::open(source)?;
+    let dest = File::options().write(true).create_new(true).open(dest)?;
+    // SAFETY: both descriptors are open for the duration of the call.
+    let result = unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_source: &Path, _dest: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(unix)]
+fn set_owner(path: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    std::os::unix::fs::chown(path, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn set_owner(_path: &Path, _metadata: &fs::Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Compares two files byte for byte, `COMPARE_CHUNK` bytes at a time.
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut a = File::open(a)?;
+    let mut b = File::open(b)?;
+    let mut chunk_a = Vec::with_capacity(COMPARE_CHUNK);
+    let mut chunk_b = Vec::with_capacity(COMPARE_CHUNK);
+    loop {
+        chunk_a.clear();
+        chunk_b.clear();
+        (&mut a)
+            .take(COMPARE_CHUNK as u64)
+            .read_to_end(&mut chunk_a)?;
+        (&mut b)
+            .take(COMPARE_CHUNK as u64)
+            .read_to_end(&mut chunk_b)?;
+        if chunk_a != chunk_b {
+            return Ok(false);
+        }
+        if chunk_a.is_empty() {
+            return Ok(true);
+        }
+    }
+}
+
+fn hash_path(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+fn absolute_path(root: &Path, relative: &Path) -> PathBuf {
+    if relative.as_os_str().is_empty() || relative == Path::new(".") {
+        root.to_path_buf()
+    } else {
+        root.join(relative)
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::filesystem::FileSystem;
+
+/// Per-directory ignore file honoured by every scan, using gitignore syntax.
+pub const DUSK_IGNORE_FILE: &str = ".duskignore";
+const GIT_IGNORE_FILE: &str = ".gitignore";
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Ignore rules in effect inside one directory: its own ignore files plus everything inherited
+/// from its ancestors below the scan root.
+struct DirRules {
+    parent: Option<Arc<DirRules>>,
+    /// Matchers in precedence order; `.duskignore` wins over `.gitignore`.
+    matchers: Vec<Gitignore>,
+    key: u64,
+}
+
+impl DirRules {
+    fn matched(&self, path: &Path, is_dir: bool) -> bool {
+        let mut rules = Some(self);
+        while let Some(current) = rules {
+            for matcher in &current.matchers {
+                match matcher.matched(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+            rules = current.parent.as_deref();
+        }
+        false
+    }
+}
+
+/// Resolves `.duskignore` (and optionally `.gitignore`) files below a scan root, read through
+/// `filesystem`.
+///
+/// Rules are loaded lazily per directory and shared between the scan loop and the walker's
+/// prefetch filter, so excluded subtrees are never read ahead.
+pub struct ExcludeRules {
+    root: PathBuf,
+    respect_gitignore: bool,
+    filesystem: Arc<dyn FileSystem>,
+    dirs: RwLock<HashMap<PathBuf, Arc<DirRules>>>,
+}
+
+impl ExcludeRules {
+    pub fn new(root: &Path, respect_gitignore: bool, filesystem: Arc<dyn FileSystem>) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            respect_gitignore,
+            filesystem,
+            dirs: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns whether `path` is excluded by the rules of its parent directories.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        if path == self.root {
+            return false;
+        }
+        match path.parent() {
+            Some(parent) if parent.starts_with(&self.root) => {
+                self.rules_for(parent).matched(path, is_dir)
+            }
+            _ => false,
+        }
+    }
+
+    /// Fingerprint of the rules in effect inside `dir`. Cached subtrees are only reused when the
+    /// fingerprint they were recorded under still matches.
+    pub fn key(&self, dir: &Path) -> u64 {
+        self.rules_for(dir).key
+    }
+
+    /// Drops the rules loaded for `dir` once the scan is done with it.
+    pub fn forget(&self, dir: &Path) {
+        if let Ok(mut dirs) = self.dirs.write() {
+            dirs.remove(dir);
+        }
+    }
+
+    fn rules_for(&self, dir: &Path) -> Arc<DirRules> {
+        if let Some(rules) = self
+            .dirs
+            .read()
+            .ok()
+            .and_then(|dirs| dirs.get(dir).cloned())
+        {
+            return rules;
+        }
+
+        let parent = match dir.parent() {
+            Some(parent) if dir != self.root && parent.starts_with(&self.root) => {
+                Some(self.rules_for(parent))
+            }
+            _ => None,
+        };
+        let rules = self.load(dir, parent);
+        if let Ok(mut dirs) = self.dirs.write() {
+            dirs.insert(dir.to_path_buf(), rules.clone());
+        }
+        rules
+    }
+
+    fn load(&self, dir: &Path, parent: Option<Arc<DirRules>>) -> Arc<DirRules> {
+        let mut key = match &parent {
+            Some(parent) => parent.key,
+            None => fnv1a(FNV_OFFSET, &[u8::from(self.respect_gitignore)]),
+        };
+
+        let mut names = vec![DUSK_IGNORE_FILE];
+        if self.respect_gitignore {
+            names.push(GIT_IGNORE_FILE);
+        }
+
+        let mut matchers = Vec::new();
+        for name in names {
+            let path = dir.join(name);
+            let mut contents = Vec::new();
+            if self
+                .filesystem
+                .open(&path)
+                .and_then(|mut file| file.read_to_end(&mut contents))
+                .is_err()
+            {
+                continue;
+            }
+            key = fnv1a(fnv1a(key, name.as_bytes()), &contents);
+
+            let mut builder = GitignoreBuilder::new(dir);
+            for line in String::from_utf8_lossy(&contents).lines() {
+                if let Err(err) = builder.add_line(Some(path.clone()), line) {
+                    eprintln!("dusk ignore file error: {err}");
+                }
+            }
+            match builder.build() {
+                Ok(matcher) => matchers.push(matcher),
+                Err(err) => eprintln!("dusk ignore file error: {err}"),
+            }
+        }
+
+        // Directories without ignore files share their parent's rules.
+        if matchers.is_empty()
+            && let Some(parent) = parent
+        {
+            return parent;
+        }
+
+        Arc::new(DirRules {
+            parent,
+            matchers,
+            key,
+        })
+    }
+}
+
+/// FNV-1a, chosen over `DefaultHasher` because keys are persisted in the cache.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
@@ -0,0 +1,644 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use crate::fs::{self as dusk_fs, FileKind, Ownership};
+
+/// Symlink hops resolved before a path is treated as a loop, as in Linux's `ELOOP` limit.
+const MAX_LINK_HOPS: usize = 40;
+const MEMORY_BLOCK_SIZE: u64 = 4096;
+/// Entries `read_dir_paced` stats per batch.
+const STAT_BATCH: usize = 64;
+const MEMORY_DEVICE: u64 = 1;
+
+/// The parts of a file's metadata the scanner looks at, detached from `std::fs::Metadata` so
+/// that fakes can produce them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsMetadata {
+    pub kind: FileKind,
+    pub len: u64,
+    /// Bytes allocated on disk, as reported by `du`.
+    pub allocated: u64,
+    pub modified: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+    /// Last change to the inode (`st_ctime`), where the platform exposes it.
+    pub changed: Option<SystemTime>,
+    /// `(device, inode)` pair, where the platform exposes one.
+    pub file_id: Option<(u64, u64)>,
+    pub link_count: u64,
+    /// Owner, group and permission bits, where the platform exposes them.
+    pub owner: Option<Ownership>,
+}
+
+impl FsMetadata {
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileKind::Directory
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == FileKind::File
+    }
+}
+
+impl From<&Metadata> for FsMetadata {
+    fn from(metadata: &Metadata) -> Self {
+        Self {
+            kind: FileKind::from_metadata(metadata),
+            len: metadata.len(),
+            allocated: dusk_fs::allocated_size(metadata),
+            modified: metadata.modified().ok(),
+            created: metadata.created().ok(),
+            changed: dusk_fs::changed_time(metadata),
+            file_id: dusk_fs::file_id(metadata),
+            link_count: dusk_fs::link_count(metadata),
+            owner: dusk_fs::ownership(metadata),
+        }
+    }
+}
+
+/// One entry of a directory listing. An entry that could not be read carries the directory's
+/// own path with the error.
+#[derive(Debug)]
+pub struct DirEntry {
+    pub path: PathBuf,
+    /// Metadata of the entry itself, without following symlinks.
+    pub metadata: io::Result<FsMetadata>,
+}
+
+/// A file opened through `FileSystem::open`.
+pub trait FileReader: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> FileReader for T {}
+
+/// Filesystem operations used by scans and deletions, so they can run against
+/// `MemoryFileSystem` in tests.
+pub trait FileSystem: fmt::Debug + Send + Sync {
+    /// Metadata of `path` itself; a symlink is not followed.
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FsMetadata>;
+
+    /// Metadata of whatever `path` resolves to.
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata>;
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    /// Lists `path` like `read_dir`, calling `before_stats` with the number of entries about to
+    /// be stat'ed ahead of each batch, so the caller can pace them. Implementations that cannot
+    /// split the listing report all of it at once.
+    fn read_dir_paced(
+        &self,
+        path: &Path,
+        before_stats: &mut dyn FnMut(u64),
+    ) -> io::Result<Vec<DirEntry>> {
+        let entries = self.read_dir(path)?;
+        before_stats(entries.len() as u64);
+        Ok(entries)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Opens the regular file `path` resolves to for reading, as for sniffing its type or
+    /// listing an archive.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn FileReader>>;
+
+    /// Up to `limit` bytes from the start of the file at `path`.
+    fn read_head(&self, path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+        let mut head = Vec::with_capacity(limit);
+        self.open(path)?.take(limit as u64).read_to_end(&mut head)?;
+        Ok(head)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Deletes `path` and, for a directory, everything below it. A symlink is removed itself;
+    /// its target is never touched.
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        if self.symlink_metadata(path)?.is_dir() {
+            self.remove_dir_all(path)
+        } else {
+            self.remove_file(path)
+        }
+    }
+}
+
+/// The host filesystem, through `std::fs`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        fs::symlink_metadata(path).map(|metadata| FsMetadata::from(&metadata))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        fs::metadata(path).map(|metadata| FsMetadata::from(&metadata))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.read_dir_paced(path, &mut |_| {})
+    }
+
+    /// Names are read `STAT_BATCH` at a time; each entry is only stat'ed by `metadata`.
+    fn read_dir_paced(
+        &self,
+        path: &Path,
+        before_stats: &mut dyn FnMut(u64),
+    ) -> io::Result<Vec<DirEntry>> {
+        let mut listing = fs::read_dir(path)?.peekable();
+        let mut entries = Vec::new();
+        while listing.peek().is_some() {
+            let batch: Vec<_> = listing.by_ref().take(STAT_BATCH).collect();
+            before_stats(batch.len() as u64);
+            entries.extend(batch.into_iter().map(|entry| match entry {
+                Ok(entry) => DirEntry {
+                    path: entry.path(),
+                    metadata: entry.metadata().map(|metadata| FsMetadata::from(&metadata)),
+                },
+                Err(err) => DirEntry {
+                    path: path.to_path_buf(),
+                    metadata: Err(err),
+                },
+            }));
+        }
+        Ok(entries)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn FileReader>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+}
+
+/// Operations of `MemoryFileSystem` that can be made to fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FsOperation {
+    /// `metadata` and `symlink_metadata`, including the metadata of a listed entry.
+    Metadata,
+    ReadDir,
+    ReadLink,
+    /// `open`, and with it `read_head`.
+    Open,
+    Remove,
+}
+
+/// An in-memory tree of directories, files and symlinks with injectable errors.
+///
+/// Every change advances a logical clock and stamps the changed node and its parent directory,
+/// so modification times behave like a real filesystem's while staying deterministic. Paths are
+/// taken literally; only the last component of a path is resolved when it is a symlink.
+#[derive(Debug, Default)]
+pub struct MemoryFileSystem {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    nodes: BTreeMap<PathBuf, MemoryNode>,
+    faults: HashMap<(PathBuf, FsOperation), io::ErrorKind>,
+    clock: u64,
+    next_inode: u64,
+}
+
+#[derive(Debug, Clone)]
+struct MemoryNode {
+    kind: FileKind,
+    len: u64,
+    link_target: Option<PathBuf>,
+    modified: SystemTime,
+    changed: SystemTime,
+    inode: u64,
+    /// Allocation set by `set_allocated`, instead of the length rounded up to whole blocks.
+    allocated: Option<u64>,
+    owner: Ownership,
+    /// Bytes set by `add_file_with_contents`; other files read as zeros.
+    contents: Option<Arc<[u8]>>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates `path` and any missing ancestors as directories.
+    pub fn add_dir(&self, path: impl AsRef<Path>) {
+        let mut state = self.lock();
+        state.create_dirs(path.as_ref());
+    }
+
+    /// Creates (or replaces) a regular file of `len` bytes, with its missing ancestors.
+    pub fn add_file(&self, path: impl AsRef<Path>, len: u64) {
+        let mut state = self.lock();
+        state.insert(path.as_ref(), FileKind::File, len, None);
+    }
+
+    /// Creates (or replaces) a regular file holding `contents`, with its missing ancestors.
+    pub fn add_file_with_contents(&self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) {
+        let contents: Arc<[u8]> = contents.into().into();
+        let mut state = self.lock();
+        state.insert(path.as_ref(), FileKind::File, contents.len() as u64, None);
+        if let Some(node) = state.nodes.get_mut(path.as_ref()) {
+            node.contents = Some(contents);
+        }
+    }
+
+    pub fn add_symlink(&self, path: impl AsRef<Path>, target: impl AsRef<Path>) {
+        let target = target.as_ref().to_path_buf();
+        let len = target.as_os_str().len() as u64;
+        let mut state = self.lock();
+        state.insert(path.as_ref(), FileKind::Symlink, len, Some(target));
+    }
+
+    /// Adds `path` as another name for the file at `existing`.
+    pub fn add_hard_link(&self, existing: impl AsRef<Path>, path: impl AsRef<Path>) {
+        let mut state = self.lock();
+        let Some(node) = state.nodes.get(existing.as_ref()).cloned() else {
+            panic!("no file at {}", existing.as_ref().display());
+        };
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            state.create_dirs(parent);
+        }
+        state.nodes.insert(path.to_path_buf(), node);
+        state.touch_parent(path);
+    }
+
+    /// Sets the length of an existing file, updating its modification time. Contents are cut
+    /// off or padded with zeros.
+    pub fn resize(&self, path: impl AsRef<Path>, len: u64) {
+        let mut state = self.lock();
+        let time = state.tick();
+        if let Some(node) = state.nodes.get_mut(path.as_ref()) {
+            if let Some(contents) = node.contents.take() {
+                let mut contents = contents.to_vec();
+                contents.resize(len as usize, 0);
+                node.contents = Some(contents.into());
+            }
+            node.len = len;
+            node.modified = time;
+            node.changed = time;
+        }
+    }
+
+    /// Reports `allocated` bytes on disk for an existing file, as for sparse or preallocated
+    /// files.
+    pub fn set_allocated(&self, path: impl AsRef<Path>, allocated: u64) {
+        if let Some(node) = self.lock().nodes.get_mut(path.as_ref()) {
+            node.allocated = Some(allocated);
+        }
+    }
+
+    /// Sets the modification time of `path`. Like `utimes`, this counts as a change to the
+    /// inode, so its change time moves on.
+    pub fn set_modified(&self, path: impl AsRef<Path>, modified: SystemTime) {
+        let mut state = self.lock();
+        let time = state.tick();
+        if let Some(node) = state.nodes.get_mut(path.as_ref()) {
+            node.modified = modified;
+            node.changed = time;
+        }
+    }
+
+    /// Changes the owner and group of `path`, which like `chown` moves its change time on.
+    /// New nodes belong to root.
+    pub fn set_owner(&self, path: impl AsRef<Path>, uid: u32, gid: u32) {
+        let mut state = self.lock();
+        let time = state.tick();
+        if let Some(node) = state.nodes.get_mut(path.as_ref()) {
+            node.owner.uid = uid;
+            node.owner.gid = gid;
+            node.changed = time;
+        }
+    }
+
+    /// Changes the permission bits of `path`, which like `chmod` moves its change time on.
+    pub fn set_mode(&self, path: impl AsRef<Path>, mode: u32) {
+        let mut state = self.lock();
+        let time = state.tick();
+        if let Some(node) = state.nodes.get_mut(path.as_ref()) {
+            node.owner.mode = mode & 0o7777;
+            node.changed = time;
+        }
+    }
+
+    /// Makes `operation` on `path` fail with `kind` until `clear_fault` is called.
+    pub fn fail(&self, path: impl AsRef<Path>, operation: FsOperation, kind: io::ErrorKind) {
+        self.lock()
+            .faults
+            .insert((path.as_ref().to_path_buf(), operation), kind);
+    }
+
+    pub fn clear_fault(&self, path: impl AsRef<Path>, operation: FsOperation) {
+        self.lock()
+            .faults
+            .remove(&(path.as_ref().to_path_buf(), operation));
+    }
+
+    pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+        self.lock().nodes.contains_key(path.as_ref())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl MemoryState {
+    fn tick(&mut self) -> SystemTime {
+        self.clock += 1;
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000 + self.clock)
+    }
+
+    fn check(&self, path: &Path, operation: FsOperation) -> io::Result<()> {
+        match self.faults.get(&(path.to_path_buf(), operation)) {
+            Some(kind) => Err(io::Error::new(
+                *kind,
+                format!("injected {operation:?} failure"),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn create_dirs(&mut self, path: &Path) {
+        let missing: Vec<PathBuf> = path
+            .ancestors()
+            .filter(|dir| !dir.as_os_str().is_empty() && !self.nodes.contains_key(*dir))
+            .map(Path::to_path_buf)
+            .collect();
+        for dir in missing.into_iter().rev() {
+            self.insert_node(&dir, FileKind::Directory, 0, None);
+        }
+    }
+
+    fn insert(&mut self, path: &Path, kind: FileKind, len: u64, link_target: Option<PathBuf>) {
+        if let Some(parent) = path.parent() {
+            self.create_dirs(parent);
+        }
+        self.insert_node(path, kind, len, link_target);
+    }
+
+    fn insert_node(&mut self, path: &Path, kind: FileKind, len: u64, link_target: Option<PathBuf>) {
+        self.next_inode += 1;
+        let time = self.tick();
+        let node = MemoryNode {
+            kind,
+            len,
+            link_target,
+            modified: time,
+            changed: time,
+            inode: self.next_inode,
+            allocated: None,
+            contents: None,
+            owner: Ownership {
+                uid: 0,
+                gid: 0,
+                mode: match kind {
+                    FileKind::Directory => 0o755,
+                    FileKind::Symlink => 0o777,
+                    _ => 0o644,
+                },
+            },
+        };
+        self.nodes.insert(path.to_path_buf(), node);
+        self.touch_parent(path);
+    }
+
+    fn touch_parent(&mut self, path: &Path) {
+        let time = self.tick();
+        if let Some(parent) = path.parent().and_then(|parent| self.nodes.get_mut(parent)) {
+            parent.modified = time;
+            parent.changed = time;
+        }
+    }
+
+    fn node(&self, path: &Path) -> io::Result<&MemoryNode> {
+        self.nodes
+            .get(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file or directory"))
+    }
+
+    fn metadata_of(&self, path: &Path) -> io::Result<FsMetadata> {
+        self.check(path, FsOperation::Metadata)?;
+        let node = self.node(path)?;
+        let link_count = self
+            .nodes
+            .values()
+            .filter(|other| other.inode == node.inode)
+            .count() as u64;
+        let allocated = match node.kind {
+            FileKind::File if let Some(allocated) = node.allocated => allocated,
+            FileKind::File => node.len.div_ceil(MEMORY_BLOCK_SIZE) * MEMORY_BLOCK_SIZE,
+            FileKind::Directory => MEMORY_BLOCK_SIZE,
+            _ => 0,
+        };
+        Ok(FsMetadata {
+            kind: node.kind,
+            len: node.len,
+            allocated,
+            modified: Some(node.modified),
+            created: Some(node.modified),
+            changed: Some(node.changed),
+            file_id: Some((MEMORY_DEVICE, node.inode)),
+            link_count,
+            owner: Some(node.owner),
+        })
+    }
+
+    /// Follows `path` through symlinks to the path of a node that is not one.
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut current = path.to_path_buf();
+        for _ in 0..MAX_LINK_HOPS {
+            self.check(&current, FsOperation::Metadata)?;
+            let node = self.node(&current)?;
+            let Some(target) = &node.link_target else {
+                return Ok(current);
+            };
+            let joined = match current.parent() {
+                Some(parent) => parent.join(target),
+                None => target.clone(),
+            };
+            current = normalize(&joined);
+        }
+        Err(io::Error::other("too many levels of symbolic links"))
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        self.lock().metadata_of(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        let state = self.lock();
+        let resolved = state.resolve(path)?;
+        state.metadata_of(&resolved)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.read_dir_paced(path, &mut |_| {})
+    }
+
+    /// Children are stat'ed `STAT_BATCH` at a time, like `RealFileSystem` does.
+    fn read_dir_paced(
+        &self,
+        path: &Path,
+        before_stats: &mut dyn FnMut(u64),
+    ) -> io::Result<Vec<DirEntry>> {
+        let children: Vec<PathBuf> = {
+            let state = self.lock();
+            state.check(path, FsOperation::ReadDir)?;
+            if state.node(path)?.kind != FileKind::Directory {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotADirectory,
+                    "not a directory",
+                ));
+            }
+            state
+                .nodes
+                .keys()
+                .filter(|child| child.parent() == Some(path))
+                .cloned()
+                .collect()
+        };
+        let mut entries = Vec::with_capacity(children.len());
+        for batch in children.chunks(STAT_BATCH) {
+            before_stats(batch.len() as u64);
+            let state = self.lock();
+            entries.extend(batch.iter().map(|child| DirEntry {
+                path: child.clone(),
+                metadata: state.metadata_of(child),
+            }));
+        }
+        Ok(entries)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        let state = self.lock();
+        state.check(path, FsOperation::ReadLink)?;
+        state
+            .node(path)?
+            .link_target
+            .clone()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a symlink"))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.lock().resolve(&normalize(path))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn FileReader>> {
+        let state = self.lock();
+        state.check(path, FsOperation::Open)?;
+        let node = state.node(&state.resolve(path)?)?;
+        match node.kind {
+            FileKind::File => {}
+            FileKind::Directory => {
+                return Err(io::Error::new(
+                    io::ErrorKind::IsADirectory,
+                    "is a directory",
+                ));
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "not a regular file",
+                ));
+            }
+        }
+        Ok(match &node.contents {
+            Some(contents) => Box::new(Cursor::new(contents.clone())),
+            None => Box::new(Zeros {
+                len: node.len,
+                position: 0,
+            }),
+        })
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.lock();
+        state.check(path, FsOperation::Remove)?;
+        if state.node(path)?.kind == FileKind::Directory {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                "is a directory",
+            ));
+        }
+        state.nodes.remove(path);
+        state.touch_parent(path);
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.lock();
+        state.check(path, FsOperation::Remove)?;
+        state.node(path)?;
+        state.nodes.retain(|other, _| !other.starts_with(path));
+        state.touch_parent(path);
+        Ok(())
+    }
+}
+
+/// What `MemoryFileSystem::open` reads from a file added by length alone.
+#[derive(Debug)]
+struct Zeros {
+    len: u64,
+    position: u64,
+}
+
+impl Read for Zeros {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.len.saturating_sub(self.position).min(buf.len() as u64) as usize;
+        buf[..count].fill(0);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for Zeros {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.position)
+    }
+}
+
+/// Resolves `.` and `..` components lexically.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
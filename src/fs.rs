@@ -1,10 +1,297 @@
-use std::path::PathBuf;
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
+use crate::filesystem::FileSystem;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum FileKind {
     File,
     Directory,
+    /// Symbolic link; the target is carried in `FileEntry::link_target`.
+    Symlink,
+    Fifo,
+    Socket,
+    CharDevice,
+    BlockDevice,
+}
+
+impl FileKind {
+    /// Classifies `metadata` obtained without following symlinks.
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let file_type = metadata.file_type();
+        if file_type.is_dir() {
+            return FileKind::Directory;
+        }
+        if file_type.is_symlink() {
+            return FileKind::Symlink;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if file_type.is_fifo() {
+                return FileKind::Fifo;
+            }
+            if file_type.is_socket() {
+                return FileKind::Socket;
+            }
+            if file_type.is_char_device() {
+                return FileKind::CharDevice;
+            }
+            if file_type.is_block_device() {
+                return FileKind::BlockDevice;
+            }
+        }
+
+        FileKind::File
+    }
+
+    /// Type character `ls -l` puts before the permissions.
+    pub fn type_char(self) -> char {
+        match self {
+            FileKind::File => '-',
+            FileKind::Directory => 'd',
+            FileKind::Symlink => 'l',
+            FileKind::Fifo => 'p',
+            FileKind::Socket => 's',
+            FileKind::CharDevice => 'c',
+            FileKind::BlockDevice => 'b',
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Directory => "directory",
+            FileKind::Symlink => "symlink",
+            FileKind::Fifo => "fifo",
+            FileKind::Socket => "socket",
+            FileKind::CharDevice => "character device",
+            FileKind::BlockDevice => "block device",
+        }
+    }
+}
+
+/// Bytes read from a file whose extension does not reveal its type.
+const SNIFF_BYTES: usize = 512;
+
+/// Broad kind of content a regular file holds, for per-category breakdowns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FileCategory {
+    Video,
+    Image,
+    Audio,
+    Archive,
+    Document,
+    Code,
+    /// Executables, libraries and compiler output.
+    Binary,
+    Log,
+    DiskImage,
+    Other,
+}
+
+impl FileCategory {
+    pub const ALL: [FileCategory; 10] = [
+        FileCategory::Video,
+        FileCategory::Image,
+        FileCategory::Audio,
+        FileCategory::Archive,
+        FileCategory::Document,
+        FileCategory::Code,
+        FileCategory::Binary,
+        FileCategory::Log,
+        FileCategory::DiskImage,
+        FileCategory::Other,
+    ];
+
+    /// Classifies `path` by its extension, reading its first bytes from `filesystem` when the
+    /// extension is missing or unknown.
+    pub fn classify(path: &Path, filesystem: &dyn FileSystem) -> Self {
+        Self::from_extension(path)
+            .or_else(|| Self::sniff(path, filesystem))
+            .unwrap_or(FileCategory::Other)
+    }
+
+    /// Classifies `path` by its extension alone. Rotated logs such as `app.log.1` count as logs.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        let category =
+            match extension.as_str() {
+                "mp4" | "m4v" | "mkv" | "webm" | "avi" | "mov" | "wmv" | "flv" | "mpg" | "mpeg"
+                | "m2ts" | "mts" | "3gp" | "vob" | "ogv" => FileCategory::Video,
+                "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tif" | "tiff" | "webp" | "heic"
+                | "heif" | "avif" | "svg" | "ico" | "psd" | "cr2" | "cr3" | "nef" | "arw"
+                | "dng" | "raf" | "orf" | "xcf" => FileCategory::Image,
+                "mp3" | "flac" | "wav" | "ogg" | "oga" | "opus" | "m4a" | "aac" | "wma" | "aif"
+                | "aiff" | "mid" | "midi" | "ape" => FileCategory::Audio,
+                "zip" | "tar" | "gz" | "tgz" | "bz2" | "tbz2" | "xz" | "txz" | "zst" | "7z"
+                | "rar" | "lz4" | "lzma" | "jar" | "war" | "deb" | "rpm" | "apk" | "cab"
+                | "whl" | "crate" | "cpio" => FileCategory::Archive,
+                "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "odt" | "ods"
+                | "odp" | "rtf" | "txt" | "md" | "rst" | "tex" | "epub" | "csv" | "tsv" => {
+                    FileCategory::Document
+                }
+                "rs" | "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "hh" | "py" | "js" | "mjs"
+                | "cjs" | "ts" | "tsx" | "jsx" | "java" | "kt" | "kts" | "go" | "rb" | "php"
+                | "swift" | "cs" | "scala" | "sh" | "bash" | "zsh" | "fish" | "pl" | "lua"
+                | "r" | "m" | "mm" | "sql" | "html" | "htm" | "css" | "scss" | "sass" | "json"
+                | "yaml" | "yml" | "toml" | "xml" | "ini" | "cfg" | "gradle" | "cmake" | "mk"
+                | "vue" | "svelte" | "dart" | "ex" | "exs" | "erl" | "hs" | "ml" | "clj"
+                | "zig" | "nix" => FileCategory::Code,
+                "o" | "obj" | "a" | "so" | "dylib" | "dll" | "exe" | "lib" | "rlib" | "rmeta"
+                | "pyc" | "pyo" | "class" | "wasm" | "pdb" | "ko" | "bin" | "pch" | "gch"
+                | "dex" => FileCategory::Binary,
+                "log" => FileCategory::Log,
+                "iso" | "img" | "dmg" | "vmdk" | "vdi" | "qcow" | "qcow2" | "vhd" | "vhdx"
+                | "ova" | "hdd" => FileCategory::DiskImage,
+                _ if extension.bytes().all(|byte| byte.is_ascii_digit()) => {
+                    let stem = Path::new(path.file_stem()?);
+                    return (stem.extension()? == "log").then_some(FileCategory::Log);
+                }
+                _ => return None,
+            };
+        Some(category)
+    }
+
+    /// Classifies `path` by the magic bytes at its start.
+    pub fn sniff(path: &Path, filesystem: &dyn FileSystem) -> Option<Self> {
+        let head = filesystem.read_head(path, SNIFF_BYTES).ok()?;
+        Self::from_magic(&head)
+    }
+
+    fn from_magic(head: &[u8]) -> Option<Self> {
+        let at =
+            |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+        let category = if at(0, b"\x7fELF")
+            || at(0, b"MZ")
+            || at(0, &[0xcf, 0xfa, 0xed, 0xfe])
+            || at(0, &[0xce, 0xfa, 0xed, 0xfe])
+            || at(0, &[0xca, 0xfe, 0xba, 0xbe])
+            || at(0, b"\0asm")
+            || at(0, b"!<arch>\n")
+        {
+            FileCategory::Binary
+        } else if at(0, b"\x89PNG")
+            || at(0, &[0xff, 0xd8, 0xff])
+            || at(0, b"GIF8")
+            || (at(0, b"RIFF") && at(8, b"WEBP"))
+        {
+            FileCategory::Image
+        } else if at(4, b"ftyp")
+            || at(0, &[0x1a, 0x45, 0xdf, 0xa3])
+            || (at(0, b"RIFF") && at(8, b"AVI "))
+        {
+            FileCategory::Video
+        } else if at(0, b"ID3")
+            || at(0, b"fLaC")
+            || at(0, b"OggS")
+            || (at(0, b"RIFF") && at(8, b"WAVE"))
+        {
+            FileCategory::Audio
+        } else if at(0, b"PK\x03\x04")
+            || at(0, &[0x1f, 0x8b])
+            || at(0, &[0xfd, b'7', b'z', b'X', b'Z', 0x00])
+            || at(0, &[0x28, 0xb5, 0x2f, 0xfd])
+            || at(0, &[b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c])
+            || at(0, b"BZh")
+            || at(0, b"Rar!")
+            || at(257, b"ustar")
+        {
+            FileCategory::Archive
+        } else if at(0, b"%PDF") {
+            FileCategory::Document
+        } else if at(0, &[b'Q', b'F', b'I', 0xfb]) || at(0, b"KDMV") || at(0, b"conectix") {
+            FileCategory::DiskImage
+        } else if at(0, b"#!") {
+            FileCategory::Code
+        } else {
+            return None;
+        };
+        Some(category)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FileCategory::Video => "Video",
+            FileCategory::Image => "Images",
+            FileCategory::Audio => "Audio",
+            FileCategory::Archive => "Archives",
+            FileCategory::Document => "Documents",
+            FileCategory::Code => "Code",
+            FileCategory::Binary => "Binaries & objects",
+            FileCategory::Log => "Logs",
+            FileCategory::DiskImage => "Disk images",
+            FileCategory::Other => "Other",
+        }
+    }
+}
+
+/// Files whose allocation differs from their length by less than this are not flagged, so that
+/// block rounding and small tails never count as sparse or preallocated.
+const ALLOCATION_SLACK: u64 = 1024 * 1024;
+
+/// How a regular file's allocated blocks compare to its length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Allocation {
+    /// Less than half of the length is backed by blocks; the rest are holes. Transparently
+    /// compressed files look the same.
+    Sparse,
+    /// More blocks are reserved than the length needs, as with `fallocate` or torrent clients.
+    Preallocated,
+}
+
+impl Allocation {
+    /// Compares a file's apparent `len` with the bytes `allocated` to it.
+    pub fn detect(len: u64, allocated: u64) -> Option<Self> {
+        if allocated.saturating_add(ALLOCATION_SLACK) < len && allocated < len / 2 {
+            Some(Allocation::Sparse)
+        } else if allocated > len.saturating_add(ALLOCATION_SLACK) {
+            Some(Allocation::Preallocated)
+        } else {
+            None
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Allocation::Sparse => "sparse",
+            Allocation::Preallocated => "preallocated",
+        }
+    }
+}
+
+/// Owner, group and permission bits of an entry, as `stat` reports them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ownership {
+    pub uid: u32,
+    pub gid: u32,
+    /// Permission bits including setuid, setgid and sticky (`st_mode & 0o7777`).
+    pub mode: u32,
+}
+
+impl Ownership {
+    /// Permissions as `ls -l` shows them after the type character, e.g. `rwxr-s--T`.
+    pub fn mode_string(&self) -> String {
+        const SPECIAL: [(u32, char, char); 3] =
+            [(0o4000, 's', 'S'), (0o2000, 's', 'S'), (0o1000, 't', 'T')];
+        let mut text = String::with_capacity(9);
+        for (class, (special_bit, with_exec, without_exec)) in SPECIAL.iter().enumerate() {
+            let bits = (self.mode >> (6 - 3 * class)) & 0o7;
+            text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+            text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+            let exec = bits & 0o1 != 0;
+            text.push(match (self.mode & special_bit != 0, exec) {
+                (true, true) => *with_exec,
+                (true, false) => *without_exec,
+                (false, true) => 'x',
+                (false, false) => '-',
+            });
+        }
+        text
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -13,8 +300,89 @@ pub struct FileEntry {
     pub file_name: String,
     pub kind: FileKind,
     pub direct_size: u64,
+    pub allocated_size: u64,
     pub modified: Option<SystemTime>,
     pub created: Option<SystemTime>,
+    /// Another hard link to the same inode was already counted in this scan.
+    pub hardlink_duplicate: bool,
+    /// Directory on another filesystem that a one-filesystem scan did not descend into.
+    pub mount_point: bool,
+    /// Where a `FileKind::Symlink` points, as stored in the link.
+    pub link_target: Option<PathBuf>,
+    /// Symlink whose target does not exist.
+    pub dangling_link: bool,
+    /// Directory excluded by ignore rules; it was not walked and its size is unknown.
+    pub excluded: bool,
+    /// Directory below a depth-limited scan's cut-off; its contents were not walked.
+    pub unscanned: bool,
+    /// Totals recorded for an unscanned directory by an earlier scan, if there was one.
+    pub estimate: Option<SizeEstimate>,
+    /// Content category of a regular file; `None` for everything else.
+    pub category: Option<FileCategory>,
+    /// Member of an archive, listed below it without existing on disk. Its size is the
+    /// uncompressed length and its allocated size the bytes it takes up inside the archive.
+    pub in_archive: bool,
+    /// Bytes an archive member takes up inside the archive, where the format records them.
+    pub compressed_size: Option<u64>,
+    /// Regular file whose allocated size is far from its length.
+    pub allocation: Option<Allocation>,
+    /// Owner and permissions; `None` on platforms without them and for archive members.
+    pub owner: Option<Ownership>,
+}
+
+/// Aggregate sizes taken from the cache instead of the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SizeEstimate {
+    pub size: u64,
+    pub allocated: u64,
+}
+
+/// Why a path could not be scanned, or its results could not be cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ScanErrorKind {
+    PermissionDenied,
+    /// The path disappeared between being listed and being read.
+    Vanished,
+    Io,
+    PathTooLong,
+    Cache,
+}
+
+impl ScanErrorKind {
+    pub const ALL: [ScanErrorKind; 5] = [
+        ScanErrorKind::PermissionDenied,
+        ScanErrorKind::Vanished,
+        ScanErrorKind::Io,
+        ScanErrorKind::PathTooLong,
+        ScanErrorKind::Cache,
+    ];
+
+    /// Classifies a filesystem error; anything unrecognised is a plain I/O error.
+    pub fn from_io(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => ScanErrorKind::PermissionDenied,
+            io::ErrorKind::NotFound => ScanErrorKind::Vanished,
+            io::ErrorKind::InvalidFilename => ScanErrorKind::PathTooLong,
+            _ => ScanErrorKind::Io,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ScanErrorKind::PermissionDenied => "permission denied",
+            ScanErrorKind::Vanished => "vanished during scan",
+            ScanErrorKind::Io => "I/O error",
+            ScanErrorKind::PathTooLong => "path too long",
+            ScanErrorKind::Cache => "cache failure",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub kind: ScanErrorKind,
+    pub message: String,
 }
 
 impl FileEntry {
@@ -24,6 +392,7 @@ impl FileEntry {
         file_name: String,
         kind: FileKind,
         direct_size: u64,
+        allocated_size: u64,
         modified: Option<SystemTime>,
         created: Option<SystemTime>,
     ) -> Self {
@@ -32,8 +401,86 @@ impl FileEntry {
             file_name,
             kind,
             direct_size,
+            allocated_size,
             modified,
             created,
+            hardlink_duplicate: false,
+            mount_point: false,
+            link_target: None,
+            dangling_link: false,
+            excluded: false,
+            unscanned: false,
+            estimate: None,
+            category: None,
+            in_archive: false,
+            compressed_size: None,
+            allocation: None,
+            owner: None,
         }
     }
 }
+
+/// Bytes actually allocated on disk for `metadata`, as reported by `du`. Platforms without block
+/// counts fall back to the apparent length.
+#[cfg(unix)]
+pub fn allocated_size(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+pub fn allocated_size(metadata: &Metadata) -> u64 {
+    metadata.len()
+}
+
+/// When the inode behind `metadata` last changed (`st_ctime`), with nanosecond precision.
+#[cfg(unix)]
+pub fn changed_time(metadata: &Metadata) -> Option<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+    let secs = u64::try_from(metadata.ctime()).ok()?;
+    let nanos = u32::try_from(metadata.ctime_nsec()).ok()?;
+    Some(SystemTime::UNIX_EPOCH + std::time::Duration::new(secs, nanos))
+}
+
+#[cfg(not(unix))]
+pub fn changed_time(_metadata: &Metadata) -> Option<SystemTime> {
+    None
+}
+
+#[cfg(unix)]
+pub fn ownership(metadata: &Metadata) -> Option<Ownership> {
+    use std::os::unix::fs::MetadataExt;
+    Some(Ownership {
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        mode: metadata.mode() & 0o7777,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn ownership(_metadata: &Metadata) -> Option<Ownership> {
+    None
+}
+
+/// `(device, inode)` pair identifying the underlying file, where the platform exposes one.
+#[cfg(unix)]
+pub fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+pub fn link_count(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+pub fn link_count(_metadata: &Metadata) -> u64 {
+    1
+}
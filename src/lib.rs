@@ -1,8 +1,15 @@
+pub mod accounts;
 pub mod app;
+pub mod archive;
 pub mod cache;
+pub mod duplicates;
+pub mod exclude;
+pub mod filesystem;
 pub mod fs;
 pub mod query;
 pub mod scanner;
+pub mod throttle;
 pub mod tree;
 pub mod util;
+pub mod walker;
 pub mod watcher;
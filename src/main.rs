@@ -2,7 +2,7 @@ use std::path::PathBuf;
 use std::process;
 use std::time::Duration;
 
-use disk_space_inspect::{app, cache, watcher};
+use disk_space_inspect::{app, cache, scanner, throttle, watcher};
 use eframe::{NativeOptions, egui};
 use env_logger::Env;
 use pico_args::Arguments;
@@ -39,7 +39,37 @@ fn main() -> eframe::Result<()> {
         }
     };
 
+    let scan_threads = match args.opt_value_from_str::<_, usize>("--scan-threads") {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("dusk: {err}");
+            process::exit(1);
+        }
+    };
+
+    let max_ops = match args.opt_value_from_str::<_, u32>("--max-ops") {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("dusk: {err}");
+            process::exit(1);
+        }
+    };
+
+    let max_depth = match args.opt_value_from_str::<_, usize>("--max-depth") {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("dusk: {err}");
+            process::exit(1);
+        }
+    };
+
     let watch_enabled = args.contains("--watch");
+    let count_links = args.contains(["-l", "--count-links"]);
+    let one_file_system = args.contains(["-x", "--one-file-system"]);
+    let follow_links = args.contains(["-L", "--follow-links"]);
+    let respect_gitignore = args.contains("--gitignore");
+    let expand_archives = args.contains("--archives");
+    let low_impact = args.contains("--low-impact") || max_ops.is_some();
 
     if let Some(raw) = clear_target {
         if let Err(err) = clear_cache_for_root(&raw) {
@@ -66,13 +96,34 @@ fn main() -> eframe::Result<()> {
         watcher_config.fallback_initial = watcher_config.fallback_max;
     }
 
-    let cwd_arg: Option<String> = match args.opt_free_from_str() {
-        Ok(value) => value,
-        Err(err) => {
-            eprintln!("dusk: {err}");
-            process::exit(1);
+    let mut scanner_config = scanner::ScannerConfig::default();
+    if let Some(threads) = scan_threads {
+        scanner_config.walker_threads = threads.max(1);
+    }
+    scanner_config.count_hard_links = count_links;
+    scanner_config.one_file_system = one_file_system;
+    scanner_config.follow_symlinks = follow_links;
+    scanner_config.respect_gitignore = respect_gitignore;
+    scanner_config.expand_archives = expand_archives;
+    if low_impact {
+        let mut settings = throttle::LowImpact::default();
+        if let Some(ops) = max_ops {
+            settings.max_ops_per_sec = ops.max(1);
         }
-    };
+        scanner_config.low_impact = Some(settings);
+    }
+
+    let mut root_args: Vec<String> = Vec::new();
+    loop {
+        match args.opt_free_from_str() {
+            Ok(Some(value)) => root_args.push(value),
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("dusk: {err}");
+                process::exit(1);
+            }
+        }
+    }
 
     let leftover = args.finish();
     if !leftover.is_empty() {
@@ -84,14 +135,31 @@ fn main() -> eframe::Result<()> {
         process::exit(1);
     }
 
-    if let Err(err) = configure_working_directory(cwd_arg) {
-        eprintln!("dusk: {err}");
+    let roots = match root_args
+        .iter()
+        .map(|raw| resolve_root(raw))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(roots) => outermost_roots(roots),
+        Err(err) => {
+            eprintln!("dusk: {err}");
+            process::exit(1);
+        }
+    };
+
+    if let Some(first) = roots.first()
+        && let Err(err) = std::env::set_current_dir(first)
+    {
+        eprintln!("dusk: failed to enter {}: {err}", first.display());
         process::exit(1);
     }
 
     let app_config = app::AppConfig {
         enable_watchers: watch_enabled,
         watcher_config,
+        scanner_config,
+        max_depth: max_depth.map(|depth| depth.max(1)),
+        roots: if roots.len() > 1 { roots } else { Vec::new() },
     };
 
     let native_options = NativeOptions {
@@ -106,12 +174,8 @@ fn main() -> eframe::Result<()> {
     )
 }
 
-fn configure_working_directory(cwd_arg: Option<String>) -> Result<(), String> {
-    let Some(raw_path) = cwd_arg else {
-        return Ok(());
-    };
-
-    let expanded = full(raw_path.as_str()).map_err(|err| err.to_string())?;
+fn resolve_root(raw_path: &str) -> Result<PathBuf, String> {
+    let expanded = full(raw_path).map_err(|err| err.to_string())?;
     let path = PathBuf::from(expanded.as_ref());
     if !path.exists() {
         return Err(format!("{} does not exist", path.display()));
@@ -120,14 +184,21 @@ fn configure_working_directory(cwd_arg: Option<String>) -> Result<(), String> {
         return Err(format!("{} is not a directory", path.display()));
     }
 
-    let canonical = path
-        .canonicalize()
-        .map_err(|err| format!("failed to canonicalize {}: {err}", path.display()))?;
-
-    std::env::set_current_dir(&canonical)
-        .map_err(|err| format!("failed to enter {}: {err}", canonical.display()))?;
+    path.canonicalize()
+        .map_err(|err| format!("failed to canonicalize {}: {err}", path.display()))
+}
 
-    Ok(())
+/// Drops roots that lie inside another root, keeping the first-given order otherwise.
+fn outermost_roots(roots: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut kept: Vec<PathBuf> = Vec::new();
+    for root in roots {
+        if kept.iter().any(|outer| root.starts_with(outer)) {
+            continue;
+        }
+        kept.retain(|inner| !inner.starts_with(&root));
+        kept.push(root);
+    }
+    kept
 }
 
 fn clear_cache_for_root(raw: &str) -> Result<(), String> {
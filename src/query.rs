@@ -3,6 +3,8 @@ use std::path::{Component, MAIN_SEPARATOR, Path, PathBuf};
 
 use shellexpand::tilde;
 
+use crate::fs::Allocation;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SizeOperator {
     GreaterThan,
@@ -34,6 +36,11 @@ pub struct SearchQuery {
     pub root: PathBuf,
     pub relative_pattern: Option<String>,
     pub size_filter: Option<SizeFilter>,
+    /// Only files with this allocation anomaly (`is:sparse`, `is:preallocated`) are listed.
+    pub allocation_filter: Option<Allocation>,
+    /// Directories this many levels below the root are not walked; their totals come from the
+    /// cache. `None` walks everything.
+    pub max_depth: Option<usize>,
 }
 
 impl Default for SearchQuery {
@@ -44,6 +51,8 @@ impl Default for SearchQuery {
             root,
             relative_pattern: None,
             size_filter: None,
+            allocation_filter: None,
+            max_depth: None,
         }
     }
 }
@@ -59,13 +68,14 @@ pub fn parse_input(input: &str) -> SearchQuery {
     let mut tokens = trimmed.split_whitespace().peekable();
     let mut pattern: Option<String> = None;
     let mut size_filter: Option<SizeFilter> = None;
+    let mut allocation_filter: Option<Allocation> = None;
 
     while let Some(token) = tokens.next() {
         if is_comparison_prefix(token) {
-            if let Some(next) = tokens.next() {
-                if let Some(filter) = parse_size_filter_parts(token, next) {
-                    size_filter = Some(filter);
-                }
+            if let Some(next) = tokens.next()
+                && let Some(filter) = parse_size_filter_parts(token, next)
+            {
+                size_filter = Some(filter);
             }
             continue;
         }
@@ -75,6 +85,11 @@ pub fn parse_input(input: &str) -> SearchQuery {
             continue;
         }
 
+        if let Some(filter) = parse_allocation_filter(token) {
+            allocation_filter = Some(filter);
+            continue;
+        }
+
         if pattern.is_none() {
             pattern = Some(token.to_string());
             continue;
@@ -92,6 +107,7 @@ pub fn parse_input(input: &str) -> SearchQuery {
     }
 
     query.size_filter = size_filter;
+    query.allocation_filter = allocation_filter;
     query
 }
 
@@ -182,6 +198,14 @@ fn is_comparison_prefix(token: &str) -> bool {
     matches!(token, ">" | "<" | ">=" | "<=")
 }
 
+fn parse_allocation_filter(token: &str) -> Option<Allocation> {
+    match token.to_ascii_lowercase().as_str() {
+        "is:sparse" => Some(Allocation::Sparse),
+        "is:preallocated" | "is:prealloc" => Some(Allocation::Preallocated),
+        _ => None,
+    }
+}
+
 fn parse_size_filter(token: &str) -> Option<SizeFilter> {
     if token.len() < 2 {
         return None;
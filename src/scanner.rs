@@ -1,16 +1,79 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use crossbeam_channel::{Receiver, Sender, unbounded};
+use crossbeam_channel::{Receiver, Sender, bounded, unbounded};
 use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 use rusqlite::Error as SqliteError;
-use walkdir::WalkDir;
 
-use crate::cache::{self, AggregateSummary, Cache, CacheValidationError};
-use crate::fs::{FileEntry, FileKind};
+use crate::archive::{self, ArchiveFormat};
+use crate::cache::{self, AggregateSummary, Cache, CacheValidationError, CachedEntry};
+use crate::duplicates::{self, DuplicateGroup, DuplicateStats, LinkReport};
+use crate::exclude::ExcludeRules;
+use crate::filesystem::{FileSystem, FsMetadata, RealFileSystem};
+use crate::fs::{
+    Allocation, FileCategory, FileEntry, FileKind, Ownership, ScanError, ScanErrorKind,
+    SizeEstimate,
+};
 use crate::query::{SearchQuery, SizeFilter};
+use crate::throttle::{self, LowImpact, Throttle, ThrottledFileSystem};
+use crate::tree::{SizeMetric, TreeStore};
+use crate::walker::ParallelWalker;
+
+#[derive(Debug, Clone)]
+pub struct ScannerConfig {
+    /// Number of threads reading directories ahead of the scan loop.
+    pub walker_threads: usize,
+    /// Count every hard link's bytes instead of each inode once (`du --count-links`).
+    pub count_hard_links: bool,
+    /// Do not descend into directories on other filesystems (`du -x`).
+    pub one_file_system: bool,
+    /// Follow symlinks to files and directories outside the root (`du -L`).
+    pub follow_symlinks: bool,
+    /// Honour `.gitignore` files in addition to `.duskignore`.
+    pub respect_gitignore: bool,
+    /// List the members of zip and tar archives as virtual children of the archive.
+    pub expand_archives: bool,
+    /// Maximum number of entries per `ScanMessage::Entries` batch.
+    pub batch_size: usize,
+    /// A partial batch is sent once it has been pending this long.
+    pub flush_interval: Duration,
+    /// Messages buffered before the scanner blocks waiting for the receiver to catch up.
+    pub channel_capacity: usize,
+    /// How often `ScanMessage::Progress` is sent while a scan runs.
+    pub progress_interval: Duration,
+    /// Filesystem scans walk and deletions go through; `MemoryFileSystem` in tests.
+    pub filesystem: Arc<dyn FileSystem>,
+    /// Cap the scan's metadata rate and lower its priority so other users of the disk are not
+    /// slowed down.
+    pub low_impact: Option<LowImpact>,
+}
+
+impl Default for ScannerConfig {
+    fn default() -> Self {
+        let walker_threads = thread::available_parallelism()
+            .map(|count| count.get().min(8))
+            .unwrap_or(4);
+        Self {
+            walker_threads,
+            count_hard_links: false,
+            one_file_system: false,
+            follow_symlinks: false,
+            respect_gitignore: false,
+            expand_archives: false,
+            batch_size: 512,
+            flush_interval: Duration::from_millis(50),
+            channel_capacity: 64,
+            progress_interval: Duration::from_millis(250),
+            filesystem: Arc::new(RealFileSystem),
+            low_impact: None,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct CacheContext {
@@ -19,39 +82,310 @@ pub struct CacheContext {
     pub canonical_root: PathBuf,
 }
 
+/// One root of a scan job covering several roots; each keeps its own cache root row.
+#[derive(Clone)]
+pub struct RootScan {
+    pub query: SearchQuery,
+    pub cache: Option<CacheContext>,
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 pub struct ScanStats {
     pub files_scanned: u64,
     pub dirs_scanned: u64,
+    /// Symlinks, FIFOs, sockets and device nodes.
+    pub special_files_scanned: u64,
     pub cached_dirs: u64,
     pub cached_entries: u64,
     pub cached_bytes: u64,
     pub fs_errors: u64,
     pub cache_validation_errors: u64,
+    pub hardlink_duplicates: u64,
+    pub mount_points_skipped: u64,
+    pub symlinks_followed: u64,
+    pub excluded_dirs: u64,
+    /// Directories past `SearchQuery::max_depth`, reported with cached totals instead of walked.
+    pub dirs_estimated: u64,
+    /// Archives whose members were read from the archive itself.
+    pub archives_listed: u64,
+    /// Archives whose members came from a cached listing.
+    pub archives_cached: u64,
+}
+
+impl ScanStats {
+    fn accumulate(&mut self, other: &ScanStats) {
+        self.files_scanned += other.files_scanned;
+        self.dirs_scanned += other.dirs_scanned;
+        self.special_files_scanned += other.special_files_scanned;
+        self.cached_dirs += other.cached_dirs;
+        self.cached_entries += other.cached_entries;
+        self.cached_bytes += other.cached_bytes;
+        self.fs_errors += other.fs_errors;
+        self.cache_validation_errors += other.cache_validation_errors;
+        self.hardlink_duplicates += other.hardlink_duplicates;
+        self.mount_points_skipped += other.mount_points_skipped;
+        self.symlinks_followed += other.symlinks_followed;
+        self.excluded_dirs += other.excluded_dirs;
+        self.dirs_estimated += other.dirs_estimated;
+        self.archives_listed += other.archives_listed;
+        self.archives_cached += other.archives_cached;
+    }
+}
+
+/// Snapshot of a running scan, sent every `ScannerConfig::progress_interval`.
+#[derive(Debug, Clone)]
+pub struct ScanProgress {
+    pub current_dir: PathBuf,
+    /// Non-directory entries walked or replayed from cache so far.
+    pub files: u64,
+    /// Bytes counted towards the root's total so far.
+    pub bytes: u64,
+    pub entries: u64,
+    pub entries_per_sec: f64,
+    /// Share of the previous scan's root total reached, when the cache has one.
+    pub fraction: Option<f32>,
+    pub eta: Option<Duration>,
+    /// Metadata operations per second a low-impact scan currently allows; below
+    /// `LowImpact::max_ops_per_sec` while it backs off.
+    pub throttled_rate: Option<f64>,
 }
 
 #[derive(Debug, Default, Clone)]
 struct DirectoryFrame {
+    path: PathBuf,
     relative: PathBuf,
     parent: Option<PathBuf>,
     direct_size: u64,
     aggregate_size: u64,
+    allocated_size: u64,
+    aggregate_allocated: u64,
     modified: Option<i64>,
     created: Option<i64>,
+    modified_ns: Option<i64>,
+    changed_ns: Option<i64>,
+    file_id: Option<(u64, u64)>,
+    owner: Option<Ownership>,
+    flags: i64,
+    link_target: Option<PathBuf>,
+    ignore_key: u64,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
 struct EmitStats {
     aggregate_size: u64,
+    aggregate_allocated: u64,
     entries: usize,
     directories: usize,
     files: usize,
+    hardlink_duplicates: usize,
+    mount_points: usize,
+    excluded: usize,
+    estimated: usize,
+    archives_listed: usize,
+    archives_cached: usize,
+}
+
+/// Remembers which multiply-linked inodes were already counted during a scan.
+#[derive(Debug, Default)]
+struct LinkTracker {
+    count_all: bool,
+    seen: HashSet<(u64, u64)>,
+    journal: Vec<(u64, u64)>,
+}
+
+impl LinkTracker {
+    fn new(count_all: bool) -> Self {
+        Self {
+            count_all,
+            ..Self::default()
+        }
+    }
+
+    fn is_duplicate(&mut self, key: Option<(u64, u64)>) -> bool {
+        let Some(key) = key else {
+            return false;
+        };
+        if self.count_all {
+            return false;
+        }
+        if self.seen.insert(key) {
+            self.journal.push(key);
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Records a directory reached through a followed symlink; true when it was already walked.
+    fn is_revisit(&mut self, key: Option<(u64, u64)>) -> bool {
+        let Some(key) = key else {
+            return false;
+        };
+        if self.seen.insert(key) {
+            self.journal.push(key);
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Marks inodes as already counted elsewhere, for subtree rescans that do not walk the rest of
+    /// the root.
+    fn seed(&mut self, keys: impl IntoIterator<Item = (u64, u64)>) {
+        self.seen.extend(keys);
+    }
+
+    /// Starts recording inodes so a failed cache replay can forget them again.
+    fn checkpoint(&mut self) {
+        self.journal.clear();
+    }
+
+    fn rollback(&mut self) {
+        for key in self.journal.drain(..) {
+            self.seen.remove(&key);
+        }
+    }
+}
+
+/// Collects scanned entries into `ScanEvent::Entries` batches.
+struct EntryBatcher<'a> {
+    pending: Vec<FileEntry>,
+    batch_size: usize,
+    flush_interval: Duration,
+    last_flush: Instant,
+    events: &'a Sender<ScanEvent>,
+}
+
+impl<'a> EntryBatcher<'a> {
+    fn new(config: &ScannerConfig, events: &'a Sender<ScanEvent>) -> Self {
+        let batch_size = config.batch_size.max(1);
+        Self {
+            pending: Vec::with_capacity(batch_size),
+            batch_size,
+            flush_interval: config.flush_interval,
+            last_flush: Instant::now(),
+            events,
+        }
+    }
+
+    fn push(&mut self, entry: FileEntry) {
+        self.pending.push(entry);
+        if self.pending.len() >= self.batch_size || self.last_flush.elapsed() >= self.flush_interval
+        {
+            self.flush();
+        }
+    }
+
+    /// Sends pending entries; blocks while the bounded channel is full.
+    fn flush(&mut self) {
+        self.last_flush = Instant::now();
+        if self.pending.is_empty() {
+            return;
+        }
+        let entries = std::mem::replace(&mut self.pending, Vec::with_capacity(self.batch_size));
+        let _ = self.events.send(ScanEvent::Entries(entries));
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct ProgressCounts {
+    files: u64,
+    bytes: u64,
+    entries: u64,
+}
+
+/// Accumulates scan progress and periodically reports it as `ScanEvent::Progress`.
+struct ProgressTracker<'a> {
+    interval: Duration,
+    started: Instant,
+    last_emit: Instant,
+    /// Root total recorded by the previous scan, used to estimate completion.
+    expected_total: Option<u64>,
+    counts: ProgressCounts,
+    throttle: Option<&'a Throttle>,
+    events: &'a Sender<ScanEvent>,
+}
+
+impl<'a> ProgressTracker<'a> {
+    fn new(
+        config: &ScannerConfig,
+        expected_total: Option<u64>,
+        throttle: Option<&'a Throttle>,
+        events: &'a Sender<ScanEvent>,
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            interval: config.progress_interval,
+            started: now,
+            last_emit: now,
+            expected_total: expected_total.filter(|&total| total > 0),
+            counts: ProgressCounts::default(),
+            throttle,
+            events,
+        }
+    }
+
+    /// Keeps time spent paused out of the throughput and ETA figures.
+    fn exclude_pause(&mut self, paused: Duration) {
+        self.started += paused;
+    }
+
+    fn record(&mut self, kind: FileKind, counted_size: u64) {
+        self.counts.entries += 1;
+        if kind != FileKind::Directory {
+            self.counts.files += 1;
+            self.counts.bytes += counted_size;
+        }
+    }
+
+    /// Counts a directory that was not walked, with the bytes the cache expects below it.
+    fn record_estimate(&mut self, estimated_size: u64) {
+        self.counts.entries += 1;
+        self.counts.bytes += estimated_size;
+    }
+
+    fn tick(&mut self, current_dir: &Path) {
+        if self.last_emit.elapsed() >= self.interval {
+            self.emit(current_dir);
+        }
+    }
+
+    fn emit(&mut self, current_dir: &Path) {
+        self.last_emit = Instant::now();
+
+        let elapsed = self.started.elapsed();
+        let ProgressCounts {
+            files,
+            bytes,
+            entries,
+        } = self.counts;
+        let entries_per_sec = entries as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        let fraction = self
+            .expected_total
+            .map(|total| (bytes as f64 / total as f64).min(1.0));
+        let eta = fraction
+            .filter(|&fraction| fraction > 0.0)
+            .map(|fraction| elapsed.mul_f64((1.0 - fraction) / fraction));
+
+        let _ = self.events.send(ScanEvent::Progress(ScanProgress {
+            current_dir: current_dir.to_path_buf(),
+            files,
+            bytes,
+            entries,
+            entries_per_sec,
+            fraction: fraction.map(|fraction| fraction as f32),
+            eta,
+            throttled_rate: self.throttle.map(Throttle::rate),
+        }));
+    }
 }
 
 #[derive(Debug)]
 enum CachedReplayError {
     Cache(CacheValidationError),
     Storage(SqliteError),
+    /// A newer job was requested or this one was cancelled.
+    Superseded,
 }
 
 impl From<CacheValidationError> for CachedReplayError {
@@ -68,12 +402,77 @@ impl From<SqliteError> for CachedReplayError {
 
 pub struct ScannerHandle {
     cmd_tx: Sender<ScanCommand>,
-    job_counter: Arc<AtomicU64>,
+    jobs: Arc<JobControl>,
+    pause: Arc<PauseControl>,
+}
+
+/// Hands out job ids and tells a running job when to stop: once a newer job was requested or it
+/// was cancelled. Ids only grow, so the newest cancelled id covers every older job too.
+#[derive(Default)]
+struct JobControl {
+    counter: AtomicU64,
+    cancelled: AtomicU64,
+}
+
+impl JobControl {
+    fn next_id(&self) -> u64 {
+        self.counter.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// True while `job_id` is the newest job and has not been cancelled.
+    fn is_current(&self, job_id: u64) -> bool {
+        self.counter.load(Ordering::SeqCst) == job_id
+            && self.cancelled.load(Ordering::SeqCst) < job_id
+    }
+
+    fn cancel(&self, job_id: u64) {
+        self.cancelled.fetch_max(job_id, Ordering::SeqCst);
+    }
+}
+
+/// Shared between the handle and the worker so a running scan can be suspended without going
+/// through the command queue, which is only read between jobs.
+#[derive(Default)]
+struct PauseControl {
+    /// Job that should be suspended; 0 when none is.
+    paused_job: AtomicU64,
+    lock: Mutex<()>,
+    changed: Condvar,
+}
+
+impl PauseControl {
+    fn is_paused(&self, job_id: u64) -> bool {
+        self.paused_job.load(Ordering::SeqCst) == job_id
+    }
+
+    fn set(&self, paused_job: u64) {
+        let _guard = self.lock.lock().unwrap_or_else(|err| err.into_inner());
+        self.paused_job.store(paused_job, Ordering::SeqCst);
+        self.changed.notify_all();
+    }
+
+    /// Wakes a suspended scan so it notices it was superseded.
+    fn notify(&self) {
+        let _guard = self.lock.lock().unwrap_or_else(|err| err.into_inner());
+        self.changed.notify_all();
+    }
+
+    /// Blocks while `job_id` is paused and still the current job.
+    fn wait_while_paused(&self, job_id: u64, jobs: &JobControl) {
+        let mut guard = self.lock.lock().unwrap_or_else(|err| err.into_inner());
+        while self.is_paused(job_id) && jobs.is_current(job_id) {
+            guard = match self.changed.wait_timeout(guard, Duration::from_millis(100)) {
+                Ok((guard, _)) => guard,
+                Err(err) => err.into_inner().0,
+            };
+        }
+    }
 }
 
 impl ScannerHandle {
     pub fn request_scan(&self, query: SearchQuery, cache: Option<CacheContext>) -> u64 {
-        let job_id = self.job_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let job_id = self.jobs.next_id();
+        self.pause.notify();
         let _ = self.cmd_tx.send(ScanCommand::Run {
             job_id,
             query,
@@ -82,11 +481,85 @@ impl ScannerHandle {
         job_id
     }
 
+    /// Scans several roots one after another as a single job, announced by
+    /// `ScanMessage::BeginRoots`. Progress is reported per root; stats are combined.
+    pub fn request_scan_roots(&self, scans: Vec<RootScan>) -> u64 {
+        let job_id = self.jobs.next_id();
+        self.pause.notify();
+        let _ = self.cmd_tx.send(ScanCommand::RunRoots { job_id, scans });
+        job_id
+    }
+
     pub fn request_cache_clear(&self, ctx: CacheContext) -> u64 {
-        let job_id = self.job_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let job_id = self.jobs.next_id();
+        self.pause.notify();
         let _ = self.cmd_tx.send(ScanCommand::ClearCache { job_id, ctx });
         job_id
     }
+
+    /// Walks `path` (a directory below `query.root`) again as part of scan `job_id`, updating only
+    /// that part of the cache and the ancestors' totals. Used to fill in directories a
+    /// depth-limited scan left unscanned and to pick up changes without a full rescan. It is
+    /// dropped if a newer scan was requested meanwhile, and is bracketed by
+    /// `ScanMessage::SubtreeBegin` and `ScanMessage::SubtreeComplete`.
+    pub fn request_subtree_rescan(
+        &self,
+        job_id: u64,
+        query: SearchQuery,
+        path: PathBuf,
+        cache: Option<CacheContext>,
+    ) {
+        let _ = self.cmd_tx.send(ScanCommand::RescanSubtree {
+            job_id,
+            query,
+            path,
+            cache,
+        });
+    }
+
+    /// Searches the cached files of `roots` for duplicates of at least `min_size` bytes as part of
+    /// scan `job_id`, answering with `ScanMessage::Duplicates`. Like subtree rescans it is
+    /// dropped, or abandoned halfway, once a newer scan is requested.
+    pub fn request_duplicates(&self, job_id: u64, roots: Vec<CacheContext>, min_size: u64) {
+        let _ = self.cmd_tx.send(ScanCommand::FindDuplicates {
+            job_id,
+            roots,
+            min_size,
+        });
+    }
+
+    /// Replaces identical copies among `paths` with links (see `duplicates::link_identical`),
+    /// writing the undo record to `undo_dir`, and answers with `ScanMessage::Linked`. Unlike
+    /// searches it runs even once a newer scan is requested, since the files are the user's pick.
+    pub fn request_link(&self, job_id: u64, paths: Vec<PathBuf>, undo_dir: PathBuf) {
+        let _ = self.cmd_tx.send(ScanCommand::LinkIdentical {
+            job_id,
+            paths,
+            undo_dir,
+        });
+    }
+
+    /// Stops job `job_id` at its next entry, also halfway through replaying a cached subtree or
+    /// checking the cache afterwards, and drops its queued subtree rescans and duplicate
+    /// searches. The scanner answers with `ScanMessage::Cancelled` once it has let go of the job.
+    pub fn cancel(&self, job_id: u64) {
+        self.jobs.cancel(job_id);
+        self.pause.notify();
+        let _ = self.cmd_tx.send(ScanCommand::Cancel { job_id });
+    }
+
+    /// Suspends scan `job_id` (also if it has not started yet), keeping its walk state. The
+    /// scanner answers with `ScanMessage::Paused` once it has stopped.
+    pub fn pause(&self, job_id: u64) {
+        self.pause.set(job_id);
+    }
+
+    /// Continues scan `job_id` where it was paused. Does nothing for other jobs.
+    pub fn resume(&self, job_id: u64) {
+        if self.pause.is_paused(job_id) {
+            self.pause.set(0);
+        }
+    }
 }
 
 pub enum ScanCommand {
@@ -95,10 +568,33 @@ pub enum ScanCommand {
         query: SearchQuery,
         cache: Option<CacheContext>,
     },
+    RunRoots {
+        job_id: u64,
+        scans: Vec<RootScan>,
+    },
+    RescanSubtree {
+        job_id: u64,
+        query: SearchQuery,
+        path: PathBuf,
+        cache: Option<CacheContext>,
+    },
     ClearCache {
         job_id: u64,
         ctx: CacheContext,
     },
+    FindDuplicates {
+        job_id: u64,
+        roots: Vec<CacheContext>,
+        min_size: u64,
+    },
+    LinkIdentical {
+        job_id: u64,
+        paths: Vec<PathBuf>,
+        undo_dir: PathBuf,
+    },
+    Cancel {
+        job_id: u64,
+    },
 }
 
 #[derive(Debug)]
@@ -107,14 +603,29 @@ pub enum ScanMessage {
         job_id: u64,
         root: PathBuf,
     },
-    Entry {
+    /// Starts a job over several roots, whose entries follow root by root.
+    BeginRoots {
         job_id: u64,
-        entry: FileEntry,
+        roots: Vec<PathBuf>,
+    },
+    /// Entries in walk order (parents before children), batched per `ScannerConfig::batch_size`.
+    Entries {
+        job_id: u64,
+        entries: Vec<FileEntry>,
     },
     Error {
         job_id: u64,
-        path: PathBuf,
-        message: String,
+        error: ScanError,
+    },
+    Progress {
+        job_id: u64,
+        progress: ScanProgress,
+    },
+    Paused {
+        job_id: u64,
+    },
+    Resumed {
+        job_id: u64,
     },
     Stats {
         job_id: u64,
@@ -128,34 +639,440 @@ pub enum ScanMessage {
     Complete {
         job_id: u64,
     },
+    /// Answers `ScannerHandle::cancel` once nothing more is sent for `job_id`.
+    Cancelled {
+        job_id: u64,
+    },
+    /// Duplicate groups found for `ScannerHandle::request_duplicates`, largest savings first.
+    Duplicates {
+        job_id: u64,
+        groups: Vec<DuplicateGroup>,
+        stats: DuplicateStats,
+    },
+    /// Outcome of `ScannerHandle::request_link`.
+    Linked {
+        job_id: u64,
+        result: io::Result<LinkReport>,
+    },
+    /// A subtree rescan for `job_id` starts walking `root`. Until the matching `SubtreeComplete`,
+    /// every entry sent belongs to it, so anything below `root` that is not sent again is gone.
+    SubtreeBegin {
+        job_id: u64,
+        root: PathBuf,
+    },
+    /// A subtree rescan for `job_id` finished; `root` is the directory it walked.
+    SubtreeComplete {
+        job_id: u64,
+        root: PathBuf,
+        stats: ScanStats,
+    },
+}
+
+/// What a single scan reports, in order. `ScanMessage` carries the same reports tagged with the
+/// job they belong to.
+#[derive(Debug)]
+pub enum ScanEvent {
+    /// Entries in walk order (parents before children), batched per `ScannerConfig::batch_size`.
+    Entries(Vec<FileEntry>),
+    Error(ScanError),
+    Progress(ScanProgress),
+    Paused,
+    Resumed,
+    /// Always the last event, also when the scan was cancelled.
+    Finished(ScanStats),
+}
+
+impl ScanEvent {
+    fn into_message(self, job_id: u64) -> ScanMessage {
+        match self {
+            ScanEvent::Entries(entries) => ScanMessage::Entries { job_id, entries },
+            ScanEvent::Error(error) => ScanMessage::Error { job_id, error },
+            ScanEvent::Progress(progress) => ScanMessage::Progress { job_id, progress },
+            ScanEvent::Paused => ScanMessage::Paused { job_id },
+            ScanEvent::Resumed => ScanMessage::Resumed { job_id },
+            ScanEvent::Finished(stats) => ScanMessage::Stats { job_id, stats },
+        }
+    }
+}
+
+/// Options for `scan` and `scan_stream`, which run a single scan without a `ScannerHandle`.
+#[derive(Clone, Default)]
+pub struct ScanOptions {
+    config: ScannerConfig,
+    pattern: Option<String>,
+    size_filter: Option<SizeFilter>,
+    allocation_filter: Option<Allocation>,
+    max_depth: Option<usize>,
+    cache: Option<Cache>,
+}
+
+impl ScanOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces every scanner setting at once; the other setters adjust single ones.
+    pub fn config(mut self, config: ScannerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn walker_threads(mut self, threads: usize) -> Self {
+        self.config.walker_threads = threads;
+        self
+    }
+
+    pub fn count_hard_links(mut self, enabled: bool) -> Self {
+        self.config.count_hard_links = enabled;
+        self
+    }
+
+    pub fn one_file_system(mut self, enabled: bool) -> Self {
+        self.config.one_file_system = enabled;
+        self
+    }
+
+    pub fn follow_symlinks(mut self, enabled: bool) -> Self {
+        self.config.follow_symlinks = enabled;
+        self
+    }
+
+    pub fn respect_gitignore(mut self, enabled: bool) -> Self {
+        self.config.respect_gitignore = enabled;
+        self
+    }
+
+    pub fn expand_archives(mut self, enabled: bool) -> Self {
+        self.config.expand_archives = enabled;
+        self
+    }
+
+    pub fn filesystem(mut self, filesystem: Arc<dyn FileSystem>) -> Self {
+        self.config.filesystem = filesystem;
+        self
+    }
+
+    pub fn low_impact(mut self, settings: LowImpact) -> Self {
+        self.config.low_impact = Some(settings);
+        self
+    }
+
+    /// Only lists entries whose path below the root matches this glob, plus their ancestors.
+    pub fn pattern(mut self, glob: impl Into<String>) -> Self {
+        self.pattern = Some(glob.into());
+        self
+    }
+
+    pub fn size_filter(mut self, filter: SizeFilter) -> Self {
+        self.size_filter = Some(filter);
+        self
+    }
+
+    pub fn allocation_filter(mut self, allocation: Allocation) -> Self {
+        self.allocation_filter = Some(allocation);
+        self
+    }
+
+    /// Stops walking this many levels below the root; deeper totals come from the cache.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Reuses unchanged directories from `cache` and records the scan there, like the app does.
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+}
+
+/// Everything a finished `scan` found.
+pub struct ScanResult {
+    /// The scanned root, canonicalized; the top node of `tree`.
+    pub root: PathBuf,
+    pub tree: TreeStore,
+    pub stats: ScanStats,
+    pub errors: Vec<ScanError>,
+}
+
+impl ScanResult {
+    /// Bytes at and below the root, each hard-linked inode counted once.
+    pub fn total_size(&self, metric: SizeMetric) -> u64 {
+        self.tree
+            .aggregated_size_with_cache(&self.root, metric, &mut BTreeMap::new())
+    }
+}
+
+/// Scans `root` on the calling thread's behalf and returns once it is done.
+pub fn scan(root: impl AsRef<Path>, options: ScanOptions) -> ScanResult {
+    let stream = scan_stream(root, options);
+    let mut result = ScanResult {
+        root: stream.root().to_path_buf(),
+        tree: TreeStore::default(),
+        stats: ScanStats::default(),
+        errors: Vec::new(),
+    };
+    for event in stream {
+        match event {
+            ScanEvent::Entries(entries) => {
+                for entry in entries {
+                    result.tree.upsert(entry);
+                }
+            }
+            ScanEvent::Error(error) => result.errors.push(error),
+            ScanEvent::Finished(stats) => result.stats = stats,
+            ScanEvent::Progress(_) | ScanEvent::Paused | ScanEvent::Resumed => {}
+        }
+    }
+    result
+}
+
+/// Starts scanning `root` and returns its events as they happen. The scan runs on threads of its
+/// own and waits while the events are not taken; dropping the stream early cancels it.
+pub fn scan_stream(root: impl AsRef<Path>, options: ScanOptions) -> ScanStream {
+    let ScanOptions {
+        mut config,
+        pattern,
+        size_filter,
+        allocation_filter,
+        max_depth,
+        cache,
+    } = options;
+    let root = root.as_ref();
+    let root = config
+        .filesystem
+        .canonicalize(root)
+        .unwrap_or_else(|_| root.to_path_buf());
+    let query = SearchQuery {
+        raw: root.display().to_string(),
+        root: root.clone(),
+        relative_pattern: pattern,
+        size_filter,
+        allocation_filter,
+        max_depth,
+    };
+    let mut cache_error = None;
+    let cache = cache.and_then(|cache| match cache.load_root(&root) {
+        Ok(root_cache) => Some(CacheContext {
+            cache,
+            root_id: root_cache.root_id,
+            canonical_root: root.clone(),
+        }),
+        Err(err) => {
+            cache_error = Some(ScanError {
+                path: root.clone(),
+                kind: ScanErrorKind::Cache,
+                message: format!("cache unavailable: {err}"),
+            });
+            None
+        }
+    });
+    let throttle = throttle_filesystem(&mut config);
+    let jobs = Arc::new(JobControl::default());
+    ScanStream::start(ScanJob {
+        job_id: jobs.next_id(),
+        query,
+        subtree: None,
+        cache,
+        cache_error,
+        config,
+        jobs,
+        pause: Arc::new(PauseControl::default()),
+        throttle,
+    })
+}
+
+/// Everything one scan needs, whether it runs for the scanner thread or for `scan_stream`.
+struct ScanJob {
+    job_id: u64,
+    query: SearchQuery,
+    subtree: Option<PathBuf>,
+    cache: Option<CacheContext>,
+    /// Reported first, for a cache that could not be opened for the root.
+    cache_error: Option<ScanError>,
+    config: ScannerConfig,
+    jobs: Arc<JobControl>,
+    pause: Arc<PauseControl>,
+    throttle: Option<Arc<Throttle>>,
+}
+
+/// The events of one running scan; see `scan_stream`.
+pub struct ScanStream {
+    root: PathBuf,
+    events: Receiver<ScanEvent>,
+    job_id: u64,
+    jobs: Arc<JobControl>,
+    pause: Arc<PauseControl>,
+    finished: bool,
+}
+
+impl ScanStream {
+    fn start(job: ScanJob) -> Self {
+        // Bounded so a consumer that falls behind throttles the scan instead of growing the queue.
+        let (event_tx, events) = bounded(job.config.channel_capacity.max(1));
+        let stream = Self {
+            root: job
+                .subtree
+                .clone()
+                .unwrap_or_else(|| job.query.root.clone()),
+            events,
+            job_id: job.job_id,
+            jobs: job.jobs.clone(),
+            pause: job.pause.clone(),
+            finished: false,
+        };
+        thread::Builder::new()
+            .name("disk-space-scan".into())
+            .spawn(move || {
+                if job.throttle.is_some()
+                    && let Err(err) = throttle::lower_thread_priority()
+                {
+                    eprintln!("dusk could not lower the scanner's priority: {err}");
+                }
+                if let Some(error) = job.cache_error {
+                    let _ = event_tx.send(ScanEvent::Error(error));
+                }
+                let stats = run_scan(
+                    job.job_id,
+                    job.query,
+                    job.subtree.as_deref(),
+                    job.cache,
+                    &job.config,
+                    &event_tx,
+                    &job.jobs,
+                    &job.pause,
+                    job.throttle.as_deref(),
+                );
+                let _ = event_tx.send(ScanEvent::Finished(stats));
+            })
+            .expect("failed to spawn scan thread");
+        stream
+    }
+
+    /// The directory being walked.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Stops the scan at its next entry without touching the cache; `Finished` still follows.
+    pub fn cancel(&self) {
+        self.jobs.cancel(self.job_id);
+        self.pause.notify();
+    }
+}
+
+impl Iterator for ScanStream {
+    type Item = ScanEvent;
+
+    fn next(&mut self) -> Option<ScanEvent> {
+        if self.finished {
+            return None;
+        }
+        let event = self.events.recv().ok();
+        self.finished = matches!(event, None | Some(ScanEvent::Finished(_)));
+        event
+    }
+}
+
+impl Drop for ScanStream {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.cancel();
+        }
+    }
 }
 
 pub fn spawn() -> (ScannerHandle, Receiver<ScanMessage>) {
+    spawn_with_config(ScannerConfig::default())
+}
+
+pub fn spawn_with_config(mut config: ScannerConfig) -> (ScannerHandle, Receiver<ScanMessage>) {
     let (cmd_tx, cmd_rx) = unbounded();
-    let (msg_tx, msg_rx) = unbounded();
-    let job_counter = Arc::new(AtomicU64::new(0));
-    let worker_counter = job_counter.clone();
+    // Bounded so a receiver that falls behind throttles the scan instead of growing the queue.
+    let (msg_tx, msg_rx) = bounded(config.channel_capacity.max(1));
+    let jobs = Arc::new(JobControl::default());
+    let pause = Arc::new(PauseControl::default());
+    let worker_jobs = jobs.clone();
+    let worker_pause = pause.clone();
     let worker_cmd = cmd_rx.clone();
+    // One throttle for the scanner's lifetime, so a backed-off rate carries over between jobs.
+    let throttle = throttle_filesystem(&mut config);
 
     thread::Builder::new()
         .name("disk-space-scanner".into())
-        .spawn(move || worker_loop(worker_cmd, msg_tx, worker_counter))
+        .spawn(move || {
+            // Duplicate searches run on this thread; scans lower their own threads.
+            if throttle.is_some()
+                && let Err(err) = throttle::lower_thread_priority()
+            {
+                eprintln!("dusk could not lower the scanner's priority: {err}");
+            }
+            worker_loop(
+                worker_cmd,
+                msg_tx,
+                worker_jobs,
+                worker_pause,
+                config,
+                throttle,
+            )
+        })
         .expect("failed to spawn scanner thread");
 
     (
         ScannerHandle {
             cmd_tx,
-            job_counter,
+            jobs,
+            pause,
         },
         msg_rx,
     )
 }
 
+/// Routes `config.filesystem` through a throttle when the scan is low-impact.
+fn throttle_filesystem(config: &mut ScannerConfig) -> Option<Arc<Throttle>> {
+    let throttle = Arc::new(Throttle::new(config.low_impact?));
+    config.filesystem = Arc::new(ThrottledFileSystem::new(
+        config.filesystem.clone(),
+        throttle.clone(),
+    ));
+    Some(throttle)
+}
+
+/// Relays the events of job `job_id` until it finishes, returning its stats.
+fn forward(job_id: u64, stream: ScanStream, msg_tx: &Sender<ScanMessage>) -> ScanStats {
+    let mut stats = ScanStats::default();
+    for event in stream {
+        match event {
+            ScanEvent::Finished(finished) => stats = finished,
+            event => {
+                let _ = msg_tx.send(event.into_message(job_id));
+            }
+        }
+    }
+    stats
+}
+
 fn worker_loop(
     cmd_rx: Receiver<ScanCommand>,
     msg_tx: Sender<ScanMessage>,
-    job_counter: Arc<AtomicU64>,
+    jobs: Arc<JobControl>,
+    pause: Arc<PauseControl>,
+    config: ScannerConfig,
+    throttle: Option<Arc<Throttle>>,
 ) {
+    let stream = |job_id, query, subtree, cache| {
+        ScanStream::start(ScanJob {
+            job_id,
+            query,
+            subtree,
+            cache,
+            cache_error: None,
+            config: config.clone(),
+            jobs: jobs.clone(),
+            pause: pause.clone(),
+            throttle: throttle.clone(),
+        })
+    };
     while let Ok(command) = cmd_rx.recv() {
         match command {
             ScanCommand::Run {
@@ -167,18 +1084,103 @@ fn worker_loop(
                     job_id,
                     root: query.root.clone(),
                 });
-                let stats = run_scan(job_id, query, cache, &msg_tx, &job_counter);
+                let stats = forward(job_id, stream(job_id, query, None, cache), &msg_tx);
+                let _ = msg_tx.send(ScanMessage::Stats { job_id, stats });
+                let _ = msg_tx.send(ScanMessage::Complete { job_id });
+            }
+            ScanCommand::RunRoots { job_id, scans } => {
+                let _ = msg_tx.send(ScanMessage::BeginRoots {
+                    job_id,
+                    roots: scans.iter().map(|scan| scan.query.root.clone()).collect(),
+                });
+                let mut stats = ScanStats::default();
+                for scan in scans {
+                    if !jobs.is_current(job_id) {
+                        break;
+                    }
+                    let root_stream = stream(job_id, scan.query, None, scan.cache);
+                    stats.accumulate(&forward(job_id, root_stream, &msg_tx));
+                }
                 let _ = msg_tx.send(ScanMessage::Stats { job_id, stats });
                 let _ = msg_tx.send(ScanMessage::Complete { job_id });
             }
+            ScanCommand::RescanSubtree {
+                job_id,
+                query,
+                path,
+                cache,
+            } => {
+                if !jobs.is_current(job_id) {
+                    continue;
+                }
+                let _ = msg_tx.send(ScanMessage::SubtreeBegin {
+                    job_id,
+                    root: path.clone(),
+                });
+                let subtree_stream = stream(job_id, query, Some(path.clone()), cache);
+                let stats = forward(job_id, subtree_stream, &msg_tx);
+                let _ = msg_tx.send(ScanMessage::SubtreeComplete {
+                    job_id,
+                    root: path,
+                    stats,
+                });
+            }
+            ScanCommand::FindDuplicates {
+                job_id,
+                roots,
+                min_size,
+            } => {
+                let superseded = || !jobs.is_current(job_id);
+                if superseded() {
+                    continue;
+                }
+                match duplicates::find_duplicates(&roots, min_size, &superseded) {
+                    Ok(Some((groups, stats))) => {
+                        let _ = msg_tx.send(ScanMessage::Duplicates {
+                            job_id,
+                            groups,
+                            stats,
+                        });
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        let path = roots
+                            .first()
+                            .map(|ctx| ctx.canonical_root.clone())
+                            .unwrap_or_default();
+                        let _ = msg_tx.send(ScanMessage::Error {
+                            job_id,
+                            error: ScanError {
+                                path,
+                                kind: ScanErrorKind::Cache,
+                                message: format!("duplicate search failed: {err}"),
+                            },
+                        });
+                    }
+                }
+            }
+            ScanCommand::LinkIdentical {
+                job_id,
+                paths,
+                undo_dir,
+            } => {
+                let result = duplicates::link_identical(&paths, &undo_dir);
+                let _ = msg_tx.send(ScanMessage::Linked { job_id, result });
+            }
+            ScanCommand::Cancel { job_id } => {
+                let _ = msg_tx.send(ScanMessage::Cancelled { job_id });
+            }
             ScanCommand::ClearCache { job_id, ctx } => {
                 let cleared = match ctx.cache.clear_root_path(&ctx.canonical_root) {
                     Ok(result) => result,
                     Err(err) => {
                         let _ = msg_tx.send(ScanMessage::Error {
                             job_id,
-                            path: ctx.canonical_root.clone(),
-                            message: format!("cache clear failed: {err}"),
+                            error: ScanError {
+                                path: ctx.canonical_root.clone(),
+                                kind: ScanErrorKind::Cache,
+                                message: format!("cache clear failed: {err}"),
+                            },
                         });
                         false
                     }
@@ -193,26 +1195,163 @@ fn worker_loop(
     }
 }
 
+/// Walks `query.root`, or only `subtree` below it when given, streaming entries to `events` and
+/// keeping the cache in step.
+#[allow(clippy::too_many_arguments)]
 fn run_scan(
     job_id: u64,
     query: SearchQuery,
+    subtree: Option<&Path>,
     cache_ctx: Option<CacheContext>,
-    msg_tx: &Sender<ScanMessage>,
-    job_counter: &Arc<AtomicU64>,
+    config: &ScannerConfig,
+    events: &Sender<ScanEvent>,
+    jobs: &Arc<JobControl>,
+    pause: &PauseControl,
+    throttle: Option<&Throttle>,
 ) -> ScanStats {
     let matcher = compile_matcher(query.relative_pattern.as_deref());
     let size_filter = query.size_filter.clone();
-    let mut session = cache_ctx
+    let allocation_filter = query.allocation_filter;
+    let max_depth = query.max_depth.map(|max| max.max(1));
+    let walk_root = subtree.unwrap_or(&query.root).to_path_buf();
+    let walk_relative = cache_ctx
         .as_ref()
-        .and_then(|ctx| ctx.cache.begin_scan(ctx.root_id).ok());
-
-    let mut walker = WalkDir::new(&query.root).follow_links(false).into_iter();
+        .map(|ctx| relative_path(&ctx.canonical_root, &walk_root));
+    let walk_cached = cache_ctx
+        .as_ref()
+        .zip(walk_relative.as_ref())
+        .and_then(|(ctx, rel)| {
+            ctx.cache
+                .entry(ctx.root_id, rel)
+                .ok()
+                .flatten()
+                .map(|cached| (cached.aggregate_size, cached.aggregate_allocated))
+        });
+    let mut session = match cache_ctx
+        .as_ref()
+        .map(|ctx| ctx.cache.begin_scan(ctx.root_id))
+    {
+        Some(Ok(session)) => Some(session),
+        Some(Err(err)) => {
+            let error = ScanError {
+                path: walk_root.clone(),
+                kind: ScanErrorKind::Cache,
+                message: format!("cache unavailable: {err}"),
+            };
+            report_error(error, cache_ctx.as_ref(), None, events);
+            None
+        }
+        None => None,
+    };
+
+    // Device of the root directory; only set when the scan must stay on one filesystem.
+    let filesystem = config.filesystem.as_ref();
+    let root_device = if config.one_file_system {
+        filesystem
+            .metadata(&query.root)
+            .ok()
+            .and_then(|metadata| metadata.file_id)
+            .map(|(device, _)| device)
+    } else {
+        None
+    };
+
+    // Link targets inside the root are counted where they live, so only the canonical root is
+    // needed to decide whether a symlink should be followed.
+    let canonical_root = if config.follow_symlinks {
+        cache_ctx
+            .as_ref()
+            .map(|ctx| ctx.canonical_root.clone())
+            .or_else(|| filesystem.canonicalize(&query.root).ok())
+    } else {
+        None
+    };
+
+    let excludes = Arc::new(ExcludeRules::new(
+        &query.root,
+        config.respect_gitignore,
+        config.filesystem.clone(),
+    ));
+
+    let prefetch_ctx = cache_ctx.clone();
+    let prefetch_jobs = jobs.clone();
+    let prefetch_excludes = excludes.clone();
+    let prefetch_root = walk_root.clone();
+    let mut walker = ParallelWalker::with_filesystem(
+        &walk_root,
+        config.walker_threads,
+        config.filesystem.clone(),
+        move |path: &Path, metadata: &FsMetadata| {
+            if !prefetch_jobs.is_current(job_id) {
+                return false;
+            }
+            if let Some(max) = max_depth
+                && path
+                    .strip_prefix(&prefetch_root)
+                    .is_ok_and(|rel| rel.components().count() >= max)
+            {
+                return false;
+            }
+            if is_foreign_device(root_device, metadata) || prefetch_excludes.is_excluded(path, true)
+            {
+                return false;
+            }
+            // Directories that will be replayed from cache are not worth reading ahead.
+            match prefetch_ctx.as_ref() {
+                Some(ctx) => {
+                    let relative = relative_path(&ctx.canonical_root, path);
+                    let ignore_key = prefetch_excludes.key(path);
+                    !cached_directory_is_clean(ctx, &relative, metadata, ignore_key)
+                }
+                None => true,
+            }
+        },
+    );
     let mut dir_stack: Vec<DirectoryFrame> = Vec::new();
     let mut stats = ScanStats::default();
+    let mut links = LinkTracker::new(config.count_hard_links);
+    // Links inside a rescanned subtree to files counted outside it must stay duplicates, or the
+    // inode is counted twice in the ancestors' totals.
+    if subtree.is_some()
+        && let Some((ctx, rel)) = cache_ctx.as_ref().zip(walk_relative.as_ref())
+    {
+        match ctx.cache.counted_links_outside(ctx.root_id, rel) {
+            Ok(keys) => links.seed(keys),
+            Err(err) => {
+                let error = ScanError {
+                    path: walk_root.clone(),
+                    kind: ScanErrorKind::Cache,
+                    message: format!("hard links outside the subtree unavailable: {err}"),
+                };
+                report_error(error, cache_ctx.as_ref(), None, events);
+            }
+        }
+    }
+    let mut batch = EntryBatcher::new(config, events);
+    let expected_total = walk_cached.map(|(size, _)| size);
+    let mut progress = ProgressTracker::new(config, expected_total, throttle, events);
+    // Paths whose ancestry must be marked dirty once the session is finished, because a
+    // depth-limited scan reported them without knowing their current contents.
+    let mut stale_estimates: Vec<PathBuf> = Vec::new();
     let mut aborted = false;
+    // Also checked for every cached entry replayed or verified, so a stale job stops promptly
+    // even inside a large unchanged subtree.
+    let superseded = || !jobs.is_current(job_id);
 
     while let Some(entry_result) = walker.next() {
-        if job_counter.load(Ordering::SeqCst) != job_id {
+        if pause.is_paused(job_id) {
+            // The walk state (stack, frames, cache session) simply waits here; workers stop
+            // reading ahead so the disk goes quiet.
+            walker.pause();
+            batch.flush();
+            let _ = events.send(ScanEvent::Paused);
+            let paused_at = Instant::now();
+            pause.wait_while_paused(job_id, jobs);
+            progress.exclude_pause(paused_at.elapsed());
+            walker.resume();
+            let _ = events.send(ScanEvent::Resumed);
+        }
+        if !jobs.is_current(job_id) {
             aborted = true;
             break;
         }
@@ -220,23 +1359,23 @@ fn run_scan(
         let entry = match entry_result {
             Ok(entry) => entry,
             Err(err) => {
-                if let Some(path) = err.path() {
-                    let _ = msg_tx.send(ScanMessage::Error {
-                        job_id,
-                        path: path.to_path_buf(),
-                        message: err.to_string(),
-                    });
-                }
+                let error = ScanError {
+                    kind: ScanErrorKind::from_io(&err.error),
+                    message: err.to_string(),
+                    path: err.path,
+                };
+                report_error(error, cache_ctx.as_ref(), session.as_mut(), events);
                 stats.fs_errors += 1;
                 continue;
             }
         };
 
-        let path = entry.path().to_path_buf();
-        let depth = entry.depth();
+        let path = entry.path;
+        let depth = entry.depth;
 
         while dir_stack.len() > depth {
             if let Some(frame) = dir_stack.pop() {
+                excludes.forget(&frame.path);
                 if let Err(err) = finalize_directory(frame, dir_stack.last_mut(), session.as_mut())
                 {
                     eprintln!("dusk cache finalize error: {err}");
@@ -244,34 +1383,76 @@ fn run_scan(
             }
         }
 
-        let metadata = match entry.metadata() {
+        let mut metadata = match entry.metadata {
             Ok(metadata) => metadata,
             Err(err) => {
-                let _ = msg_tx.send(ScanMessage::Error {
-                    job_id,
+                let error = ScanError {
                     path: path.clone(),
+                    kind: ScanErrorKind::from_io(&err),
                     message: err.to_string(),
-                });
+                };
+                report_error(error, cache_ctx.as_ref(), session.as_mut(), events);
                 stats.fs_errors += 1;
                 continue;
             }
         };
 
-        let kind = if metadata.is_dir() {
-            FileKind::Directory
-        } else if metadata.is_file() {
-            FileKind::File
-        } else {
+        let mut kind = metadata.kind;
+        // Excluded files are dropped; excluded directories stay visible as unsized markers.
+        let excluded = depth > 0 && excludes.is_excluded(&path, kind == FileKind::Directory);
+        if excluded && kind != FileKind::Directory {
             continue;
+        }
+        let (link_target, dangling_link) = if kind == FileKind::Symlink {
+            let target = filesystem.read_link(&path).ok();
+            (target, filesystem.metadata(&path).is_err())
+        } else {
+            (None, false)
         };
 
-        let direct_size = if kind == FileKind::File {
-            metadata.len()
+        let mut link_flags = 0;
+        if kind == FileKind::Symlink
+            && !dangling_link
+            && let Some(canonical_root) = canonical_root.as_deref()
+        {
+            match follow_symlink(filesystem, &path, canonical_root, &dir_stack, &mut links) {
+                Some(target) => {
+                    kind = target.kind;
+                    metadata = target;
+                    link_flags = cache::FLAG_FOLLOWED_LINK;
+                    stats.symlinks_followed += 1;
+                }
+                None => link_flags = cache::FLAG_LINK_NOT_FOLLOWED,
+            }
+        }
+        let followed = link_flags == cache::FLAG_FOLLOWED_LINK;
+
+        // Symlinks report the length of their target path, like `du`.
+        let direct_size = match kind {
+            FileKind::File | FileKind::Symlink => metadata.len,
+            _ => 0,
+        };
+        let allocated_size = if excluded { 0 } else { metadata.allocated };
+        let file_id = metadata.file_id;
+        let link_key =
+            file_id.filter(|_| kind == FileKind::File && (followed || metadata.link_count > 1));
+        let mount_point = kind == FileKind::Directory && is_foreign_device(root_device, &metadata);
+        let ignore_key = if kind == FileKind::Directory && !excluded {
+            Some(excludes.key(&path))
         } else {
-            0
+            None
         };
-        let modified_ts = cache::timestamp_from_system(metadata.modified().ok());
-        let created_ts = cache::timestamp_from_system(metadata.created().ok());
+        let modified_ts = cache::timestamp_from_system(metadata.modified);
+        let created_ts = cache::timestamp_from_system(metadata.created);
+        let modified_ns = cache::timestamp_nanos_from_system(metadata.modified);
+        let changed_ns = cache::timestamp_nanos_from_system(metadata.changed);
+        // Directories past the depth limit are not walked; the cache stands in for their contents.
+        let unscanned = kind == FileKind::Directory
+            && depth > 0
+            && !mount_point
+            && !excluded
+            && max_depth.is_some_and(|max| depth >= max);
+        let mut estimate = None;
 
         let mut rel_path = None;
         let mut parent_rel = None;
@@ -280,54 +1461,150 @@ fn run_scan(
             parent_rel = parent_relative(&relative);
             rel_path = Some(relative.clone());
 
-            // Skip decision matrix: reuse the cached subtree when the entry is clean (`flags & 1 == 0`)
-            // and the on-disk mtime matches what we stored previously. Any validation failure drops
-            // back to a full walk and marks the ancestry dirty so subsequent scans re-evaluate.
-            if kind == FileKind::Directory {
-                if let Ok(Some(cached)) = ctx.cache.entry(ctx.root_id, &relative) {
-                    let cached_mtime = cached.modified;
-                    if cached.flags & 1 == 0 && cached_mtime == modified_ts {
-                        let session_ptr =
-                            session.as_mut().map(|sess| sess as *mut cache::ScanSession);
-                        match emit_cached_subtree(
-                            job_id,
-                            ctx,
-                            &relative,
-                            session_ptr,
-                            matcher.as_ref(),
-                            size_filter.as_ref(),
-                            msg_tx,
-                        ) {
-                            Ok(emit_stats) => {
-                                stats.cached_dirs += emit_stats.directories as u64;
-                                stats.cached_entries += emit_stats.entries as u64;
-                                stats.cached_bytes += emit_stats.aggregate_size;
-                                if let Some(parent) = dir_stack.last_mut() {
-                                    parent.aggregate_size += emit_stats.aggregate_size;
+            if unscanned {
+                match ctx.cache.entry(ctx.root_id, &relative) {
+                    Ok(Some(cached)) => {
+                        estimate = Some(SizeEstimate {
+                            size: cached.aggregate_size,
+                            allocated: cached.aggregate_allocated,
+                        });
+                        if cached.modified_ns != modified_ns || cached.changed_ns != changed_ns {
+                            stale_estimates.push(relative.clone());
+                        }
+                        if let Some(session) = session.as_mut()
+                            && let Err(err) = session.retain_subtree(&relative)
+                        {
+                            eprintln!("dusk cache retain error: {err}");
+                        }
+                    }
+                    _ => stale_estimates.extend(parent_rel.clone()),
+                }
+            }
+
+            // Reuse the cached subtree when the skip decision matrix allows it. Any validation
+            // failure drops back to a full walk and marks the ancestry dirty so subsequent scans
+            // re-evaluate.
+            if let Some(ignore_key) = ignore_key
+                && !mount_point
+                && !unscanned
+                && cached_directory_is_clean(ctx, &relative, &metadata, ignore_key)
+            {
+                let session_ptr = session.as_mut().map(|sess| sess as *mut cache::ScanSession);
+                links.checkpoint();
+                let progress_checkpoint = progress.counts;
+                match emit_cached_subtree(
+                    ctx,
+                    filesystem,
+                    &superseded,
+                    &relative,
+                    session_ptr,
+                    matcher.as_ref(),
+                    size_filter.as_ref(),
+                    allocation_filter,
+                    root_device,
+                    config.follow_symlinks,
+                    config.expand_archives,
+                    max_depth.map(|max| max - depth),
+                    &mut links,
+                    &mut batch,
+                    &mut progress,
+                ) {
+                    Ok(emit_stats) => {
+                        stats.cached_dirs += emit_stats.directories as u64;
+                        stats.cached_entries += emit_stats.entries as u64;
+                        stats.cached_bytes += emit_stats.aggregate_size;
+                        stats.hardlink_duplicates += emit_stats.hardlink_duplicates as u64;
+                        stats.mount_points_skipped += emit_stats.mount_points as u64;
+                        stats.excluded_dirs += emit_stats.excluded as u64;
+                        stats.dirs_estimated += emit_stats.estimated as u64;
+                        stats.archives_listed += emit_stats.archives_listed as u64;
+                        stats.archives_cached += emit_stats.archives_cached as u64;
+                        if let Some(parent) = dir_stack.last_mut() {
+                            parent.aggregate_size += emit_stats.aggregate_size;
+                            parent.aggregate_allocated += emit_stats.aggregate_allocated;
+                        }
+                        // Errors inside an unchanged subtree still apply, so report them again.
+                        if let Some(session) = session.as_mut() {
+                            match session.retain_errors(&relative) {
+                                Ok(errors) => {
+                                    stats.fs_errors += errors.len() as u64;
+                                    for mut error in errors {
+                                        error.path = absolute_from_relative(
+                                            &ctx.canonical_root,
+                                            &error.path,
+                                        );
+                                        let _ = events.send(ScanEvent::Error(error));
+                                    }
                                 }
-                                walker.skip_current_dir();
-                                continue;
-                            }
-                            Err(CachedReplayError::Cache(err)) => {
-                                eprintln!("dusk cache validation failure: {err}");
-                                let _ = ctx.cache.mark_ancestors_dirty(ctx.root_id, &relative);
-                            }
-                            Err(CachedReplayError::Storage(err)) => {
-                                eprintln!("dusk cache replay error: {err}");
+                                Err(err) => eprintln!("dusk cache error replay failed: {err}"),
                             }
                         }
+                        walker.skip_current_dir();
+                        continue;
+                    }
+                    Err(CachedReplayError::Cache(err)) => {
+                        links.rollback();
+                        progress.counts = progress_checkpoint;
+                        eprintln!("dusk cache validation failure: {err}");
+                        let _ = ctx.cache.mark_ancestors_dirty(ctx.root_id, &relative);
+                    }
+                    Err(CachedReplayError::Storage(err)) => {
+                        links.rollback();
+                        progress.counts = progress_checkpoint;
+                        eprintln!("dusk cache replay error: {err}");
+                    }
+                    // The session is dropped unfinished, so the partial replay never lands.
+                    Err(CachedReplayError::Superseded) => {
+                        aborted = true;
+                        break;
                     }
                 }
             }
         }
 
+        let hardlink_duplicate = links.is_duplicate(link_key);
+        let (counted_size, counted_allocated) = if hardlink_duplicate {
+            stats.hardlink_duplicates += 1;
+            (0, 0)
+        } else {
+            (direct_size, allocated_size)
+        };
+        let mut flags = link_flags;
+        if link_key.is_some() {
+            flags |= cache::FLAG_MULTI_LINK;
+        }
+        if hardlink_duplicate {
+            flags |= cache::FLAG_HARDLINK_DUPLICATE;
+        }
+        if mount_point {
+            flags |= cache::FLAG_MOUNT_POINT;
+        }
+        if dangling_link {
+            flags |= cache::FLAG_DANGLING_LINK;
+        }
+        if excluded {
+            flags |= cache::FLAG_EXCLUDED;
+        }
+        let allocation = (kind == FileKind::File)
+            .then(|| Allocation::detect(direct_size, allocated_size))
+            .flatten();
+        match allocation {
+            Some(Allocation::Sparse) => flags |= cache::FLAG_SPARSE,
+            Some(Allocation::Preallocated) => flags |= cache::FLAG_PREALLOCATED,
+            None => {}
+        }
+
         if !should_include(
             &path,
             kind,
             direct_size,
             matcher.as_ref(),
-            &query.root,
+            cache_ctx
+                .as_ref()
+                .map_or(&query.root, |ctx| &ctx.canonical_root),
             size_filter.as_ref(),
+            allocation,
+            allocation_filter,
         ) {
             continue;
         }
@@ -337,108 +1614,228 @@ fn run_scan(
             .and_then(|name| name.to_str())
             .map(|s| s.to_string())
             .unwrap_or_else(|| path.display().to_string());
+        let category = (kind == FileKind::File).then(|| FileCategory::classify(&path, filesystem));
 
-        let entry = FileEntry::new(
+        let mut entry = FileEntry::new(
             path.clone(),
             file_name,
             kind,
             direct_size,
-            metadata.modified().ok(),
-            metadata.created().ok(),
+            allocated_size,
+            metadata.modified,
+            metadata.created,
         );
-
-        let _ = msg_tx.send(ScanMessage::Entry { job_id, entry });
-
-        if let (Some(session), Some(rel)) = (session.as_mut(), rel_path.as_ref()) {
-            let parent_ref = parent_rel.as_ref().map(|p| p.as_path());
-            if let Err(err) = session.upsert_entry(
-                rel,
-                parent_ref,
+        entry.hardlink_duplicate = hardlink_duplicate;
+        entry.mount_point = mount_point;
+        entry.link_target = link_target.clone();
+        entry.dangling_link = dangling_link;
+        entry.excluded = excluded;
+        entry.unscanned = unscanned;
+        entry.estimate = estimate;
+        entry.category = category;
+        entry.allocation = allocation;
+        entry.owner = metadata.owner;
+
+        batch.push(entry);
+
+        // Unscanned directories keep whatever row the cache already has.
+        if !unscanned && let (Some(session), Some(rel)) = (session.as_mut(), rel_path.as_ref()) {
+            let is_file = kind != FileKind::Directory;
+            let record = CachedEntry {
+                path: rel.clone(),
+                parent: parent_rel.clone(),
                 kind,
                 direct_size,
-                if kind == FileKind::File {
-                    direct_size
-                } else {
-                    0
-                },
+                aggregate_size: if is_file { counted_size } else { 0 },
+                modified: modified_ts,
+                created: created_ts,
+                flags,
+                allocated_size,
+                aggregate_allocated: if is_file { counted_allocated } else { 0 },
+                device: file_id.map(|(device, _)| device),
+                inode: file_id.map(|(_, inode)| inode),
+                link_target: link_target.clone(),
+                ignore_key,
+                category,
+                modified_ns,
+                changed_ns,
+                owner: metadata.owner,
+            };
+            if let Err(err) = session.upsert_entry(&record) {
+                eprintln!("dusk cache upsert error: {err}");
+            }
+        }
+
+        if config.expand_archives
+            && kind == FileKind::File
+            && let Some(format) = ArchiveFormat::detect(&path)
+        {
+            let ctx = cache_ctx.as_ref();
+            if emit_archive_members(
+                filesystem,
+                ctx,
+                &path,
+                format,
                 modified_ts,
-                created_ts,
+                direct_size,
+                &mut batch,
             ) {
-                eprintln!("dusk cache upsert error: {err}");
+                stats.archives_cached += 1;
+            } else {
+                stats.archives_listed += 1;
             }
         }
 
         match kind {
-            FileKind::File => {
-                stats.files_scanned += 1;
+            FileKind::Directory if mount_point => {
+                stats.mount_points_skipped += 1;
+                walker.skip_current_dir();
+                progress.record(kind, 0);
+            }
+            FileKind::Directory if excluded => {
+                stats.excluded_dirs += 1;
+                walker.skip_current_dir();
+                progress.record(kind, 0);
+            }
+            FileKind::Directory if unscanned => {
+                stats.dirs_estimated += 1;
+                walker.skip_current_dir();
+                let estimate = estimate.unwrap_or_default();
                 if let Some(parent) = dir_stack.last_mut() {
-                    parent.aggregate_size += direct_size;
+                    parent.aggregate_size += estimate.size;
+                    parent.aggregate_allocated += estimate.allocated;
                 }
+                progress.record_estimate(estimate.size);
             }
             FileKind::Directory => {
                 stats.dirs_scanned += 1;
+                progress.record(kind, 0);
+                progress.tick(&path);
+                if followed {
+                    walker.descend_into(path.clone());
+                }
                 if let Some(rel) = rel_path {
                     dir_stack.push(DirectoryFrame {
+                        path: path.clone(),
                         relative: rel,
                         parent: parent_rel,
                         direct_size,
                         aggregate_size: 0,
+                        allocated_size,
+                        aggregate_allocated: 0,
                         modified: modified_ts,
                         created: created_ts,
+                        modified_ns,
+                        changed_ns,
+                        file_id,
+                        owner: metadata.owner,
+                        flags,
+                        link_target,
+                        ignore_key: ignore_key.unwrap_or_default(),
                     });
                 }
             }
+            _ => {
+                if kind == FileKind::File {
+                    stats.files_scanned += 1;
+                } else {
+                    stats.special_files_scanned += 1;
+                }
+                if let Some(parent) = dir_stack.last_mut() {
+                    parent.aggregate_size += counted_size;
+                    parent.aggregate_allocated += counted_allocated;
+                }
+                progress.record(kind, counted_size);
+                progress.tick(path.parent().unwrap_or(&path));
+            }
         }
     }
 
+    batch.flush();
+
     if !aborted {
+        progress.emit(&walk_root);
         while let Some(frame) = dir_stack.pop() {
             if let Err(err) = finalize_directory(frame, dir_stack.last_mut(), session.as_mut()) {
                 eprintln!("dusk cache finalize error: {err}");
             }
         }
         if let Some(session) = session {
-            if let Err(err) = session.finish() {
-                eprintln!("dusk cache flush error: {err}");
+            let finished = match (subtree, walk_relative.as_deref()) {
+                (Some(_), Some(relative)) => {
+                    session.finish_subtree(relative, walk_cached.unwrap_or_default())
+                }
+                _ => session.finish(),
+            };
+            if let Err(err) = finished {
+                let error = ScanError {
+                    path: walk_root.clone(),
+                    kind: ScanErrorKind::Cache,
+                    message: format!("cache update failed: {err}"),
+                };
+                report_error(error, None, None, events);
             }
         }
 
-        if let Some(ctx) = cache_ctx.as_ref() {
-            match verify_cache_root(ctx) {
+        if let (Some(ctx), Some(relative)) = (cache_ctx.as_ref(), walk_relative.as_deref()) {
+            for path in &stale_estimates {
+                let _ = ctx.cache.mark_ancestors_dirty(ctx.root_id, path);
+            }
+            match verify_cache_root(ctx, relative, &superseded) {
                 Ok(_summary) => {}
                 Err(err) => {
                     stats.cache_validation_errors += 1;
                     eprintln!("dusk cache validation error: {err}");
-                    let _ = ctx.cache.mark_dirty(ctx.root_id, Path::new("."));
+                    let _ = ctx.cache.mark_ancestors_dirty(ctx.root_id, relative);
                 }
             }
         }
     }
 
     eprintln!(
-        "dusk scan stats job={job_id} aborted={aborted} files={} dirs={} cached_dirs={} cached_entries={} cached_bytes={} fs_errors={} cache_validation_errors={}",
+        "dusk scan stats job={job_id} aborted={aborted} files={} dirs={} special={} cached_dirs={} cached_entries={} cached_bytes={} fs_errors={} cache_validation_errors={} hardlink_duplicates={} mount_points_skipped={} symlinks_followed={} excluded_dirs={} dirs_estimated={} archives_listed={} archives_cached={}",
         stats.files_scanned,
         stats.dirs_scanned,
+        stats.special_files_scanned,
         stats.cached_dirs,
         stats.cached_entries,
         stats.cached_bytes,
         stats.fs_errors,
-        stats.cache_validation_errors
+        stats.cache_validation_errors,
+        stats.hardlink_duplicates,
+        stats.mount_points_skipped,
+        stats.symlinks_followed,
+        stats.excluded_dirs,
+        stats.dirs_estimated,
+        stats.archives_listed,
+        stats.archives_cached
     );
 
     stats
 }
 
+#[allow(clippy::too_many_arguments)]
 fn emit_cached_subtree(
-    job_id: u64,
     ctx: &CacheContext,
+    filesystem: &dyn FileSystem,
+    superseded: &dyn Fn() -> bool,
     relative: &Path,
     session_ptr: Option<*mut cache::ScanSession>,
     matcher: Option<&GlobSet>,
     size_filter: Option<&SizeFilter>,
-    msg_tx: &Sender<ScanMessage>,
+    allocation_filter: Option<Allocation>,
+    root_device: Option<u64>,
+    follow_symlinks: bool,
+    expand_archives: bool,
+    remaining_depth: Option<usize>,
+    links: &mut LinkTracker,
+    batch: &mut EntryBatcher<'_>,
+    progress: &mut ProgressTracker<'_>,
 ) -> Result<EmitStats, CachedReplayError> {
-    let entry = ctx
+    if superseded() {
+        return Err(CachedReplayError::Superseded);
+    }
+    let mut entry = ctx
         .cache
         .entry(ctx.root_id, relative)?
         .ok_or_else(|| CacheValidationError::MissingEntry(relative.to_path_buf()))?;
@@ -451,10 +1848,69 @@ fn emit_cached_subtree(
         matcher,
         &ctx.canonical_root,
         size_filter,
+        entry.allocation(),
+        allocation_filter,
     );
 
     let mut stats = EmitStats::default();
 
+    // Mount-point markers only stay valid while the one-filesystem setting matches the cached run.
+    let mount_point = entry.flags & cache::FLAG_MOUNT_POINT != 0;
+    let foreign = root_device.is_some_and(|root| entry.device.is_some_and(|dev| dev != root));
+    if entry.kind == FileKind::Directory && mount_point != foreign {
+        return Err(CacheValidationError::OptionsChanged(entry.path).into());
+    }
+    if mount_point {
+        stats.mount_points += 1;
+    }
+    let excluded = entry.flags & cache::FLAG_EXCLUDED != 0;
+    if excluded {
+        stats.excluded += 1;
+    }
+
+    // Symlink decisions are only valid under the same follow setting, and a followed entry is only
+    // as fresh as its target, which lives outside the root and is not covered by our mtimes.
+    let followed = entry.flags & cache::FLAG_FOLLOWED_LINK != 0;
+    let link_decided = followed || entry.flags & cache::FLAG_LINK_NOT_FOLLOWED != 0;
+    let live_symlink =
+        entry.kind == FileKind::Symlink && entry.flags & cache::FLAG_DANGLING_LINK == 0;
+    let options_changed = if follow_symlinks {
+        live_symlink && !link_decided
+    } else {
+        link_decided
+    };
+    if options_changed {
+        return Err(CacheValidationError::OptionsChanged(entry.path).into());
+    }
+    if followed {
+        let target = filesystem.metadata(&abs_path).ok();
+        let target_id = target.as_ref().and_then(|meta| meta.file_id);
+        let target_modified = target
+            .as_ref()
+            .and_then(|meta| cache::timestamp_nanos_from_system(meta.modified));
+        let cached_id = entry.device.zip(entry.inode);
+        let revisit = entry.kind == FileKind::Directory && links.is_revisit(cached_id);
+        if target_id != cached_id || target_modified != entry.modified_ns || revisit {
+            return Err(CacheValidationError::LinkTargetChanged(entry.path).into());
+        }
+    }
+
+    // Hard-link bookkeeping is redone on replay; if the outcome differs from what was cached, the
+    // aggregate check below fails and the directory is walked again.
+    let hardlink_duplicate = entry.kind == FileKind::File && links.is_duplicate(entry.link_key());
+    if hardlink_duplicate {
+        entry.flags |= cache::FLAG_HARDLINK_DUPLICATE;
+        stats.hardlink_duplicates += 1;
+    } else {
+        entry.flags &= !cache::FLAG_HARDLINK_DUPLICATE;
+    }
+
+    // Past the depth limit the cached totals stand in for the subtree, which is kept as it is.
+    let estimated = entry.kind == FileKind::Directory
+        && !mount_point
+        && !excluded
+        && remaining_depth == Some(0);
+
     if include {
         let file_name = abs_path
             .file_name()
@@ -462,57 +1918,111 @@ fn emit_cached_subtree(
             .map(|s| s.to_string())
             .unwrap_or_else(|| abs_path.display().to_string());
 
-        let file_entry = FileEntry::new(
+        let mut file_entry = FileEntry::new(
             abs_path.clone(),
             file_name,
             entry.kind,
             entry.direct_size,
+            entry.allocated_size,
             cache::timestamp_to_system(entry.modified),
             cache::timestamp_to_system(entry.created),
         );
+        file_entry.hardlink_duplicate = hardlink_duplicate;
+        file_entry.mount_point = mount_point;
+        file_entry.link_target = entry.link_target.clone();
+        file_entry.dangling_link = entry.flags & cache::FLAG_DANGLING_LINK != 0;
+        file_entry.excluded = excluded;
+        file_entry.unscanned = estimated;
+        file_entry.category = entry.file_category();
+        file_entry.allocation = entry.allocation();
+        file_entry.owner = entry.owner;
+        if estimated {
+            file_entry.estimate = Some(SizeEstimate {
+                size: entry.aggregate_size,
+                allocated: entry.aggregate_allocated,
+            });
+        }
 
-        let _ = msg_tx.send(ScanMessage::Entry {
-            job_id,
-            entry: file_entry,
-        });
+        batch.push(file_entry);
+
+        if expand_archives
+            && entry.kind == FileKind::File
+            && let Some(format) = ArchiveFormat::detect(&abs_path)
+        {
+            let (modified, size) = (entry.modified, entry.direct_size);
+            if emit_archive_members(
+                filesystem,
+                Some(ctx),
+                &abs_path,
+                format,
+                modified,
+                size,
+                batch,
+            ) {
+                stats.archives_cached += 1;
+            } else {
+                stats.archives_listed += 1;
+            }
+        }
     }
 
     if let Some(ptr) = session_ptr {
         unsafe {
-            let parent_buf = entry.parent.clone();
-            let parent_ref = parent_buf.as_deref();
-            (*ptr).upsert_entry(
-                &entry.path,
-                parent_ref,
-                entry.kind,
-                entry.direct_size,
-                entry.aggregate_size,
-                entry.modified,
-                entry.created,
-            )?;
+            if estimated {
+                (*ptr).retain_subtree(&entry.path)?;
+            } else {
+                (*ptr).upsert_entry(&entry)?;
+            }
         }
     }
 
-    let mut computed_total = entry.direct_size;
-
+    if estimated {
+        progress.record_estimate(entry.aggregate_size);
+    } else {
+        progress.record(entry.kind, entry.counted_size());
+    }
     if entry.kind == FileKind::Directory {
+        progress.tick(&abs_path);
+    }
+
+    let mut computed_total = entry.counted_size();
+
+    if estimated {
+        stats.estimated += 1;
+        computed_total = entry.aggregate_size;
+    } else if entry.kind == FileKind::Directory {
         stats.directories += 1;
         let children = ctx.cache.children_of(ctx.root_id, &entry.path)?;
         for child in children {
             let child_stats = emit_cached_subtree(
-                job_id,
                 ctx,
+                filesystem,
+                superseded,
                 &child.path,
                 session_ptr,
                 matcher,
                 size_filter,
-                msg_tx,
+                allocation_filter,
+                root_device,
+                follow_symlinks,
+                expand_archives,
+                remaining_depth.map(|depth| depth.saturating_sub(1)),
+                links,
+                batch,
+                progress,
             )?;
             computed_total += child_stats.aggregate_size;
             stats.aggregate_size += child_stats.aggregate_size;
+            stats.aggregate_allocated += child_stats.aggregate_allocated;
             stats.entries += child_stats.entries;
             stats.directories += child_stats.directories;
             stats.files += child_stats.files;
+            stats.hardlink_duplicates += child_stats.hardlink_duplicates;
+            stats.mount_points += child_stats.mount_points;
+            stats.excluded += child_stats.excluded;
+            stats.estimated += child_stats.estimated;
+            stats.archives_listed += child_stats.archives_listed;
+            stats.archives_cached += child_stats.archives_cached;
         }
     } else {
         stats.files += 1;
@@ -528,13 +2038,143 @@ fn emit_cached_subtree(
     }
 
     stats.aggregate_size = entry.aggregate_size;
+    stats.aggregate_allocated = entry.aggregate_allocated;
     stats.entries += 1;
 
     Ok(stats)
 }
 
-fn verify_cache_root(ctx: &CacheContext) -> Result<AggregateSummary, CacheValidationError> {
-    ctx.cache.validate_aggregate(ctx.root_id, Path::new("."))
+/// Sends the members of the archive at `path` as virtual children of it, returning whether they
+/// came from the cache. A listing is reused while the archive's `modified` and `size` match the
+/// ones it was read at; archives that cannot be read are cached as empty so they are not retried
+/// on every scan.
+fn emit_archive_members(
+    filesystem: &dyn FileSystem,
+    ctx: Option<&CacheContext>,
+    path: &Path,
+    format: ArchiveFormat,
+    modified: Option<i64>,
+    size: u64,
+    batch: &mut EntryBatcher<'_>,
+) -> bool {
+    let relative = ctx.map(|ctx| relative_path(&ctx.canonical_root, path));
+    let cached = ctx.zip(relative.as_deref()).and_then(|(ctx, relative)| {
+        ctx.cache
+            .archive_listing(ctx.root_id, relative, modified, size)
+            .unwrap_or_else(|err| {
+                eprintln!("dusk archive cache error: {err}");
+                None
+            })
+    });
+    let from_cache = cached.is_some();
+    let members = cached.unwrap_or_else(|| {
+        let members = filesystem
+            .open(path)
+            .and_then(|file| archive::list(file, format))
+            .unwrap_or_else(|err| {
+                eprintln!("dusk archive listing error for {}: {err}", path.display());
+                Vec::new()
+            });
+        if let (Some(ctx), Some(relative)) = (ctx, relative.as_deref())
+            && let Err(err) =
+                ctx.cache
+                    .store_archive_listing(ctx.root_id, relative, modified, size, &members)
+        {
+            eprintln!("dusk archive cache error: {err}");
+        }
+        members
+    });
+
+    for member in members {
+        let file_name = member
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| member.path.display().to_string());
+        let mut entry = FileEntry::new(
+            path.join(&member.path),
+            file_name,
+            member.kind,
+            member.size,
+            member.compressed_size.unwrap_or(0),
+            None,
+            None,
+        );
+        entry.in_archive = true;
+        entry.compressed_size = member.compressed_size;
+        entry.category = (member.kind == FileKind::File)
+            .then(|| FileCategory::from_extension(&member.path).unwrap_or(FileCategory::Other));
+        batch.push(entry);
+    }
+    from_cache
+}
+
+/// Skip decision matrix: a cached directory can be replayed when it is clean (no `FLAG_DIRTY`),
+/// its nanosecond mtime and ctime and its device and inode all match what we stored previously,
+/// and it was walked under the same ignore rules. The ctime also moves when an mtime is set
+/// back, and the inode tells a directory apart from one that replaced it under the same name.
+fn cached_directory_is_clean(
+    ctx: &CacheContext,
+    relative: &Path,
+    metadata: &FsMetadata,
+    ignore_key: u64,
+) -> bool {
+    match ctx.cache.entry(ctx.root_id, relative) {
+        Ok(Some(cached)) => {
+            cached.flags & cache::FLAG_DIRTY == 0
+                && cached.modified_ns == cache::timestamp_nanos_from_system(metadata.modified)
+                && cached.changed_ns == cache::timestamp_nanos_from_system(metadata.changed)
+                && cached.device.zip(cached.inode) == metadata.file_id
+                && cached.ignore_key == Some(ignore_key)
+        }
+        _ => false,
+    }
+}
+
+/// Resolves a symlink for a link-following scan, returning the target's metadata when it should
+/// be walked. Targets inside the root are left to the regular walk, and directories that are an
+/// ancestor of the link or were already reached through another link are not entered again.
+fn follow_symlink(
+    filesystem: &dyn FileSystem,
+    path: &Path,
+    canonical_root: &Path,
+    dir_stack: &[DirectoryFrame],
+    links: &mut LinkTracker,
+) -> Option<FsMetadata> {
+    let target = filesystem.metadata(path).ok()?;
+    let canonical = filesystem.canonicalize(path).ok()?;
+    if canonical.starts_with(canonical_root) || canonical_root.starts_with(&canonical) {
+        return None;
+    }
+
+    if target.is_dir() {
+        let id = target.file_id;
+        let is_loop = id.is_some() && dir_stack.iter().any(|frame| frame.file_id == id);
+        if is_loop || links.is_revisit(id) {
+            return None;
+        }
+    } else if !target.is_file() {
+        return None;
+    }
+
+    Some(target)
+}
+
+fn is_foreign_device(root_device: Option<u64>, metadata: &FsMetadata) -> bool {
+    match (root_device, metadata.file_id) {
+        (Some(root), Some((device, _))) => device != root,
+        _ => false,
+    }
+}
+
+/// Re-adds the cached totals below `relative`; `None` when the job was superseded halfway.
+fn verify_cache_root(
+    ctx: &CacheContext,
+    relative: &Path,
+    superseded: &dyn Fn() -> bool,
+) -> Result<Option<AggregateSummary>, CacheValidationError> {
+    ctx.cache
+        .validate_aggregate_until(ctx.root_id, relative, superseded)
 }
 
 fn finalize_directory(
@@ -543,36 +2183,80 @@ fn finalize_directory(
     session: Option<&mut cache::ScanSession>,
 ) -> Result<(), SqliteError> {
     let DirectoryFrame {
+        path: _,
         relative,
         parent,
         direct_size,
         aggregate_size,
+        allocated_size,
+        aggregate_allocated,
         modified,
         created,
+        modified_ns,
+        changed_ns,
+        file_id,
+        owner,
+        flags,
+        link_target,
+        ignore_key,
     } = frame;
 
     let total = aggregate_size + direct_size;
+    let total_allocated = aggregate_allocated + allocated_size;
 
     if let Some(session) = session {
-        let parent_ref = parent.as_deref();
-        session.upsert_entry(
-            &relative,
-            parent_ref,
-            FileKind::Directory,
+        session.upsert_entry(&CachedEntry {
+            path: relative,
+            parent,
+            kind: FileKind::Directory,
             direct_size,
-            total,
+            aggregate_size: total,
             modified,
             created,
-        )?;
+            flags,
+            allocated_size,
+            aggregate_allocated: total_allocated,
+            device: file_id.map(|(device, _)| device),
+            inode: file_id.map(|(_, inode)| inode),
+            link_target,
+            ignore_key: Some(ignore_key),
+            category: None,
+            modified_ns,
+            changed_ns,
+            owner,
+        })?;
     }
 
     if let Some(parent_frame) = parent_frame {
         parent_frame.aggregate_size += total;
+        parent_frame.aggregate_allocated += total_allocated;
     }
 
     Ok(())
 }
 
+/// Reports `error` and, when the scan is cached, stores it under the root so it is
+/// still listed when the tree is reloaded from the cache.
+fn report_error(
+    error: ScanError,
+    ctx: Option<&CacheContext>,
+    session: Option<&mut cache::ScanSession>,
+    events: &Sender<ScanEvent>,
+) {
+    if let (Some(ctx), Some(session)) = (ctx, session)
+        && error.path.starts_with(&ctx.canonical_root)
+    {
+        let stored = ScanError {
+            path: relative_path(&ctx.canonical_root, &error.path),
+            ..error.clone()
+        };
+        if let Err(err) = session.record_error(&stored) {
+            eprintln!("dusk cache error record failed: {err}");
+        }
+    }
+    let _ = events.send(ScanEvent::Error(error));
+}
+
 fn relative_path(root: &Path, path: &Path) -> PathBuf {
     match path.strip_prefix(root) {
         Ok(rel) if rel.as_os_str().is_empty() => PathBuf::from("."),
@@ -603,6 +2287,7 @@ fn parent_relative(path: &Path) -> Option<PathBuf> {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn should_include(
     path: &Path,
     kind: FileKind,
@@ -610,15 +2295,21 @@ fn should_include(
     matcher: Option<&GlobSet>,
     root: &Path,
     size_filter: Option<&SizeFilter>,
+    allocation: Option<Allocation>,
+    allocation_filter: Option<Allocation>,
 ) -> bool {
     if kind == FileKind::Directory {
         return true;
     }
 
-    if let Some(filter) = size_filter {
-        if !filter.matches(direct_size) {
-            return false;
-        }
+    if allocation_filter.is_some() && allocation != allocation_filter {
+        return false;
+    }
+
+    if let Some(filter) = size_filter
+        && !filter.matches(direct_size)
+    {
+        return false;
     }
 
     if let Some(matcher) = matcher {
@@ -627,14 +2318,12 @@ fn should_include(
             return true;
         }
 
-        if let Ok(relative) = path.strip_prefix(root) {
-            if !relative.as_os_str().is_empty() {
-                if let Some(relative_str) = relative.to_str() {
-                    if matcher.is_match(relative_str) {
-                        return true;
-                    }
-                }
-            }
+        if let Ok(relative) = path.strip_prefix(root)
+            && !relative.as_os_str().is_empty()
+            && let Some(relative_str) = relative.to_str()
+            && matcher.is_match(relative_str)
+        {
+            return true;
         }
 
         false
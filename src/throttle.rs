@@ -0,0 +1,214 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::filesystem::{DirEntry, FileReader, FileSystem, FsMetadata};
+
+/// Weight of the newest sample in the smoothed per-operation latency.
+const LATENCY_WEIGHT: f64 = 0.2;
+/// Factor the allowed rate is cut by while latency stays above the target.
+const BACKOFF: f64 = 0.75;
+/// Factor the allowed rate grows by while latency stays well below the target.
+const RECOVERY: f64 = 1.05;
+/// The rate never backs off below this share of the cap.
+const MIN_RATE_SHARE: f64 = 0.05;
+
+/// Settings of low-impact scans, which go easy on disks shared with other users.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LowImpact {
+    /// Upper bound on metadata operations (stats, and one per directory listing) per second.
+    pub max_ops_per_sec: u32,
+    /// Smoothed per-operation latency above which the rate is lowered further.
+    pub latency_target: Duration,
+}
+
+impl Default for LowImpact {
+    fn default() -> Self {
+        Self {
+            max_ops_per_sec: 1000,
+            latency_target: Duration::from_millis(5),
+        }
+    }
+}
+
+/// Paces metadata operations to a rate that starts at `LowImpact::max_ops_per_sec` and backs
+/// off while the measured latency per operation is above `LowImpact::latency_target`. Operations
+/// booked beyond the rate are carried forward, so later callers wait until they are paid off.
+#[derive(Debug)]
+pub struct Throttle {
+    settings: LowImpact,
+    state: Mutex<ThrottleState>,
+}
+
+#[derive(Debug)]
+struct ThrottleState {
+    rate: f64,
+    /// Earliest time the next operation may start.
+    next_free: Instant,
+    latency: Option<f64>,
+}
+
+impl Throttle {
+    pub fn new(settings: LowImpact) -> Self {
+        let rate = f64::from(settings.max_ops_per_sec.max(1));
+        Self {
+            settings,
+            state: Mutex::new(ThrottleState {
+                rate,
+                next_free: Instant::now(),
+                latency: None,
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> LowImpact {
+        self.settings
+    }
+
+    /// Operations per second currently allowed; below the cap while backing off.
+    pub fn rate(&self) -> f64 {
+        self.lock().rate
+    }
+
+    /// Blocks until `ops` more operations may start within the allowed rate.
+    pub fn acquire(&self, ops: u64) {
+        let start = self.reserve(ops);
+        sleep_until(start);
+    }
+
+    /// Books the next `ops` operations, returning when they may start.
+    fn reserve(&self, ops: u64) -> Instant {
+        let mut state = self.lock();
+        let start = state.next_free.max(Instant::now());
+        state.next_free = start + Duration::from_secs_f64(ops as f64 / state.rate);
+        start
+    }
+
+    /// Feeds back that `ops` operations took `elapsed` in total, adjusting the allowed rate.
+    pub fn record(&self, ops: u64, elapsed: Duration) {
+        let sample = elapsed.as_secs_f64() / ops.max(1) as f64;
+        let cap = f64::from(self.settings.max_ops_per_sec.max(1));
+        let target = self.settings.latency_target.as_secs_f64();
+        let mut state = self.lock();
+        let latency = match state.latency {
+            Some(latency) => LATENCY_WEIGHT * sample + (1.0 - LATENCY_WEIGHT) * latency,
+            None => sample,
+        };
+        state.latency = Some(latency);
+        if latency > target {
+            state.rate = (state.rate * BACKOFF).max(cap * MIN_RATE_SHARE).max(1.0);
+        } else if latency < target / 2.0 {
+            state.rate = (state.rate * RECOVERY).min(cap);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ThrottleState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn sleep_until(deadline: Instant) {
+    let wait = deadline.saturating_duration_since(Instant::now());
+    if !wait.is_zero() {
+        thread::sleep(wait);
+    }
+}
+
+/// Passes scan operations through to another filesystem at the pace a `Throttle` allows.
+/// Opening a file counts as one operation; reading it does not. Deletions are not throttled.
+#[derive(Debug)]
+pub struct ThrottledFileSystem {
+    inner: Arc<dyn FileSystem>,
+    throttle: Arc<Throttle>,
+}
+
+impl ThrottledFileSystem {
+    pub fn new(inner: Arc<dyn FileSystem>, throttle: Arc<Throttle>) -> Self {
+        Self { inner, throttle }
+    }
+
+    fn timed<T>(&self, operation: impl FnOnce() -> T) -> T {
+        self.throttle.acquire(1);
+        let started = Instant::now();
+        let result = operation();
+        self.throttle.record(1, started.elapsed());
+        result
+    }
+}
+
+impl FileSystem for ThrottledFileSystem {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        self.timed(|| self.inner.symlink_metadata(path))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        self.timed(|| self.inner.metadata(path))
+    }
+
+    /// Listing a directory stats each entry, so each batch of entries waits for its share of the
+    /// rate before it is stat'ed. Time spent waiting is left out of the measured latency.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.throttle.acquire(1);
+        let started = Instant::now();
+        let mut ops = 1;
+        let mut waited = Duration::ZERO;
+        let result = self.inner.read_dir_paced(path, &mut |count| {
+            let waiting = Instant::now();
+            self.throttle.acquire(count);
+            waited += waiting.elapsed();
+            ops += count;
+        });
+        self.throttle
+            .record(ops, started.elapsed().saturating_sub(waited));
+        result
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.timed(|| self.inner.read_link(path))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.timed(|| self.inner.canonicalize(path))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn FileReader>> {
+        self.timed(|| self.inner.open(path))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_dir_all(path)
+    }
+}
+
+/// Lowers the calling thread's CPU niceness and I/O priority. Threads it spawns afterwards, such
+/// as the walker's, inherit both.
+#[cfg(target_os = "linux")]
+pub fn lower_thread_priority() -> io::Result<()> {
+    const NICENESS: libc::c_int = 10;
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_BEST_EFFORT: libc::c_int = 2;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_LOWEST_LEVEL: libc::c_int = 7;
+
+    // SAFETY: plain syscalls on the calling thread's own id.
+    let tid = unsafe { libc::gettid() };
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, NICENESS) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let priority = (IOPRIO_CLASS_BEST_EFFORT << IOPRIO_CLASS_SHIFT) | IOPRIO_LOWEST_LEVEL;
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, priority) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn lower_thread_priority() -> io::Result<()> {
+    Ok(())
+}
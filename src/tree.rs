@@ -2,11 +2,77 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
-use crate::fs::{FileEntry, FileKind};
+use serde::{Deserialize, Serialize};
+
+use crate::fs::{Allocation, FileCategory, FileEntry, FileKind, Ownership, SizeEstimate};
+
+/// Which byte count drives the Size/Total columns and size sorting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum SizeMetric {
+    #[default]
+    Apparent,
+    Allocated,
+}
+
+/// Bytes per `FileCategory` at or below a node, indexed like `FileCategory::ALL`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CategoryTotals {
+    pub bytes: [u64; FileCategory::ALL.len()],
+    /// Bytes in unscanned directories, whose files are not known yet.
+    pub unclassified: u64,
+}
+
+impl CategoryTotals {
+    pub fn get(&self, category: FileCategory) -> u64 {
+        self.bytes[category as usize]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.bytes.iter().sum::<u64>() + self.unclassified
+    }
+
+    fn add(&mut self, other: &CategoryTotals) {
+        for (total, bytes) in self.bytes.iter_mut().zip(other.bytes) {
+            *total += bytes;
+        }
+        self.unclassified += other.unclassified;
+    }
+}
+
+/// Bytes at or below a node by owning user and by owning group.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnerUsage {
+    pub users: BTreeMap<u32, u64>,
+    pub groups: BTreeMap<u32, u64>,
+    /// Bytes of unscanned directories and of entries scanned without ownership.
+    pub unknown: u64,
+}
+
+impl OwnerUsage {
+    /// Users by bytes owned, largest first.
+    pub fn top_users(&self) -> Vec<(u32, u64)> {
+        largest_first(&self.users)
+    }
+
+    /// Groups by bytes owned, largest first.
+    pub fn top_groups(&self) -> Vec<(u32, u64)> {
+        largest_first(&self.groups)
+    }
+}
+
+fn largest_first(usage: &BTreeMap<u32, u64>) -> Vec<(u32, u64)> {
+    let mut sorted: Vec<(u32, u64)> = usage.iter().map(|(id, bytes)| (*id, *bytes)).collect();
+    sorted.sort_by_key(|(id, bytes)| (std::cmp::Reverse(*bytes), *id));
+    sorted
+}
 
 #[derive(Default)]
 pub struct TreeStore {
     nodes: BTreeMap<PathBuf, TreeNode>,
+    /// Directories whose totals are estimates, kept ordered so subtrees are contiguous ranges.
+    unscanned: BTreeSet<PathBuf>,
+    /// Stamped onto nodes as they are upserted, so a refreshed subtree can drop what was not sent.
+    generation: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -14,15 +80,30 @@ pub struct TreeNode {
     pub name: String,
     pub kind: FileKind,
     pub direct_size: u64,
+    pub allocated_size: u64,
     pub modified: Option<SystemTime>,
     pub created: Option<SystemTime>,
     pub children: BTreeSet<PathBuf>,
     pub contains_match: bool,
+    pub hardlink_duplicate: bool,
+    pub mount_point: bool,
+    pub link_target: Option<PathBuf>,
+    pub dangling_link: bool,
+    pub excluded: bool,
+    pub unscanned: bool,
+    pub estimate: Option<SizeEstimate>,
+    pub category: Option<FileCategory>,
+    pub in_archive: bool,
+    pub compressed_size: Option<u64>,
+    pub allocation: Option<Allocation>,
+    pub owner: Option<Ownership>,
+    generation: u64,
 }
 
 impl TreeStore {
     pub fn clear(&mut self) {
         self.nodes.clear();
+        self.unscanned.clear();
     }
 
     pub fn upsert(&mut self, entry: FileEntry) {
@@ -35,20 +116,93 @@ impl TreeStore {
         node.name = entry.file_name;
         node.kind = entry.kind;
         node.direct_size = entry.direct_size;
+        node.allocated_size = entry.allocated_size;
         node.modified = entry.modified;
         node.created = entry.created;
+        node.hardlink_duplicate = entry.hardlink_duplicate;
+        node.mount_point = entry.mount_point;
+        node.link_target = entry.link_target;
+        node.dangling_link = entry.dangling_link;
+        node.excluded = entry.excluded;
+        node.unscanned = entry.unscanned;
+        node.estimate = entry.estimate;
+        node.category = entry.category;
+        node.in_archive = entry.in_archive;
+        node.compressed_size = entry.compressed_size;
+        node.allocation = entry.allocation;
+        node.owner = entry.owner;
+        node.generation = self.generation;
 
-        if let Some(parent) = path.parent() {
-            if let Some(parent_node) = self.nodes.get_mut(parent) {
-                parent_node.children.insert(path.clone());
-            }
+        if entry.unscanned {
+            self.unscanned.insert(path.clone());
+        } else {
+            self.unscanned.remove(&path);
         }
 
-        if entry.kind == FileKind::File {
+        if let Some(parent) = path.parent()
+            && let Some(parent_node) = self.nodes.get_mut(parent)
+        {
+            parent_node.children.insert(path.clone());
+        }
+
+        // Mount points, excluded and unscanned directories have no children but must stay visible.
+        if entry.kind != FileKind::Directory
+            || entry.mount_point
+            || entry.excluded
+            || entry.unscanned
+        {
             self.mark_contains_match_upwards(&path);
         }
     }
 
+    /// Adds a top node at `path` grouping `roots`, for jobs that scan several roots. Like any
+    /// directory, its total is the sum of its children's.
+    pub fn insert_group(&mut self, path: &Path, name: &str, roots: &[PathBuf]) {
+        self.upsert(FileEntry::new(
+            path.to_path_buf(),
+            name.to_string(),
+            FileKind::Directory,
+            0,
+            0,
+            None,
+            None,
+        ));
+        if let Some(node) = self.nodes.get_mut(path) {
+            node.children.extend(roots.iter().cloned());
+            node.contains_match = true;
+        }
+    }
+
+    /// Starts refreshing a subtree in place; pass the returned generation to `prune_stale` once
+    /// all of its entries were upserted again.
+    pub fn begin_refresh(&mut self) -> u64 {
+        self.generation += 1;
+        self.generation
+    }
+
+    /// Removes `root` and the nodes below it that were not upserted since `begin_refresh`
+    /// returned `generation`, returning their paths.
+    pub fn prune_stale(&mut self, root: &Path, generation: u64) -> Vec<PathBuf> {
+        let stale: Vec<PathBuf> = self
+            .nodes
+            .range(root.to_path_buf()..)
+            .take_while(|(path, _)| path.starts_with(root))
+            .filter(|(_, node)| node.generation < generation)
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        for path in &stale {
+            self.nodes.remove(path);
+            self.unscanned.remove(path);
+            if let Some(parent) = path.parent()
+                && let Some(parent_node) = self.nodes.get_mut(parent)
+            {
+                parent_node.children.remove(path);
+            }
+        }
+        stale
+    }
+
     pub fn get(&self, path: &Path) -> Option<&TreeNode> {
         self.nodes.get(path)
     }
@@ -60,9 +214,18 @@ impl TreeStore {
             .unwrap_or_default()
     }
 
+    /// Whether the total of `path` still depends on an unscanned directory at or below it.
+    pub fn contains_estimate(&self, path: &Path) -> bool {
+        self.unscanned
+            .range(path.to_path_buf()..)
+            .next()
+            .is_some_and(|unscanned| unscanned.starts_with(path))
+    }
+
     pub fn aggregated_size_with_cache(
         &self,
         path: &Path,
+        metric: SizeMetric,
         cache: &mut BTreeMap<PathBuf, u64>,
     ) -> u64 {
         if let Some(size) = cache.get(path) {
@@ -73,10 +236,21 @@ impl TreeStore {
             return 0;
         };
 
-        let mut total = node.direct_size;
-        if node.kind == FileKind::Directory {
+        // Extra hard links are shown with their size but only the first link counts toward totals.
+        let mut total = if node.hardlink_duplicate {
+            0
+        } else {
+            node.size(metric)
+        };
+        // Cached aggregates already include the directory's own size.
+        if node.unscanned {
+            total = node.estimate.map_or(total, |estimate| match metric {
+                SizeMetric::Apparent => estimate.size,
+                SizeMetric::Allocated => estimate.allocated,
+            });
+        } else if node.kind == FileKind::Directory {
             for child in &node.children {
-                total += self.aggregated_size_with_cache(child, cache);
+                total += self.aggregated_size_with_cache(child, metric, cache);
             }
         }
 
@@ -84,14 +258,89 @@ impl TreeStore {
         total
     }
 
+    /// Splits the total of `path` by file category. Directories themselves, symlinks and special
+    /// files are left out; unscanned directories count as unclassified.
+    pub fn category_totals_with_cache(
+        &self,
+        path: &Path,
+        metric: SizeMetric,
+        cache: &mut BTreeMap<PathBuf, CategoryTotals>,
+    ) -> CategoryTotals {
+        if let Some(totals) = cache.get(path) {
+            return *totals;
+        }
+
+        let Some(node) = self.nodes.get(path) else {
+            return CategoryTotals::default();
+        };
+
+        let mut totals = CategoryTotals::default();
+        if node.unscanned {
+            totals.unclassified = node.estimate.map_or(0, |estimate| match metric {
+                SizeMetric::Apparent => estimate.size,
+                SizeMetric::Allocated => estimate.allocated,
+            });
+        } else if node.kind == FileKind::Directory {
+            for child in &node.children {
+                let child_totals = self.category_totals_with_cache(child, metric, cache);
+                totals.add(&child_totals);
+            }
+        } else if let Some(category) = node.category
+            && !node.hardlink_duplicate
+        {
+            totals.bytes[category as usize] = node.size(metric);
+        }
+
+        cache.insert(path.to_path_buf(), totals);
+        totals
+    }
+
+    /// Splits the total of `path` by the user and the group owning each entry, directories
+    /// included, so the sums match `aggregated_size_with_cache`.
+    pub fn owner_usage(&self, path: &Path, metric: SizeMetric) -> OwnerUsage {
+        let mut usage = OwnerUsage::default();
+        self.add_owner_usage(path, metric, &mut usage);
+        usage
+    }
+
+    fn add_owner_usage(&self, path: &Path, metric: SizeMetric, usage: &mut OwnerUsage) {
+        let Some(node) = self.nodes.get(path) else {
+            return;
+        };
+        if node.unscanned {
+            usage.unknown += node.estimate.map_or(0, |estimate| match metric {
+                SizeMetric::Apparent => estimate.size,
+                SizeMetric::Allocated => estimate.allocated,
+            });
+            return;
+        }
+        let bytes = if node.hardlink_duplicate {
+            0
+        } else {
+            node.size(metric)
+        };
+        match node.owner {
+            Some(owner) => {
+                *usage.users.entry(owner.uid).or_default() += bytes;
+                *usage.groups.entry(owner.gid).or_default() += bytes;
+            }
+            None => usage.unknown += bytes,
+        }
+        if node.kind == FileKind::Directory {
+            for child in &node.children {
+                self.add_owner_usage(child, metric, usage);
+            }
+        }
+    }
+
     fn mark_contains_match_upwards(&mut self, start: &Path) {
         let mut current = Some(start.to_path_buf());
         while let Some(path) = current {
             let parent = path.parent().map(|p| p.to_path_buf());
-            if let Some(node) = self.nodes.get_mut(&path) {
-                if !node.contains_match {
-                    node.contains_match = true;
-                }
+            if let Some(node) = self.nodes.get_mut(&path)
+                && !node.contains_match
+            {
+                node.contains_match = true;
             }
             current = parent;
         }
@@ -99,15 +348,36 @@ impl TreeStore {
 }
 
 impl TreeNode {
+    pub fn size(&self, metric: SizeMetric) -> u64 {
+        match metric {
+            SizeMetric::Apparent => self.direct_size,
+            SizeMetric::Allocated => self.allocated_size,
+        }
+    }
+
     fn new(entry: &FileEntry) -> Self {
         Self {
             name: entry.file_name.clone(),
             kind: entry.kind,
             direct_size: entry.direct_size,
+            allocated_size: entry.allocated_size,
             modified: entry.modified,
             created: entry.created,
             children: BTreeSet::new(),
-            contains_match: entry.kind == FileKind::File,
+            contains_match: entry.kind != FileKind::Directory,
+            hardlink_duplicate: entry.hardlink_duplicate,
+            mount_point: entry.mount_point,
+            link_target: entry.link_target.clone(),
+            dangling_link: entry.dangling_link,
+            excluded: entry.excluded,
+            unscanned: entry.unscanned,
+            estimate: entry.estimate,
+            category: entry.category,
+            in_archive: entry.in_archive,
+            compressed_size: entry.compressed_size,
+            allocation: entry.allocation,
+            owner: entry.owner,
+            generation: 0,
         }
     }
 }
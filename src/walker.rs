@@ -0,0 +1,391 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+
+use crate::filesystem::{FileSystem, FsMetadata, RealFileSystem};
+
+const IDLE_PARK: Duration = Duration::from_millis(1);
+/// Directories that may be scheduled for reading ahead of the consumer at once, counting those
+/// read but not yet descended into. Keeps memory bounded however far ahead workers could get.
+pub const READ_AHEAD_DIRS: usize = 256;
+
+/// Decides whether workers should read a directory ahead of the consumer. Directories that are
+/// rejected are still yielded; they are only read if the consumer descends into them.
+pub type DescendFilter = dyn Fn(&Path, &FsMetadata) -> bool + Send + Sync;
+
+#[derive(Debug)]
+pub struct WalkEntry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub metadata: io::Result<FsMetadata>,
+}
+
+#[derive(Debug)]
+pub struct WalkError {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "IO error for operation on {}: {}",
+            self.path.display(),
+            self.error
+        )
+    }
+}
+
+impl std::error::Error for WalkError {}
+
+#[derive(Debug)]
+struct Child {
+    path: PathBuf,
+    metadata: io::Result<FsMetadata>,
+    is_dir: bool,
+    scheduled: bool,
+}
+
+struct Listing {
+    path: PathBuf,
+    children: io::Result<Vec<Child>>,
+}
+
+struct Shared {
+    injector: Injector<PathBuf>,
+    stealers: Vec<Stealer<PathBuf>>,
+    shutdown: AtomicBool,
+    paused: AtomicBool,
+    /// Read-ahead slots in use, up to `READ_AHEAD_DIRS`.
+    read_ahead: AtomicUsize,
+    descend: Box<DescendFilter>,
+    filesystem: Arc<dyn FileSystem>,
+}
+
+impl Shared {
+    fn reserve_read_ahead(&self) -> bool {
+        self.read_ahead
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                (used < READ_AHEAD_DIRS).then_some(used + 1)
+            })
+            .is_ok()
+    }
+
+    fn release_read_ahead(&self) {
+        self.read_ahead.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Multi-threaded directory walker that yields entries in depth-first pre-order.
+///
+/// A pool of work-stealing threads reads directories (and stats their children) ahead of the
+/// consumer, while the iterator itself reassembles the listings so every directory is yielded
+/// before its contents, mirroring `walkdir` with `follow_links(false)`. At most
+/// `READ_AHEAD_DIRS` directories are read ahead at a time; the rest are read when the consumer
+/// descends into them.
+pub struct ParallelWalker {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    listings_rx: Receiver<Listing>,
+    ready: HashMap<PathBuf, io::Result<Vec<Child>>>,
+    /// Read-ahead directories skipped before their listing arrived; it is dropped on arrival.
+    discarded: HashSet<PathBuf>,
+    stack: Vec<std::vec::IntoIter<Child>>,
+    root: Option<PathBuf>,
+    pending_descent: Option<(PathBuf, bool)>,
+}
+
+impl ParallelWalker {
+    pub fn new<F>(root: &Path, threads: usize, descend: F) -> Self
+    where
+        F: Fn(&Path, &FsMetadata) -> bool + Send + Sync + 'static,
+    {
+        Self::with_filesystem(root, threads, Arc::new(RealFileSystem), descend)
+    }
+
+    /// Walks `root` as seen through `filesystem` instead of the host filesystem.
+    pub fn with_filesystem<F>(
+        root: &Path,
+        threads: usize,
+        filesystem: Arc<dyn FileSystem>,
+        descend: F,
+    ) -> Self
+    where
+        F: Fn(&Path, &FsMetadata) -> bool + Send + Sync + 'static,
+    {
+        let threads = threads.max(1);
+        let locals: Vec<Worker<PathBuf>> = (0..threads).map(|_| Worker::new_lifo()).collect();
+        let shared = Arc::new(Shared {
+            injector: Injector::new(),
+            stealers: locals.iter().map(|local| local.stealer()).collect(),
+            shutdown: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            read_ahead: AtomicUsize::new(0),
+            descend: Box::new(descend),
+            filesystem,
+        });
+        // Listings in flight are bounded by the read-ahead slots plus the one the consumer waits
+        // for, so the channel itself need not be.
+        let (listings_tx, listings_rx) = unbounded();
+
+        let workers = locals
+            .into_iter()
+            .enumerate()
+            .map(|(index, local)| {
+                let shared = shared.clone();
+                let tx = listings_tx.clone();
+                thread::Builder::new()
+                    .name(format!("dusk-walker-{index}"))
+                    .spawn(move || worker_loop(local, shared, tx))
+                    .expect("failed to spawn walker thread")
+            })
+            .collect();
+
+        Self {
+            shared,
+            workers,
+            listings_rx,
+            ready: HashMap::new(),
+            discarded: HashSet::new(),
+            stack: Vec::new(),
+            root: Some(root.to_path_buf()),
+            pending_descent: None,
+        }
+    }
+
+    /// Skips the contents of the directory that was yielded last.
+    pub fn skip_current_dir(&mut self) {
+        if let Some((dir, true)) = self.pending_descent.take() {
+            self.discard(dir);
+        }
+    }
+
+    /// Read-ahead slots currently held, out of `READ_AHEAD_DIRS`.
+    pub fn read_ahead_in_use(&self) -> usize {
+        self.shared.read_ahead.load(Ordering::SeqCst)
+    }
+
+    /// Descends into `dir` although it was not yielded as a directory, e.g. a symlink the
+    /// consumer decided to follow. Must be called right after `dir` was yielded.
+    pub fn descend_into(&mut self, dir: PathBuf) {
+        self.pending_descent = Some((dir, false));
+    }
+
+    /// Stops workers from reading ahead until `resume` is called. Directories already being read
+    /// are finished first.
+    pub fn pause(&self) {
+        self.shared.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.shared.paused.store(false, Ordering::SeqCst);
+        for worker in &self.workers {
+            worker.thread().unpark();
+        }
+    }
+
+    fn schedule(&self, dir: PathBuf) {
+        self.shared.injector.push(dir);
+        for worker in &self.workers {
+            worker.thread().unpark();
+        }
+    }
+
+    fn wait_for(&mut self, dir: &Path) -> io::Result<Vec<Child>> {
+        loop {
+            if let Some(children) = self.ready.remove(dir) {
+                return children;
+            }
+            if !self.receive_listing() {
+                return Err(io::Error::other("walker threads exited"));
+            }
+        }
+    }
+
+    /// Receives the next listing, dropping it if its directory was discarded meanwhile. Returns
+    /// `false` once the workers are gone.
+    fn receive_listing(&mut self) -> bool {
+        match self.listings_rx.recv() {
+            Ok(listing) if self.discarded.remove(&listing.path) => {
+                self.drop_listing(listing.children);
+            }
+            Ok(listing) => {
+                self.ready.insert(listing.path, listing.children);
+            }
+            Err(_) => return false,
+        }
+        true
+    }
+
+    /// Gives up on a scheduled directory: its listing is dropped now if it was read already,
+    /// otherwise as soon as it arrives.
+    fn discard(&mut self, dir: PathBuf) {
+        match self.ready.remove(&dir) {
+            Some(children) => self.drop_listing(children),
+            None => {
+                self.discarded.insert(dir);
+            }
+        }
+    }
+
+    /// Releases the slot of a listing nobody will descend into, along with those of the
+    /// subdirectories it scheduled.
+    fn drop_listing(&mut self, children: io::Result<Vec<Child>>) {
+        self.shared.release_read_ahead();
+        for child in children.into_iter().flatten() {
+            if child.scheduled {
+                self.discard(child.path);
+            }
+        }
+    }
+}
+
+impl Iterator for ParallelWalker {
+    type Item = Result<WalkEntry, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            let metadata = self.shared.filesystem.metadata(&root);
+            if metadata.as_ref().is_ok_and(|meta| meta.is_dir()) {
+                self.pending_descent = Some((root.clone(), false));
+            }
+            return Some(Ok(WalkEntry {
+                path: root,
+                depth: 0,
+                metadata,
+            }));
+        }
+
+        if let Some((dir, scheduled)) = self.pending_descent.take() {
+            if scheduled {
+                self.shared.release_read_ahead();
+            } else {
+                self.schedule(dir.clone());
+            }
+            match self.wait_for(&dir) {
+                Ok(children) => self.stack.push(children.into_iter()),
+                Err(error) => return Some(Err(WalkError { path: dir, error })),
+            }
+        }
+
+        loop {
+            let depth = self.stack.len();
+            let Some(children) = self.stack.last_mut() else {
+                // Settle listings still in flight for skipped directories so every slot is
+                // released by the end of the walk.
+                while !self.discarded.is_empty() && self.receive_listing() {}
+                return None;
+            };
+            match children.next() {
+                Some(child) => {
+                    if child.is_dir {
+                        self.pending_descent = Some((child.path.clone(), child.scheduled));
+                    }
+                    return Some(Ok(WalkEntry {
+                        path: child.path,
+                        depth,
+                        metadata: child.metadata,
+                    }));
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ParallelWalker {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        for worker in &self.workers {
+            worker.thread().unpark();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker_loop(local: Worker<PathBuf>, shared: Arc<Shared>, tx: Sender<Listing>) {
+    while !shared.shutdown.load(Ordering::SeqCst) {
+        if shared.paused.load(Ordering::SeqCst) {
+            thread::park_timeout(IDLE_PARK);
+            continue;
+        }
+        match find_task(&local, &shared) {
+            Some(dir) => {
+                let listing = read_listing(dir, &local, &shared);
+                if tx.send(listing).is_err() {
+                    break;
+                }
+            }
+            None => thread::park_timeout(IDLE_PARK),
+        }
+    }
+}
+
+fn find_task(local: &Worker<PathBuf>, shared: &Shared) -> Option<PathBuf> {
+    local.pop().or_else(|| {
+        iter::repeat_with(|| {
+            shared.injector.steal_batch_and_pop(local).or_else(|| {
+                shared
+                    .stealers
+                    .iter()
+                    .map(|stealer| stealer.steal())
+                    .collect::<Steal<PathBuf>>()
+            })
+        })
+        .find(|steal| !steal.is_retry())
+        .and_then(|steal| steal.success())
+    })
+}
+
+fn read_listing(dir: PathBuf, local: &Worker<PathBuf>, shared: &Shared) -> Listing {
+    let entries = match shared.filesystem.read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            return Listing {
+                path: dir,
+                children: Err(err),
+            };
+        }
+    };
+
+    let mut children = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let path = entry.path;
+        let metadata = entry.metadata;
+        let is_dir = metadata.as_ref().is_ok_and(|meta| meta.is_dir());
+        let scheduled = is_dir
+            && !shared.shutdown.load(Ordering::SeqCst)
+            && metadata
+                .as_ref()
+                .is_ok_and(|meta| (shared.descend)(&path, meta))
+            && shared.reserve_read_ahead();
+        if scheduled {
+            local.push(path.clone());
+        }
+        children.push(Child {
+            path,
+            metadata,
+            is_dir,
+            scheduled,
+        });
+    }
+
+    Listing {
+        path: dir,
+        children: Ok(children),
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
@@ -126,13 +126,13 @@ pub fn spawn_with_config(
 }
 
 fn run_notify_loop(
-    root: &PathBuf,
+    root: &Path,
     shutdown: &Arc<AtomicBool>,
     event_tx: &Sender<WatchEvent>,
     config: &WatcherConfig,
 ) -> Result<(), String> {
     let tx = event_tx.clone();
-    let root_clone = root.clone();
+    let root_clone = root.to_path_buf();
     let mut watcher = RecommendedWatcher::new(
         move |event: Result<Event, notify::Error>| match event {
             Ok(event) => {
@@ -170,7 +170,7 @@ fn run_notify_loop(
 }
 
 fn run_polling_loop(
-    root: &PathBuf,
+    root: &Path,
     shutdown: &Arc<AtomicBool>,
     event_tx: &Sender<WatchEvent>,
     config: &WatcherConfig,
@@ -184,18 +184,18 @@ fn run_polling_loop(
             break;
         }
         trace!("dusk watcher polling tick interval={:?}", interval);
-        let _ = event_tx.send(WatchEvent::rescan(root.clone()));
+        let _ = event_tx.send(WatchEvent::rescan(root.to_path_buf()));
         interval = (interval * 2).min(max_interval);
     }
 }
 
-fn map_event_kind(kind: &EventKind, path: PathBuf, root: &PathBuf) -> Option<WatchEvent> {
+fn map_event_kind(kind: &EventKind, path: PathBuf, root: &Path) -> Option<WatchEvent> {
     match kind {
         EventKind::Remove(_) => Some(WatchEvent::dirty(path)),
         EventKind::Create(_) => Some(WatchEvent::dirty(path)),
         EventKind::Modify(_) => Some(WatchEvent::dirty(path)),
         EventKind::Access(_) => None,
-        EventKind::Other => Some(WatchEvent::rescan(root.clone())),
-        EventKind::Any => Some(WatchEvent::rescan(root.clone())),
+        EventKind::Other => Some(WatchEvent::rescan(root.to_path_buf())),
+        EventKind::Any => Some(WatchEvent::rescan(root.to_path_buf())),
     }
 }
@@ -0,0 +1,203 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crossbeam_channel::Receiver;
+use disk_space_inspect::cache::Cache;
+use disk_space_inspect::fs::{FileEntry, FileKind};
+use disk_space_inspect::query::SearchQuery;
+use disk_space_inspect::scanner::{
+    self, CacheContext, ScanMessage, ScanStats, ScannerConfig, ScannerHandle,
+};
+
+fn write_tar<W: Write>(writer: W, files: &[(&str, &[u8])]) -> W {
+    let mut builder = tar::Builder::new(writer);
+    for (name, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, name, *contents)
+            .expect("append tar member");
+    }
+    builder.into_inner().expect("finish tar")
+}
+
+fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+    let mut writer = zip::ZipWriter::new(File::create(path).expect("create zip"));
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, contents) in files {
+        writer.start_file(*name, options).expect("start zip member");
+        writer.write_all(contents).expect("write zip member");
+    }
+    writer.finish().expect("finish zip");
+}
+
+fn scan(
+    handle: &ScannerHandle,
+    rx: &Receiver<ScanMessage>,
+    ctx: &CacheContext,
+) -> (ScanStats, Vec<FileEntry>) {
+    let query = SearchQuery {
+        root: ctx.canonical_root.clone(),
+        raw: ctx.canonical_root.display().to_string(),
+        ..SearchQuery::default()
+    };
+    let job_id = handle.request_scan(query, Some(ctx.clone()));
+    let mut stats = ScanStats::default();
+    let mut entries = Vec::new();
+    while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+        match message {
+            ScanMessage::Entries {
+                job_id: id,
+                entries: batch,
+            } if id == job_id => entries.extend(batch),
+            ScanMessage::Stats {
+                job_id: id,
+                stats: s,
+            } if id == job_id => stats = s,
+            ScanMessage::Complete { job_id: id } if id == job_id => break,
+            _ => {}
+        }
+    }
+    (stats, entries)
+}
+
+fn members_of<'a>(entries: &'a [FileEntry], archive: &Path) -> Vec<&'a FileEntry> {
+    entries
+        .iter()
+        .filter(|entry| entry.in_archive && entry.path.starts_with(archive))
+        .collect()
+}
+
+#[test]
+fn archive_members_are_listed_as_virtual_children_and_cached() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let root = temp.path().canonicalize().expect("canonical root");
+    let files: &[(&str, &[u8])] = &[
+        ("docs/readme.txt", &[b'r'; 300]),
+        ("docs/deep/notes.md", &[b'n'; 40]),
+        ("video.mp4", &[0; 5000]),
+    ];
+    let archives: Vec<PathBuf> = [
+        "bundle.zip",
+        "bundle.tar",
+        "bundle.tar.gz",
+        "bundle.tar.zst",
+    ]
+    .iter()
+    .map(|name| root.join(name))
+    .collect();
+    write_zip(&archives[0], files);
+    write_tar(File::create(&archives[1]).expect("create tar"), files);
+    write_tar(
+        flate2::write::GzEncoder::new(
+            File::create(&archives[2]).expect("create tar.gz"),
+            flate2::Compression::default(),
+        ),
+        files,
+    )
+    .finish()
+    .expect("finish gzip");
+    write_tar(
+        zstd::Encoder::new(File::create(&archives[3]).expect("create tar.zst"), 3)
+            .expect("zstd encoder"),
+        files,
+    )
+    .finish()
+    .expect("finish zstd");
+
+    let cache_dir = tempfile::tempdir().expect("cache temp");
+    let cache = Cache::open_in_path(cache_dir.path().join("cache.sqlite")).expect("open cache");
+    let root_cache = cache.load_root(&root).expect("load root");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: root.clone(),
+    };
+    let (handle, rx) = scanner::spawn_with_config(ScannerConfig {
+        expand_archives: true,
+        ..ScannerConfig::default()
+    });
+
+    let (stats, entries) = scan(&handle, &rx, &ctx);
+    assert_eq!(stats.archives_listed, 4);
+    assert_eq!(stats.archives_cached, 0);
+    for archive in &archives {
+        let members = members_of(&entries, archive);
+        let paths: Vec<PathBuf> = members
+            .iter()
+            .map(|entry| entry.path.strip_prefix(archive).unwrap().to_path_buf())
+            .collect();
+        // Parents come first, including directories only implied by member paths.
+        assert_eq!(
+            paths,
+            [
+                "docs",
+                "docs/deep",
+                "docs/deep/notes.md",
+                "docs/readme.txt",
+                "video.mp4"
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>(),
+            "members of {}",
+            archive.display()
+        );
+        assert_eq!(members[0].kind, FileKind::Directory);
+        let video = members[4];
+        assert_eq!(video.direct_size, 5000);
+        let compressed = if archive.ends_with("bundle.zip") || archive.ends_with("bundle.tar") {
+            Some(5000)
+        } else {
+            None
+        };
+        assert_eq!(video.compressed_size, compressed, "{}", archive.display());
+    }
+    // Members are never written to the cache as real entries.
+    assert!(
+        cache
+            .entry(ctx.root_id, Path::new("bundle.zip/video.mp4"))
+            .expect("query cache")
+            .is_none()
+    );
+    let root_total = cache
+        .entry(ctx.root_id, Path::new("."))
+        .expect("query cache")
+        .expect("cached root")
+        .aggregate_size;
+    let archive_bytes: u64 = archives
+        .iter()
+        .map(|path| fs::metadata(path).expect("stat archive").len())
+        .sum();
+    assert_eq!(root_total, archive_bytes);
+
+    // A replayed root reuses every listing.
+    let (stats, entries) = scan(&handle, &rx, &ctx);
+    assert_eq!(stats.archives_cached, 4);
+    assert_eq!(stats.archives_listed, 0);
+    assert_eq!(members_of(&entries, &archives[2]).len(), 5);
+
+    // A rewritten archive is listed again; the others still come from the cache.
+    write_tar(
+        File::create(&archives[1]).expect("rewrite tar"),
+        &[("only.txt", b"1")],
+    );
+    for path in [&root, &archives[1]] {
+        File::options()
+            .read(true)
+            .open(path)
+            .and_then(|file| file.set_modified(SystemTime::now() + Duration::from_secs(5)))
+            .expect("bump mtime");
+    }
+    let (stats, entries) = scan(&handle, &rx, &ctx);
+    assert_eq!(stats.archives_listed, 1);
+    assert_eq!(stats.archives_cached, 3);
+    let members = members_of(&entries, &archives[1]);
+    assert_eq!(members.len(), 1);
+    assert_eq!(members[0].path, archives[1].join("only.txt"));
+}
@@ -1,10 +1,9 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
 
 use disk_space_inspect::cache::Cache;
-use disk_space_inspect::query::SearchQuery;
-use disk_space_inspect::scanner::{self, CacheContext, ScanMessage, ScanStats};
+use disk_space_inspect::scanner::{self, ScanOptions};
+use disk_space_inspect::tree::SizeMetric;
 use serde::Deserialize;
 use tempfile::TempDir;
 
@@ -45,55 +44,15 @@ fn make_cache() -> (Cache, TempDir) {
     (cache, dir)
 }
 
-fn make_query(root: &Path) -> SearchQuery {
-    let mut query = SearchQuery::default();
-    query.root = root.to_path_buf();
-    query.raw = root.display().to_string();
-    query
-}
-
-fn run_scan(
-    handle: &scanner::ScannerHandle,
-    rx: &crossbeam_channel::Receiver<ScanMessage>,
-    query: SearchQuery,
-    ctx: CacheContext,
-) -> ScanStats {
-    let job_id = handle.request_scan(query, Some(ctx));
-    let mut stats = ScanStats::default();
-    let timeout = Duration::from_secs(5);
-    while let Ok(message) = rx.recv_timeout(timeout) {
-        match message {
-            ScanMessage::Stats {
-                job_id: msg_id,
-                stats: s,
-            } if msg_id == job_id => {
-                stats = s;
-            }
-            ScanMessage::Complete { job_id: msg_id } if msg_id == job_id => {
-                break;
-            }
-            _ => {}
-        }
-    }
-    stats
-}
-
 #[test]
 fn benchmark_snapshots_stay_stable() {
     for name in ["tiny", "medium", "large"] {
         let snapshot = load_snapshot(name);
         let root = sample_root(name);
         let (cache, _cache_dir) = make_cache();
-        let root_cache = cache.load_root(&root).expect("load root cache");
-        let (scanner, rx) = scanner::spawn();
-        let ctx = CacheContext {
-            cache: cache.clone(),
-            root_id: root_cache.root_id,
-            canonical_root: root.clone(),
-        };
-
-        let query = make_query(&root);
-        let stats = run_scan(&scanner, &rx, query, ctx);
+        let result = scanner::scan(&root, ScanOptions::new().cache(cache.clone()));
+        let stats = result.stats;
+        assert!(result.errors.is_empty(), "{name}: {:?}", result.errors);
         assert_eq!(
             stats.files_scanned, snapshot.files_scanned,
             "files scanned mismatch for {name}"
@@ -102,6 +61,7 @@ fn benchmark_snapshots_stay_stable() {
             stats.dirs_scanned, snapshot.dirs_scanned,
             "dirs scanned mismatch for {name}"
         );
+        let root_cache = cache.load_root(&root).expect("load root cache");
         let summary = cache
             .validate_aggregate(root_cache.root_id, Path::new("."))
             .expect("aggregate validation");
@@ -109,5 +69,10 @@ fn benchmark_snapshots_stay_stable() {
             summary.total_size, snapshot.total_size,
             "total size mismatch for {name}"
         );
+        assert_eq!(
+            result.total_size(SizeMetric::Apparent),
+            snapshot.total_size,
+            "tree total mismatch for {name}"
+        );
     }
 }
@@ -1,10 +1,15 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
-use disk_space_inspect::cache::Cache;
+use disk_space_inspect::cache::{self, Cache};
+use disk_space_inspect::fs::{Allocation, FileCategory};
 use disk_space_inspect::query::SearchQuery;
-use disk_space_inspect::scanner::{self, CacheContext, ScanMessage, ScanStats};
+use disk_space_inspect::scanner::{
+    self, CacheContext, RootScan, ScanMessage, ScanStats, ScannerConfig,
+};
+use disk_space_inspect::tree::{SizeMetric, TreeStore};
 use tempfile::TempDir;
 
 fn create_file(path: &Path, contents: &str) {
@@ -19,10 +24,11 @@ fn canonical_temp_dir() -> (TempDir, PathBuf) {
 }
 
 fn make_query(root: &Path) -> SearchQuery {
-    let mut query = SearchQuery::default();
-    query.root = root.to_path_buf();
-    query.raw = root.display().to_string();
-    query
+    SearchQuery {
+        root: root.to_path_buf(),
+        raw: root.display().to_string(),
+        ..SearchQuery::default()
+    }
 }
 
 fn make_cache() -> (Cache, TempDir) {
@@ -106,3 +112,931 @@ fn cache_reuses_directories_on_subsequent_scans() {
 
     drop(temp_root);
 }
+
+#[cfg(unix)]
+#[test]
+fn directories_changed_within_the_same_second_are_walked_again() {
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    let dir = canonical_root.join("a");
+    create_file(&dir.join("one.txt"), "one");
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("clock after epoch");
+    let second = SystemTime::UNIX_EPOCH + Duration::from_secs(now.as_secs());
+    let set_mtime = |nanos: u64| {
+        for path in [&canonical_root, &dir] {
+            fs::File::open(path)
+                .and_then(|file| file.set_modified(second + Duration::from_nanos(nanos)))
+                .expect("set mtime");
+        }
+    };
+    set_mtime(100);
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let (scanner, rx) = scanner::spawn();
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+    next_scan(&scanner, &rx, make_query(&canonical_root), ctx.clone());
+    let cached = cache
+        .entry(ctx.root_id, Path::new("a"))
+        .expect("query a")
+        .expect("cached a");
+    assert_eq!(cached.modified_ns.map(|ns| ns % 1_000_000_000), Some(100));
+    assert!(cached.changed_ns.is_some() && cached.inode.is_some());
+
+    // Same whole-second mtime as before, so only the finer stamps tell the change apart.
+    create_file(&dir.join("two.txt"), "two");
+    set_mtime(900);
+    let stats = next_scan(&scanner, &rx, make_query(&canonical_root), ctx.clone());
+    assert_eq!(stats.cached_dirs, 0);
+    let root = cache
+        .entry(ctx.root_id, Path::new("."))
+        .expect("query root")
+        .expect("root entry");
+    assert_eq!(root.aggregate_size, 6);
+
+    drop(temp_root);
+}
+
+#[cfg(unix)]
+#[test]
+fn cache_records_allocated_bytes_for_sparse_files() {
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    let sparse_path = canonical_root.join("disk.img");
+    let file = fs::File::create(&sparse_path).expect("create sparse file");
+    file.set_len(8 * 1024 * 1024).expect("extend sparse file");
+    drop(file);
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let (scanner, rx) = scanner::spawn();
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+
+    next_scan(&scanner, &rx, make_query(&canonical_root), ctx);
+
+    let root = cache
+        .entry(root_cache.root_id, Path::new("."))
+        .expect("query root")
+        .expect("root entry");
+    assert_eq!(root.aggregate_size, 8 * 1024 * 1024);
+    assert!(
+        root.aggregate_allocated < root.aggregate_size,
+        "sparse file should allocate less than its length ({} >= {})",
+        root.aggregate_allocated,
+        root.aggregate_size
+    );
+    let image = cache
+        .entry(root_cache.root_id, Path::new("disk.img"))
+        .expect("query image")
+        .expect("image entry");
+    assert_ne!(image.flags & cache::FLAG_SPARSE, 0);
+    assert_eq!(image.allocation(), Some(Allocation::Sparse));
+
+    drop(temp_root);
+}
+
+#[cfg(unix)]
+#[test]
+fn hard_links_are_counted_once_across_cached_rescans() {
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    let original = canonical_root.join("a/data.bin");
+    create_file(&original, &"x".repeat(1000));
+    fs::create_dir_all(canonical_root.join("b")).expect("create b");
+    fs::hard_link(&original, canonical_root.join("b/data.bin")).expect("hard link");
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+    let root_total = || {
+        cache
+            .entry(root_cache.root_id, Path::new("."))
+            .expect("query root")
+            .expect("root entry")
+            .aggregate_size
+    };
+
+    let (scanner, rx) = scanner::spawn();
+    let cold = next_scan(&scanner, &rx, make_query(&canonical_root), ctx.clone());
+    assert_eq!(cold.hardlink_duplicates, 1);
+    assert_eq!(root_total(), 1000);
+
+    let warm = next_scan(&scanner, &rx, make_query(&canonical_root), ctx.clone());
+    assert!(warm.cached_dirs >= 2, "expected cache reuse");
+    assert_eq!(warm.hardlink_duplicates, 1);
+    assert_eq!(warm.cache_validation_errors, 0);
+    assert_eq!(root_total(), 1000);
+
+    let (counting, counting_rx) = scanner::spawn_with_config(ScannerConfig {
+        count_hard_links: true,
+        ..ScannerConfig::default()
+    });
+    let counted = next_scan(&counting, &counting_rx, make_query(&canonical_root), ctx);
+    assert_eq!(counted.hardlink_duplicates, 0);
+    assert_eq!(counted.cache_validation_errors, 0);
+    assert_eq!(root_total(), 2000);
+
+    drop(temp_root);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_and_special_files_are_cached_with_their_kind() {
+    use disk_space_inspect::cache::FLAG_DANGLING_LINK;
+    use disk_space_inspect::fs::FileKind;
+    use std::os::unix::fs::symlink;
+    use std::os::unix::net::UnixListener;
+
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    let dir = canonical_root.join("dir");
+    create_file(&dir.join("target.txt"), "contents");
+    symlink("target.txt", dir.join("link")).expect("symlink");
+    symlink("missing.txt", dir.join("dangling")).expect("dangling symlink");
+    let _listener = UnixListener::bind(dir.join("socket")).expect("bind socket");
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+    let cached = |relative: &str| {
+        cache
+            .entry(root_cache.root_id, Path::new(relative))
+            .expect("query entry")
+            .expect("cached entry")
+    };
+
+    let (scanner, rx) = scanner::spawn();
+    let cold = next_scan(&scanner, &rx, make_query(&canonical_root), ctx.clone());
+    assert_eq!(cold.files_scanned, 1);
+    assert_eq!(cold.special_files_scanned, 3);
+
+    let link = cached("dir/link");
+    assert_eq!(link.kind, FileKind::Symlink);
+    assert_eq!(link.link_target.as_deref(), Some(Path::new("target.txt")));
+    assert_eq!(link.flags & FLAG_DANGLING_LINK, 0);
+    assert_ne!(cached("dir/dangling").flags & FLAG_DANGLING_LINK, 0);
+    assert_eq!(cached("dir/socket").kind, FileKind::Socket);
+    // The link is sized by its target path, never by the file it points at.
+    assert_eq!(
+        cached("dir").aggregate_size,
+        "contents".len() as u64 + "target.txt".len() as u64 + "missing.txt".len() as u64
+    );
+
+    let warm = next_scan(&scanner, &rx, make_query(&canonical_root), ctx);
+    assert!(warm.cached_dirs >= 1, "expected cache reuse");
+    assert_eq!(warm.cache_validation_errors, 0);
+    assert_eq!(cached("dir/link").kind, FileKind::Symlink);
+
+    drop(temp_root);
+}
+
+#[cfg(unix)]
+#[test]
+fn followed_symlinks_are_counted_once_and_revalidated() {
+    use std::os::unix::fs::symlink;
+
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    let (temp_external, external) = canonical_temp_dir();
+    create_file(&external.join("shared.bin"), &"x".repeat(100));
+    fs::create_dir_all(external.join("sub")).expect("create sub");
+    symlink(&external, external.join("sub/back")).expect("loop symlink");
+    create_file(&canonical_root.join("local/own.txt"), "own");
+    symlink(&external, canonical_root.join("ext")).expect("symlink");
+    symlink(&external, canonical_root.join("ext_again")).expect("second symlink");
+    symlink(canonical_root.join("local"), canonical_root.join("inner")).expect("inner symlink");
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+    let link_len = |path: PathBuf| fs::symlink_metadata(path).expect("lstat").len();
+    let root_total = || {
+        cache
+            .entry(root_cache.root_id, Path::new("."))
+            .expect("query root")
+            .expect("root entry")
+            .aggregate_size
+    };
+    let unfollowed = link_len(canonical_root.join("ext_again"))
+        + link_len(canonical_root.join("inner"))
+        + "own".len() as u64;
+    let external_total = 100 + link_len(external.join("sub/back"));
+
+    let (following, following_rx) = scanner::spawn_with_config(ScannerConfig {
+        follow_symlinks: true,
+        ..ScannerConfig::default()
+    });
+    let cold = next_scan(
+        &following,
+        &following_rx,
+        make_query(&canonical_root),
+        ctx.clone(),
+    );
+    assert_eq!(cold.symlinks_followed, 1);
+    assert_eq!(root_total(), external_total + unfollowed);
+
+    let warm = next_scan(
+        &following,
+        &following_rx,
+        make_query(&canonical_root),
+        ctx.clone(),
+    );
+    assert!(warm.cached_dirs >= 1, "expected cache reuse");
+    assert_eq!(warm.cache_validation_errors, 0);
+    assert_eq!(root_total(), external_total + unfollowed);
+
+    // Changes behind the link do not touch any mtime inside the root but must still be picked up.
+    create_file(&external.join("new.bin"), &"y".repeat(50));
+    fs::File::open(&external)
+        .and_then(|dir| dir.set_modified(SystemTime::now() + Duration::from_secs(5)))
+        .expect("bump external mtime");
+    let changed = next_scan(
+        &following,
+        &following_rx,
+        make_query(&canonical_root),
+        ctx.clone(),
+    );
+    assert_eq!(changed.cache_validation_errors, 0);
+    assert_eq!(root_total(), external_total + 50 + unfollowed);
+
+    let (plain, plain_rx) = scanner::spawn();
+    let unfollowing = next_scan(&plain, &plain_rx, make_query(&canonical_root), ctx);
+    assert_eq!(unfollowing.symlinks_followed, 0);
+    assert_eq!(unfollowing.cache_validation_errors, 0);
+    assert_eq!(
+        root_total(),
+        link_len(canonical_root.join("ext")) + unfollowed
+    );
+
+    drop(temp_external);
+    drop(temp_root);
+}
+
+#[test]
+fn ignore_rules_exclude_subtrees_and_key_the_cache() {
+    use disk_space_inspect::cache::FLAG_EXCLUDED;
+
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    create_file(&canonical_root.join("src/main.rs"), "fn main() {}");
+    create_file(&canonical_root.join("src/debug.log"), &"l".repeat(40));
+    create_file(&canonical_root.join("src/.duskignore"), "*.log\n");
+    create_file(
+        &canonical_root.join("node_modules/dep/index.js"),
+        &"j".repeat(500),
+    );
+    create_file(&canonical_root.join("build/out.bin"), &"b".repeat(300));
+    create_file(&canonical_root.join(".gitignore"), "build/\n");
+    create_file(&canonical_root.join(".duskignore"), "node_modules/\n");
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+    let root_total = || {
+        cache
+            .entry(root_cache.root_id, Path::new("."))
+            .expect("query root")
+            .expect("root entry")
+            .aggregate_size
+    };
+    let ignore_files = "*.log\n".len() + "build/\n".len() + "node_modules/\n".len();
+    let base = ("fn main() {}".len() + ignore_files) as u64;
+
+    let (scanner, rx) = scanner::spawn();
+    let cold = next_scan(&scanner, &rx, make_query(&canonical_root), ctx.clone());
+    assert_eq!(cold.excluded_dirs, 1);
+    assert_eq!(root_total(), base + 300);
+    let marker = cache
+        .entry(root_cache.root_id, Path::new("node_modules"))
+        .expect("query marker")
+        .expect("excluded directory is cached as a marker");
+    assert_ne!(marker.flags & FLAG_EXCLUDED, 0);
+    assert!(
+        cache
+            .entry(root_cache.root_id, Path::new("src/debug.log"))
+            .expect("query log")
+            .is_none()
+    );
+
+    let warm = next_scan(&scanner, &rx, make_query(&canonical_root), ctx.clone());
+    assert!(warm.cached_dirs >= 1, "expected cache reuse");
+    assert_eq!(warm.excluded_dirs, 1);
+    assert_eq!(root_total(), base + 300);
+
+    // Switching on .gitignore support changes the rules, so the cached root must not be reused.
+    let (git_scanner, git_rx) = scanner::spawn_with_config(ScannerConfig {
+        respect_gitignore: true,
+        ..ScannerConfig::default()
+    });
+    let git_aware = next_scan(
+        &git_scanner,
+        &git_rx,
+        make_query(&canonical_root),
+        ctx.clone(),
+    );
+    assert_eq!(git_aware.excluded_dirs, 2);
+    assert_eq!(git_aware.cache_validation_errors, 0);
+    assert_eq!(root_total(), base);
+
+    // Editing an ignore file in place changes the rules without touching any directory mtime.
+    fs::write(canonical_root.join(".duskignore"), "# nothing\n").expect("rewrite ignore");
+    let edited = next_scan(&scanner, &rx, make_query(&canonical_root), ctx);
+    assert_eq!(edited.excluded_dirs, 0);
+    assert_eq!(edited.cache_validation_errors, 0);
+    assert_eq!(
+        root_total(),
+        base - "node_modules/\n".len() as u64 + "# nothing\n".len() as u64 + 300 + 500
+    );
+
+    drop(temp_root);
+}
+
+#[test]
+fn entries_are_batched_for_walked_and_cached_subtrees() {
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    for dir in ["a", "b", "c"] {
+        for index in 0..5 {
+            create_file(&canonical_root.join(dir).join(format!("{index}.txt")), dir);
+        }
+    }
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+
+    let (scanner, rx) = scanner::spawn_with_config(ScannerConfig {
+        batch_size: 4,
+        flush_interval: Duration::from_secs(60),
+        channel_capacity: 1,
+        ..ScannerConfig::default()
+    });
+    let collect_batches = || {
+        let job_id = scanner.request_scan(make_query(&canonical_root), Some(ctx.clone()));
+        let mut batches = Vec::new();
+        while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+            match message {
+                ScanMessage::Entries {
+                    job_id: msg_id,
+                    entries,
+                } if msg_id == job_id => batches.push(entries.len()),
+                ScanMessage::Complete { job_id: msg_id } if msg_id == job_id => break,
+                _ => {}
+            }
+        }
+        batches
+    };
+
+    // Root, three directories and fifteen files.
+    for batches in [collect_batches(), collect_batches()] {
+        assert_eq!(batches.iter().sum::<usize>(), 19);
+        assert!(batches.iter().all(|&len| (1..=4).contains(&len)));
+        assert!(batches[..batches.len() - 1].iter().all(|&len| len == 4));
+    }
+
+    drop(temp_root);
+}
+
+#[test]
+fn progress_reports_completion_against_the_cached_total() {
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    create_file(&canonical_root.join("a/one.bin"), &"1".repeat(400));
+    create_file(&canonical_root.join("b/two.bin"), &"2".repeat(600));
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+
+    let (scanner, rx) = scanner::spawn_with_config(ScannerConfig {
+        progress_interval: Duration::ZERO,
+        ..ScannerConfig::default()
+    });
+    let collect_progress = || {
+        let job_id = scanner.request_scan(make_query(&canonical_root), Some(ctx.clone()));
+        let mut reports = Vec::new();
+        while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+            match message {
+                ScanMessage::Progress {
+                    job_id: msg_id,
+                    progress,
+                } if msg_id == job_id => reports.push(progress),
+                ScanMessage::Complete { job_id: msg_id } if msg_id == job_id => break,
+                _ => {}
+            }
+        }
+        reports
+    };
+
+    let cold = collect_progress();
+    assert!(!cold.is_empty());
+    assert!(cold.iter().all(|report| report.fraction.is_none()));
+    assert!(cold.windows(2).all(|pair| pair[0].bytes <= pair[1].bytes));
+
+    let warm = collect_progress();
+    let last = warm.last().expect("progress during warm scan");
+    assert_eq!(last.bytes, 1000);
+    assert_eq!(last.files, 2);
+    let fraction = last.fraction.expect("fraction from cached total");
+    assert!((fraction - 1.0).abs() < f32::EPSILON);
+    assert!(
+        warm.iter()
+            .all(|report| report.eta.is_some() || report.bytes == 0)
+    );
+
+    drop(temp_root);
+}
+
+#[test]
+fn paused_scans_hold_their_walk_until_resumed() {
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    for index in 0..20 {
+        create_file(&canonical_root.join(format!("d{index}/f.txt")), "data");
+    }
+
+    let (scanner, rx) = scanner::spawn_with_config(ScannerConfig {
+        batch_size: 1,
+        ..ScannerConfig::default()
+    });
+    // Pausing a queued job takes effect as soon as it starts walking.
+    let job_id = scanner.request_scan(make_query(&canonical_root), None);
+    scanner.pause(job_id);
+
+    let mut entries_before_pause = 0;
+    loop {
+        match rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("scan message")
+        {
+            ScanMessage::Entries { entries, .. } => entries_before_pause += entries.len(),
+            ScanMessage::Paused { job_id: msg_id } if msg_id == job_id => break,
+            ScanMessage::Complete { .. } => panic!("scan completed while paused"),
+            _ => {}
+        }
+    }
+    assert!(
+        rx.recv_timeout(Duration::from_millis(200)).is_err(),
+        "paused scan kept sending messages"
+    );
+
+    scanner.resume(job_id);
+    let mut entries_after_resume = 0;
+    let mut resumed = false;
+    loop {
+        match rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("scan message")
+        {
+            ScanMessage::Resumed { job_id: msg_id } if msg_id == job_id => resumed = true,
+            ScanMessage::Entries { entries, .. } => entries_after_resume += entries.len(),
+            ScanMessage::Complete { job_id: msg_id } if msg_id == job_id => break,
+            _ => {}
+        }
+    }
+    assert!(resumed);
+    // Root, twenty directories and twenty files, none lost or repeated across the pause.
+    assert_eq!(entries_before_pause + entries_after_resume, 41);
+
+    drop(temp_root);
+}
+
+#[test]
+fn depth_limited_scans_estimate_deeper_directories_until_expanded() {
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    create_file(&canonical_root.join("top.txt"), &"t".repeat(10));
+    create_file(&canonical_root.join("a/b/c/deep.bin"), &"d".repeat(100));
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+    let (scanner, rx) = scanner::spawn();
+    next_scan(&scanner, &rx, make_query(&canonical_root), ctx.clone());
+
+    let lazy_query = SearchQuery {
+        max_depth: Some(1),
+        ..make_query(&canonical_root)
+    };
+    let job_id = scanner.request_scan(lazy_query.clone(), Some(ctx.clone()));
+    let mut entries = Vec::new();
+    let mut stats = ScanStats::default();
+    while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+        match message {
+            ScanMessage::Entries { entries: batch, .. } => entries.extend(batch),
+            ScanMessage::Stats { stats: s, .. } => stats = s,
+            ScanMessage::Complete { .. } => break,
+            _ => {}
+        }
+    }
+    assert_eq!(entries.len(), 3, "root, top.txt and the unscanned a");
+    assert_eq!(stats.dirs_estimated, 1);
+    let dir_a = entries
+        .iter()
+        .find(|entry| entry.path == canonical_root.join("a"))
+        .expect("entry for a");
+    assert!(dir_a.unscanned);
+    assert_eq!(dir_a.estimate.map(|estimate| estimate.size), Some(100));
+    // Rows below the cut-off survive the lazy scan.
+    assert!(
+        cache
+            .entry(ctx.root_id, Path::new("a/b/c/deep.bin"))
+            .expect("query cache")
+            .is_some()
+    );
+
+    // Expanding `a` reveals one more level below it.
+    scanner.request_subtree_rescan(
+        job_id,
+        lazy_query,
+        canonical_root.join("a"),
+        Some(ctx.clone()),
+    );
+    let mut sub_entries = Vec::new();
+    loop {
+        match rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("subtree rescan message")
+        {
+            ScanMessage::Entries { entries: batch, .. } => sub_entries.extend(batch),
+            ScanMessage::SubtreeComplete {
+                job_id: msg_id,
+                root,
+                ..
+            } => {
+                assert_eq!(msg_id, job_id);
+                assert_eq!(root, canonical_root.join("a"));
+                break;
+            }
+            _ => {}
+        }
+    }
+    let names: Vec<_> = sub_entries
+        .iter()
+        .map(|entry| (entry.file_name.as_str(), entry.unscanned))
+        .collect();
+    assert_eq!(names, vec![("a", false), ("b", true)]);
+
+    // A subtree that grew is patched into its ancestors' cached totals.
+    create_file(&canonical_root.join("a/b/new.bin"), &"n".repeat(50));
+    fs::File::open(canonical_root.join("a/b"))
+        .and_then(|dir| dir.set_modified(SystemTime::now() + Duration::from_secs(5)))
+        .expect("bump mtime of a/b");
+
+    scanner.request_subtree_rescan(
+        job_id,
+        make_query(&canonical_root),
+        canonical_root.join("a/b"),
+        Some(ctx.clone()),
+    );
+    loop {
+        if let ScanMessage::SubtreeComplete { .. } = rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("subtree rescan message")
+        {
+            break;
+        }
+    }
+    let aggregate = |relative: &str| {
+        cache
+            .entry(ctx.root_id, Path::new(relative))
+            .expect("query cache")
+            .expect("cached row")
+            .aggregate_size
+    };
+    assert_eq!(aggregate("a/b"), 150);
+    assert_eq!(aggregate("a"), 150);
+    assert_eq!(aggregate("."), 160);
+    let summary = cache
+        .validate_aggregate(ctx.root_id, Path::new("."))
+        .expect("cache stays consistent");
+    assert_eq!(summary.total_size, 160);
+
+    drop(temp_root);
+}
+
+#[test]
+fn subtree_rescans_drop_vanished_entries_and_fix_ancestor_totals() {
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    create_file(&canonical_root.join("keep/a.bin"), &"a".repeat(100));
+    create_file(&canonical_root.join("work/old/b.bin"), &"b".repeat(200));
+    create_file(&canonical_root.join("work/c.bin"), &"c".repeat(300));
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+    let (scanner, rx) = scanner::spawn();
+    let job_id = scanner.request_scan(make_query(&canonical_root), Some(ctx.clone()));
+    while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+        if let ScanMessage::Complete { .. } = message {
+            break;
+        }
+    }
+
+    fs::remove_dir_all(canonical_root.join("work/old")).expect("remove old");
+    create_file(&canonical_root.join("work/d.bin"), &"d".repeat(50));
+    cache
+        .mark_subtree_dirty(ctx.root_id, Path::new("work"))
+        .expect("mark work dirty");
+    scanner.request_subtree_rescan(
+        job_id,
+        make_query(&canonical_root),
+        canonical_root.join("work"),
+        Some(ctx.clone()),
+    );
+
+    let mut began = false;
+    let mut paths = Vec::new();
+    let stats = loop {
+        match rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("subtree rescan message")
+        {
+            ScanMessage::SubtreeBegin { root, .. } => {
+                assert_eq!(root, canonical_root.join("work"));
+                began = true;
+            }
+            ScanMessage::Entries { entries, .. } => {
+                assert!(began, "entries before SubtreeBegin");
+                paths.extend(entries.into_iter().map(|entry| entry.path));
+            }
+            ScanMessage::SubtreeComplete { stats, .. } => break stats,
+            _ => {}
+        }
+    };
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            canonical_root.join("work"),
+            canonical_root.join("work/c.bin"),
+            canonical_root.join("work/d.bin"),
+        ]
+    );
+    assert_eq!(stats.cached_dirs, 0, "dirty subtree must be walked");
+
+    let entry = |relative: &str| {
+        cache
+            .entry(ctx.root_id, Path::new(relative))
+            .expect("query cache")
+    };
+    assert!(entry("work/old").is_none());
+    assert!(entry("work/old/b.bin").is_none());
+    assert_eq!(entry("work").expect("work row").aggregate_size, 350);
+    assert_eq!(entry("keep").expect("keep row").aggregate_size, 100);
+    assert_eq!(entry(".").expect("root row").aggregate_size, 450);
+    cache
+        .validate_aggregate(ctx.root_id, Path::new("."))
+        .expect("cache stays consistent");
+
+    drop(temp_root);
+}
+
+#[cfg(unix)]
+#[test]
+fn subtree_rescans_keep_links_to_files_counted_outside_as_duplicates() {
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    create_file(&canonical_root.join("a/data.bin"), &"x".repeat(1000));
+    create_file(&canonical_root.join("b/other.bin"), &"y".repeat(10));
+    fs::hard_link(
+        canonical_root.join("a/data.bin"),
+        canonical_root.join("b/data.bin"),
+    )
+    .expect("hard link");
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+    let (scanner, rx) = scanner::spawn();
+    let job_id = scanner.request_scan(make_query(&canonical_root), Some(ctx.clone()));
+    while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+        if let ScanMessage::Complete { .. } = message {
+            break;
+        }
+    }
+    let entry = |relative: &str| {
+        cache
+            .entry(ctx.root_id, Path::new(relative))
+            .expect("query cache")
+            .expect("cached row")
+    };
+    assert_eq!(entry(".").aggregate_size, 1010);
+
+    // Whichever link the walk met second is the duplicate; rescan the folder holding it.
+    let (counted, linked) = if entry("b/data.bin").flags & cache::FLAG_HARDLINK_DUPLICATE != 0 {
+        ("a", "b")
+    } else {
+        ("b", "a")
+    };
+    cache
+        .mark_subtree_dirty(ctx.root_id, Path::new(linked))
+        .expect("mark subtree dirty");
+    scanner.request_subtree_rescan(
+        job_id,
+        make_query(&canonical_root),
+        canonical_root.join(linked),
+        Some(ctx.clone()),
+    );
+    let stats = loop {
+        if let ScanMessage::SubtreeComplete { stats, .. } = rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("subtree rescan message")
+        {
+            break stats;
+        }
+    };
+    assert_eq!(stats.hardlink_duplicates, 1);
+    let link = entry(&format!("{linked}/data.bin"));
+    assert_ne!(link.flags & cache::FLAG_HARDLINK_DUPLICATE, 0);
+    assert_eq!(entry(".").aggregate_size, 1010);
+    let counted_total = entry(counted).aggregate_size;
+    assert_eq!(counted_total + entry(linked).aggregate_size, 1010);
+    cache
+        .validate_aggregate(ctx.root_id, Path::new("."))
+        .expect("cache stays consistent");
+
+    drop(temp_root);
+}
+
+#[test]
+fn multi_root_scans_cache_each_root_separately() {
+    let (temp_a, root_a) = canonical_temp_dir();
+    let (temp_b, root_b) = canonical_temp_dir();
+    create_file(&root_a.join("docs/a.txt"), &"a".repeat(100));
+    create_file(&root_b.join("media/b.bin"), &"b".repeat(250));
+    create_file(&root_b.join("c.bin"), &"c".repeat(50));
+
+    let (cache, _cache_dir) = make_cache();
+    let scans: Vec<RootScan> = [&root_a, &root_b]
+        .into_iter()
+        .map(|root| {
+            let root_cache = cache.load_root(root).expect("load root cache");
+            RootScan {
+                query: make_query(root),
+                cache: Some(CacheContext {
+                    cache: cache.clone(),
+                    root_id: root_cache.root_id,
+                    canonical_root: root.clone(),
+                }),
+            }
+        })
+        .collect();
+    let root_ids: Vec<i64> = scans
+        .iter()
+        .map(|scan| scan.cache.as_ref().expect("cache context").root_id)
+        .collect();
+    assert_ne!(root_ids[0], root_ids[1]);
+
+    let (scanner, rx) = scanner::spawn();
+    let job_id = scanner.request_scan_roots(scans);
+    let mut began = None;
+    let mut entries = Vec::new();
+    let mut stats = ScanStats::default();
+    while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+        match message {
+            ScanMessage::BeginRoots {
+                job_id: msg_id,
+                roots,
+            } => {
+                assert_eq!(msg_id, job_id);
+                began = Some(roots);
+            }
+            ScanMessage::Entries { entries: batch, .. } => entries.extend(batch),
+            ScanMessage::Stats { stats: s, .. } => stats = s,
+            ScanMessage::Complete { job_id: msg_id } if msg_id == job_id => break,
+            _ => {}
+        }
+    }
+
+    assert_eq!(began, Some(vec![root_a.clone(), root_b.clone()]));
+    assert!(
+        entries
+            .iter()
+            .any(|entry| entry.path == root_a.join("docs/a.txt"))
+    );
+    assert!(
+        entries
+            .iter()
+            .any(|entry| entry.path == root_b.join("media/b.bin"))
+    );
+    assert_eq!(stats.files_scanned, 3);
+
+    let aggregate = |root_id: i64| {
+        cache
+            .entry(root_id, Path::new("."))
+            .expect("query cache")
+            .expect("cached root row")
+            .aggregate_size
+    };
+    assert_eq!(aggregate(root_ids[0]), 100);
+    assert_eq!(aggregate(root_ids[1]), 300);
+    let reloaded = cache.load_root(&root_b).expect("reload root b");
+    assert_eq!(reloaded.root_id, root_ids[1]);
+    assert!(!reloaded.entries.is_empty());
+
+    drop(temp_a);
+    drop(temp_b);
+}
+
+#[test]
+fn files_are_categorised_and_totalled_per_directory() {
+    let (temp_root, canonical_root) = canonical_temp_dir();
+    create_file(&canonical_root.join("media/clip.mp4"), "0123456789");
+    create_file(&canonical_root.join("media/notes.md"), "abc");
+    create_file(&canonical_root.join("logs/server.log.1"), "12345");
+    // No extension, so only the magic bytes identify it.
+    fs::write(canonical_root.join("media/photo"), b"\x89PNG\r\n\x1a\nrest").expect("write png");
+    create_file(&canonical_root.join("misc"), "plain");
+
+    let (cache, _cache_dir) = make_cache();
+    let root_cache = cache.load_root(&canonical_root).expect("load root cache");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: canonical_root.clone(),
+    };
+
+    let (scanner, rx) = scanner::spawn();
+    let collect_tree = || {
+        let job_id = scanner.request_scan(make_query(&canonical_root), Some(ctx.clone()));
+        let mut tree = TreeStore::default();
+        while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+            match message {
+                ScanMessage::Entries {
+                    job_id: msg_id,
+                    entries,
+                } if msg_id == job_id => entries.into_iter().for_each(|entry| tree.upsert(entry)),
+                ScanMessage::Complete { job_id: msg_id } if msg_id == job_id => break,
+                _ => {}
+            }
+        }
+        tree
+    };
+
+    // The second scan replays the cache, which must keep the categories.
+    for tree in [collect_tree(), collect_tree()] {
+        let totals = tree.category_totals_with_cache(
+            &canonical_root,
+            SizeMetric::Apparent,
+            &mut BTreeMap::new(),
+        );
+        assert_eq!(totals.get(FileCategory::Video), 10);
+        assert_eq!(totals.get(FileCategory::Image), 12);
+        assert_eq!(totals.get(FileCategory::Document), 3);
+        assert_eq!(totals.get(FileCategory::Log), 5);
+        assert_eq!(totals.get(FileCategory::Other), 5);
+        assert_eq!(totals.unclassified, 0);
+
+        let media = tree.category_totals_with_cache(
+            &canonical_root.join("media"),
+            SizeMetric::Apparent,
+            &mut BTreeMap::new(),
+        );
+        assert_eq!(media.total(), 25);
+        assert_eq!(media.get(FileCategory::Log), 0);
+    }
+
+    let entry = cache
+        .entry(ctx.root_id, Path::new("media/photo"))
+        .expect("query cache")
+        .expect("cached row");
+    assert_eq!(entry.category, Some(FileCategory::Image));
+
+    drop(temp_root);
+}
@@ -0,0 +1,322 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crossbeam_channel::Receiver;
+use disk_space_inspect::cache::Cache;
+use disk_space_inspect::duplicates::{
+    self, DuplicateGroup, DuplicateStats, HEAD_BYTES, LinkMethod,
+};
+use disk_space_inspect::query::SearchQuery;
+use disk_space_inspect::scanner::{self, CacheContext, ScanMessage, ScannerHandle};
+
+fn create_file(path: &Path, contents: &[u8]) {
+    fs::create_dir_all(path.parent().unwrap()).expect("create parent");
+    fs::write(path, contents).expect("write file");
+}
+
+fn scan(handle: &ScannerHandle, rx: &Receiver<ScanMessage>, ctx: &CacheContext) -> u64 {
+    let query = SearchQuery {
+        root: ctx.canonical_root.clone(),
+        raw: ctx.canonical_root.display().to_string(),
+        ..SearchQuery::default()
+    };
+    let job_id = handle.request_scan(query, Some(ctx.clone()));
+    while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+        if let ScanMessage::Complete { job_id: msg_id } = message
+            && msg_id == job_id
+        {
+            break;
+        }
+    }
+    job_id
+}
+
+fn find(
+    handle: &ScannerHandle,
+    rx: &Receiver<ScanMessage>,
+    job_id: u64,
+    ctx: &CacheContext,
+) -> (Vec<DuplicateGroup>, DuplicateStats) {
+    handle.request_duplicates(job_id, vec![ctx.clone()], 1);
+    loop {
+        match rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("duplicates message")
+        {
+            ScanMessage::Duplicates {
+                job_id: msg_id,
+                groups,
+                stats,
+            } => {
+                assert_eq!(msg_id, job_id);
+                return (groups, stats);
+            }
+            ScanMessage::Error { error, .. } => panic!("unexpected error: {error:?}"),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn duplicates_are_grouped_by_content_and_hashes_are_reused() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let root = temp.path().canonicalize().expect("canonical root");
+    let big = vec![7u8; HEAD_BYTES * 3];
+    // Same size and head as `big`, different tail.
+    let mut near = big.clone();
+    *near.last_mut().unwrap() = 8;
+    create_file(&root.join("a/big.bin"), &big);
+    create_file(&root.join("b/big copy.bin"), &big);
+    create_file(&root.join("c/big.bin"), &big);
+    create_file(&root.join("c/near.bin"), &near);
+    create_file(&root.join("notes.txt"), b"hello");
+    create_file(&root.join("b/notes.txt"), b"hello");
+    create_file(&root.join("other.txt"), b"world");
+    create_file(&root.join("empty1"), b"");
+    create_file(&root.join("empty2"), b"");
+
+    let cache_dir = tempfile::tempdir().expect("cache temp");
+    let cache = Cache::open_in_path(cache_dir.path().join("cache.sqlite")).expect("open cache");
+    let root_cache = cache.load_root(&root).expect("load root");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: root.clone(),
+    };
+    let (handle, rx) = scanner::spawn();
+    let job_id = scan(&handle, &rx, &ctx);
+
+    let (groups, stats) = find(&handle, &rx, job_id, &ctx);
+    let paths =
+        |relative: &[&str]| -> Vec<PathBuf> { relative.iter().map(|rel| root.join(rel)).collect() };
+    assert_eq!(groups.len(), 2);
+    assert_eq!(
+        groups[0].paths,
+        paths(&["a/big.bin", "b/big copy.bin", "c/big.bin"])
+    );
+    assert_eq!(groups[0].reclaimable(), 2 * big.len() as u64);
+    assert_eq!(groups[1].paths, paths(&["b/notes.txt", "notes.txt"]));
+    assert_eq!(groups[1].reclaimable(), 5);
+    // Both size groups are hashed by their heads; only the large one needs full hashes.
+    assert_eq!(stats.head_hashes, 7);
+    assert_eq!(stats.full_hashes, 4);
+    assert_eq!(stats.hashes_reused, 0);
+
+    // A rerun after a rescan reads nothing but the file that changed.
+    fs::write(root.join("c/big.bin"), &near).expect("rewrite");
+    for dir in [root.clone(), root.join("c")] {
+        fs::File::open(&dir)
+            .and_then(|dir| dir.set_modified(SystemTime::now() + Duration::from_secs(5)))
+            .expect("bump directory mtime");
+    }
+    fs::File::options()
+        .write(true)
+        .open(root.join("c/big.bin"))
+        .and_then(|file| file.set_modified(SystemTime::now() + Duration::from_secs(5)))
+        .expect("bump mtime of c/big.bin");
+    let job_id = scan(&handle, &rx, &ctx);
+    let (groups, stats) = find(&handle, &rx, job_id, &ctx);
+    assert_eq!(groups.len(), 3);
+    assert_eq!(groups[0].paths, paths(&["a/big.bin", "b/big copy.bin"]));
+    assert_eq!(groups[1].paths, paths(&["c/big.bin", "c/near.bin"]));
+    assert_eq!(stats.head_hashes, 1);
+    assert_eq!(stats.full_hashes, 1);
+    assert!(stats.hashes_reused >= 8);
+}
+
+#[test]
+fn identical_copies_are_replaced_by_links_with_an_undo_record() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let root = temp.path().canonicalize().expect("canonical root");
+    let contents = vec![3u8; HEAD_BYTES * 2];
+    create_file(&root.join("keep/report.pdf"), &contents);
+    create_file(&root.join("copy/report.pdf"), &contents);
+    create_file(&root.join("copy/other.pdf"), &contents[1..]);
+
+    let cache_dir = tempfile::tempdir().expect("cache temp");
+    let cache = Cache::open_in_path(cache_dir.path().join("cache.sqlite")).expect("open cache");
+    let root_cache = cache.load_root(&root).expect("load root");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: root.clone(),
+    };
+    let (handle, rx) = scanner::spawn();
+    let job_id = scan(&handle, &rx, &ctx);
+
+    let selected = vec![
+        root.join("copy/report.pdf"),
+        root.join("keep/report.pdf"),
+        root.join("copy/other.pdf"),
+    ];
+    handle.request_link(job_id, selected, cache.undo_dir());
+    let report = loop {
+        match rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("linked message")
+        {
+            ScanMessage::Linked {
+                job_id: msg_id,
+                result,
+            } => {
+                assert_eq!(msg_id, job_id);
+                break result.expect("link copies");
+            }
+            ScanMessage::Error { error, .. } => panic!("unexpected error: {error:?}"),
+            _ => {}
+        }
+    };
+    assert_eq!(report.bytes_saved, contents.len() as u64);
+    assert_eq!(report.linked.len(), 1);
+    let linked = &report.linked[0];
+    assert_eq!(linked.path, root.join("keep/report.pdf"));
+    assert_eq!(linked.source, root.join("copy/report.pdf"));
+    assert_eq!(
+        report
+            .skipped
+            .iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>(),
+        vec![&root.join("copy/other.pdf")]
+    );
+    assert_eq!(
+        fs::read(root.join("keep/report.pdf")).expect("read"),
+        contents
+    );
+
+    let record = report.undo_record.as_ref().expect("undo record");
+    let json: serde_json::Value =
+        serde_json::from_slice(&fs::read(record).expect("read undo record")).expect("parse");
+    assert_eq!(
+        json["linked"][0]["path"],
+        root.join("keep/report.pdf").display().to_string()
+    );
+
+    // A hard link shows up in the cache as a second link to one inode once its folder is
+    // rescanned.
+    if linked.method == LinkMethod::HardLink {
+        cache
+            .mark_ancestors_dirty(ctx.root_id, Path::new("keep"))
+            .expect("mark keep dirty");
+        cache
+            .mark_ancestors_dirty(ctx.root_id, Path::new("copy"))
+            .expect("mark copy dirty");
+        scan(&handle, &rx, &ctx);
+        let entry = cache
+            .entry(ctx.root_id, Path::new("keep/report.pdf"))
+            .expect("query cache")
+            .expect("cached row");
+        assert_ne!(entry.flags & disk_space_inspect::cache::FLAG_MULTI_LINK, 0);
+        let root_entry = cache
+            .entry(ctx.root_id, Path::new("."))
+            .expect("query cache")
+            .expect("cached root");
+        assert_eq!(
+            root_entry.aggregate_size,
+            (2 * contents.len() - 1) as u64,
+            "the shared copy is counted once"
+        );
+    }
+}
+
+#[test]
+fn cached_hashes_are_not_reused_for_files_rewritten_with_the_same_mtime_and_size() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let root = temp.path().canonicalize().expect("canonical root");
+    let contents = vec![5u8; HEAD_BYTES * 2];
+    create_file(&root.join("a.bin"), &contents);
+    create_file(&root.join("b.bin"), &contents);
+
+    let cache_dir = tempfile::tempdir().expect("cache temp");
+    let cache = Cache::open_in_path(cache_dir.path().join("cache.sqlite")).expect("open cache");
+    let root_cache = cache.load_root(&root).expect("load root");
+    let ctx = CacheContext {
+        cache: cache.clone(),
+        root_id: root_cache.root_id,
+        canonical_root: root.clone(),
+    };
+    let (handle, rx) = scanner::spawn();
+    let job_id = scan(&handle, &rx, &ctx);
+    let (groups, _) = find(&handle, &rx, job_id, &ctx);
+    assert_eq!(groups.len(), 1);
+
+    // Same length, same mtime to the nanosecond; only the ctime tells the rewrite apart.
+    let modified = fs::metadata(root.join("b.bin"))
+        .and_then(|metadata| metadata.modified())
+        .expect("mtime");
+    let mut rewritten = contents.clone();
+    rewritten[0] = 6;
+    fs::write(root.join("b.bin"), &rewritten).expect("rewrite");
+    fs::File::options()
+        .write(true)
+        .open(root.join("b.bin"))
+        .and_then(|file| file.set_modified(modified))
+        .expect("restore mtime");
+
+    // The directory is replayed, so its row is stale; the reused hash is checked against the disk.
+    let job_id = scan(&handle, &rx, &ctx);
+    let (groups, stats) = find(&handle, &rx, job_id, &ctx);
+    assert!(groups.is_empty(), "{groups:?}");
+    assert_eq!(stats.stale_files, 1);
+
+    // Once rescanned, the row's new ctime no longer matches the stored hash key.
+    cache
+        .mark_ancestors_dirty(ctx.root_id, Path::new("."))
+        .expect("mark root dirty");
+    let job_id = scan(&handle, &rx, &ctx);
+    let (groups, stats) = find(&handle, &rx, job_id, &ctx);
+    assert!(groups.is_empty(), "{groups:?}");
+    assert_eq!(stats.stale_files, 0);
+    assert_eq!(stats.head_hashes, 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn copies_with_other_permissions_keep_them_or_are_left_alone() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let temp = tempfile::tempdir().expect("tempdir");
+    let root = temp.path().canonicalize().expect("canonical root");
+    let contents = vec![7u8; HEAD_BYTES * 2];
+    create_file(&root.join("keep/data.bin"), &contents);
+    create_file(&root.join("copy/data.bin"), &contents);
+    fs::set_permissions(
+        root.join("keep/data.bin"),
+        fs::Permissions::from_mode(0o644),
+    )
+    .expect("chmod kept copy");
+    fs::set_permissions(
+        root.join("copy/data.bin"),
+        fs::Permissions::from_mode(0o600),
+    )
+    .expect("chmod copy");
+    let before = fs::metadata(root.join("copy/data.bin")).expect("stat copy");
+
+    let undo_dir = tempfile::tempdir().expect("undo temp");
+    let selected = vec![root.join("copy/data.bin"), root.join("keep/data.bin")];
+    let report = duplicates::link_identical(&selected, undo_dir.path()).expect("link copies");
+
+    let after = fs::metadata(root.join("copy/data.bin")).expect("stat copy");
+    match report.linked.first() {
+        // Only a clone can keep the copy's own permissions.
+        Some(linked) => {
+            assert_eq!(linked.method, LinkMethod::Reflink);
+            assert_eq!(linked.uid, Some(before.uid()));
+            assert_eq!(linked.gid, Some(before.gid()));
+            assert_eq!(after.mode() & 0o7777, 0o600);
+            assert_eq!((after.uid(), after.gid()), (before.uid(), before.gid()));
+        }
+        None => {
+            assert_eq!(report.skipped.len(), 1, "{:?}", report.skipped);
+            assert_eq!(after.ino(), before.ino());
+            assert_eq!(after.mode() & 0o7777, 0o600);
+        }
+    }
+    let leftovers: Vec<_> = fs::read_dir(root.join("copy"))
+        .expect("list copy dir")
+        .map(|entry| entry.expect("dir entry").file_name())
+        .filter(|name| name.to_string_lossy().contains("dusk-link"))
+        .collect();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+}
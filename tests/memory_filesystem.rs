@@ -0,0 +1,535 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Receiver;
+use disk_space_inspect::cache::{self, Cache};
+use disk_space_inspect::filesystem::{FileSystem, FsOperation, MemoryFileSystem};
+use disk_space_inspect::fs::{
+    Allocation, FileCategory, FileEntry, FileKind, Ownership, ScanError, ScanErrorKind,
+};
+use disk_space_inspect::query::SearchQuery;
+use disk_space_inspect::scanner::{
+    self, CacheContext, ScanMessage, ScanOptions, ScanStats, ScannerConfig, ScannerHandle,
+};
+use disk_space_inspect::tree::SizeMetric;
+use tempfile::TempDir;
+
+const ROOT: &str = "/dusk-memory";
+
+fn setup(filesystem: &Arc<MemoryFileSystem>) -> (ScannerHandle, Receiver<ScanMessage>) {
+    scanner::spawn_with_config(ScannerConfig {
+        walker_threads: 2,
+        filesystem: filesystem.clone(),
+        ..ScannerConfig::default()
+    })
+}
+
+fn make_cache(root: &Path) -> (CacheContext, TempDir) {
+    let cache_dir = tempfile::tempdir().expect("cache temp");
+    let cache = Cache::open_in_path(cache_dir.path().join("cache.sqlite")).expect("open cache");
+    let root_cache = cache.load_root(root).expect("load root");
+    let ctx = CacheContext {
+        cache,
+        root_id: root_cache.root_id,
+        canonical_root: root.to_path_buf(),
+    };
+    (ctx, cache_dir)
+}
+
+fn root_query(ctx: &CacheContext) -> SearchQuery {
+    SearchQuery {
+        root: ctx.canonical_root.clone(),
+        raw: ctx.canonical_root.display().to_string(),
+        ..SearchQuery::default()
+    }
+}
+
+fn scan(
+    handle: &ScannerHandle,
+    rx: &Receiver<ScanMessage>,
+    ctx: &CacheContext,
+) -> (ScanStats, Vec<ScanError>) {
+    let query = root_query(ctx);
+    let job_id = handle.request_scan(query, Some(ctx.clone()));
+    let mut stats = ScanStats::default();
+    let mut errors = Vec::new();
+    while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+        match message {
+            ScanMessage::Stats {
+                job_id: id,
+                stats: s,
+            } if id == job_id => stats = s,
+            ScanMessage::Error { job_id: id, error } if id == job_id => errors.push(error),
+            ScanMessage::Complete { job_id: id } if id == job_id => break,
+            _ => {}
+        }
+    }
+    errors.sort_by(|lhs, rhs| lhs.path.cmp(&rhs.path));
+    (stats, errors)
+}
+
+fn scan_files(
+    handle: &ScannerHandle,
+    rx: &Receiver<ScanMessage>,
+    ctx: &CacheContext,
+    query: SearchQuery,
+) -> Vec<FileEntry> {
+    let job_id = handle.request_scan(query, Some(ctx.clone()));
+    let mut files = Vec::new();
+    while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+        match message {
+            ScanMessage::Entries {
+                job_id: id,
+                entries,
+            } if id == job_id => files.extend(
+                entries
+                    .into_iter()
+                    .filter(|entry| entry.kind != FileKind::Directory),
+            ),
+            ScanMessage::Complete { job_id: id } if id == job_id => break,
+            _ => {}
+        }
+    }
+    files.sort_by(|lhs, rhs| lhs.path.cmp(&rhs.path));
+    files
+}
+
+fn total(ctx: &CacheContext, relative: &str) -> u64 {
+    ctx.cache
+        .entry(ctx.root_id, Path::new(relative))
+        .expect("query cache")
+        .expect("cached row")
+        .aggregate_size
+}
+
+#[test]
+fn unchanged_directories_are_replayed_and_changed_ones_walked() {
+    let root = PathBuf::from(ROOT);
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    filesystem.add_file(root.join("a/one.bin"), 100);
+    filesystem.add_file(root.join("a/deep/two.bin"), 20);
+    filesystem.add_file(root.join("b/three.bin"), 3);
+    filesystem.add_file(root.join("top.bin"), 1000);
+    filesystem.add_hard_link(root.join("a/one.bin"), root.join("b/one-again.bin"));
+
+    let (ctx, _cache_dir) = make_cache(&root);
+    let (handle, rx) = setup(&filesystem);
+
+    let (stats, errors) = scan(&handle, &rx, &ctx);
+    assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+    assert_eq!(stats.files_scanned, 5);
+    assert_eq!(stats.dirs_scanned, 4);
+    assert_eq!(stats.hardlink_duplicates, 1);
+    assert_eq!(total(&ctx, "."), 1123);
+    assert_eq!(total(&ctx, "a"), 120);
+    assert_eq!(total(&ctx, "b"), 3);
+
+    // Nothing changed, so the whole root comes from the cache.
+    let (stats, _) = scan(&handle, &rx, &ctx);
+    assert_eq!(stats.files_scanned, 0);
+    assert_eq!(stats.cached_dirs, 4);
+    assert_eq!(stats.cached_bytes, 1123);
+
+    // A new file changes the root's mtime only; its subdirectories are still replayed.
+    filesystem.add_file(root.join("new.bin"), 7);
+    let (stats, _) = scan(&handle, &rx, &ctx);
+    assert_eq!(stats.dirs_scanned, 1);
+    assert_eq!(stats.files_scanned, 2);
+    assert_eq!(stats.cached_dirs, 3);
+    assert_eq!(total(&ctx, "."), 1130);
+
+    // Resizing a file leaves directory mtimes alone, so every directory on its path is touched.
+    filesystem.resize(root.join("a/deep/two.bin"), 50);
+    filesystem.add_file(root.join("a/deep/marker.bin"), 0);
+    filesystem.add_file(root.join("a/marker.bin"), 0);
+    filesystem.add_file(root.join("marker.bin"), 0);
+    let (stats, _) = scan(&handle, &rx, &ctx);
+    assert_eq!(stats.dirs_scanned, 3);
+    assert_eq!(stats.cached_dirs, 1);
+    assert_eq!(total(&ctx, "a"), 150);
+    assert_eq!(total(&ctx, "."), 1160);
+}
+
+#[test]
+fn injected_failures_are_reported_and_cached_with_their_kind() {
+    let root = PathBuf::from(ROOT);
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    filesystem.add_file(root.join("ok/file.bin"), 10);
+    filesystem.add_file(root.join("locked/secret.bin"), 20);
+    filesystem.add_file(root.join("gone.bin"), 30);
+    filesystem.fail(
+        root.join("locked"),
+        FsOperation::ReadDir,
+        io::ErrorKind::PermissionDenied,
+    );
+    // Listed by its directory but gone by the time its metadata is read.
+    filesystem.fail(
+        root.join("gone.bin"),
+        FsOperation::Metadata,
+        io::ErrorKind::NotFound,
+    );
+
+    let (ctx, _cache_dir) = make_cache(&root);
+    let (handle, rx) = setup(&filesystem);
+    let (stats, errors) = scan(&handle, &rx, &ctx);
+
+    assert_eq!(stats.fs_errors, 2);
+    let kinds: Vec<(PathBuf, ScanErrorKind)> = errors
+        .iter()
+        .map(|error| (error.path.clone(), error.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (root.join("gone.bin"), ScanErrorKind::Vanished),
+            (root.join("locked"), ScanErrorKind::PermissionDenied),
+        ]
+    );
+    assert_eq!(total(&ctx, "."), 10);
+    assert!(
+        ctx.cache
+            .entry(ctx.root_id, Path::new("gone.bin"))
+            .expect("query cache")
+            .is_none()
+    );
+
+    let stored = ctx.cache.load_root(&root).expect("reload root").errors;
+    assert_eq!(stored.len(), 2);
+
+    // Once the directory is readable again its contents are counted and the error is dropped.
+    filesystem.clear_fault(root.join("locked"), FsOperation::ReadDir);
+    filesystem.clear_fault(root.join("gone.bin"), FsOperation::Metadata);
+    filesystem.add_file(root.join("locked/marker.bin"), 0);
+    filesystem.add_file(root.join("marker.bin"), 0);
+    let (_, errors) = scan(&handle, &rx, &ctx);
+    assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+    assert_eq!(total(&ctx, "."), 60);
+    assert!(
+        ctx.cache
+            .load_root(&root)
+            .expect("reload root")
+            .errors
+            .is_empty()
+    );
+}
+
+#[test]
+fn removing_a_symlink_leaves_its_target_and_failures_surface() {
+    let root = PathBuf::from(ROOT);
+    let filesystem = MemoryFileSystem::new();
+    filesystem.add_file(root.join("data/file.bin"), 10);
+    filesystem.add_symlink(root.join("link"), "data");
+    filesystem.add_file(root.join("locked/file.bin"), 10);
+    filesystem.fail(
+        root.join("locked"),
+        FsOperation::Remove,
+        io::ErrorKind::PermissionDenied,
+    );
+
+    assert!(
+        filesystem
+            .metadata(&root.join("link"))
+            .expect("follow")
+            .is_dir()
+    );
+    filesystem.remove(&root.join("link")).expect("remove link");
+    assert!(!filesystem.exists(root.join("link")));
+    assert!(filesystem.exists(root.join("data/file.bin")));
+
+    filesystem.remove(&root.join("data")).expect("remove dir");
+    assert!(!filesystem.exists(root.join("data/file.bin")));
+
+    let err = filesystem
+        .remove(&root.join("locked"))
+        .expect_err("injected failure");
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    assert!(filesystem.exists(root.join("locked/file.bin")));
+}
+
+#[test]
+fn sparse_and_preallocated_files_are_flagged_and_filterable() {
+    let root = PathBuf::from(ROOT);
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    filesystem.add_file(root.join("vm/disk.qcow2"), 64 << 20);
+    filesystem.set_allocated(root.join("vm/disk.qcow2"), 3 << 20);
+    filesystem.add_file(root.join("torrents/movie.mkv"), 1 << 20);
+    filesystem.set_allocated(root.join("torrents/movie.mkv"), 700 << 20);
+    // Within the slack either way, so not worth a badge.
+    filesystem.add_file(root.join("torrents/small.bin"), 1000);
+    filesystem.set_allocated(root.join("torrents/small.bin"), 64 << 10);
+    filesystem.add_file(root.join("plain.txt"), 10_000);
+
+    let (ctx, _cache_dir) = make_cache(&root);
+    let (handle, rx) = setup(&filesystem);
+    let flagged = |files: &[FileEntry]| -> Vec<(PathBuf, Option<Allocation>)> {
+        files
+            .iter()
+            .map(|entry| (entry.path.clone(), entry.allocation))
+            .collect()
+    };
+
+    let files = scan_files(&handle, &rx, &ctx, root_query(&ctx));
+    assert_eq!(
+        flagged(&files),
+        vec![
+            (root.join("plain.txt"), None),
+            (
+                root.join("torrents/movie.mkv"),
+                Some(Allocation::Preallocated)
+            ),
+            (root.join("torrents/small.bin"), None),
+            (root.join("vm/disk.qcow2"), Some(Allocation::Sparse)),
+        ]
+    );
+    let flags = |relative: &str| {
+        ctx.cache
+            .entry(ctx.root_id, Path::new(relative))
+            .expect("query cache")
+            .expect("cached row")
+            .flags
+    };
+    assert_ne!(flags("vm/disk.qcow2") & cache::FLAG_SPARSE, 0);
+    assert_ne!(flags("torrents/movie.mkv") & cache::FLAG_PREALLOCATED, 0);
+    assert_eq!(
+        flags("plain.txt") & (cache::FLAG_SPARSE | cache::FLAG_PREALLOCATED),
+        0
+    );
+
+    // The filter applies both to replayed directories and to walked ones.
+    let sparse_only = SearchQuery {
+        allocation_filter: Some(Allocation::Sparse),
+        ..root_query(&ctx)
+    };
+    let files = scan_files(&handle, &rx, &ctx, sparse_only.clone());
+    assert_eq!(
+        flagged(&files),
+        vec![(root.join("vm/disk.qcow2"), Some(Allocation::Sparse))]
+    );
+    filesystem.add_file(root.join("vm/fresh.img"), 10 << 20);
+    filesystem.set_allocated(root.join("vm/fresh.img"), 0);
+    filesystem.add_file(root.join("marker.bin"), 0);
+    let files = scan_files(&handle, &rx, &ctx, sparse_only);
+    assert_eq!(
+        flagged(&files),
+        vec![
+            (root.join("vm/disk.qcow2"), Some(Allocation::Sparse)),
+            (root.join("vm/fresh.img"), Some(Allocation::Sparse)),
+        ]
+    );
+}
+
+#[test]
+fn cancelling_a_scan_stops_a_cached_replay_midway() {
+    let root = PathBuf::from(ROOT);
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    for dir in 0..20 {
+        for file in 0..200 {
+            filesystem.add_file(root.join(format!("d{dir}/f{file}.bin")), 10);
+        }
+    }
+    let (ctx, _cache_dir) = make_cache(&root);
+    // A tiny channel keeps the scanner from running far ahead of the receiver.
+    let (handle, rx) = scanner::spawn_with_config(ScannerConfig {
+        walker_threads: 2,
+        filesystem: filesystem.clone(),
+        batch_size: 16,
+        channel_capacity: 1,
+        ..ScannerConfig::default()
+    });
+    let (stats, _) = scan(&handle, &rx, &ctx);
+    assert_eq!(stats.files_scanned, 4000);
+
+    // Nothing changed, so the second scan is one long replay of the cached root.
+    let job_id = handle.request_scan(root_query(&ctx), Some(ctx.clone()));
+    let mut cancelled_at = None;
+    let mut stats = None;
+    while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+        match message {
+            ScanMessage::Entries { job_id: id, .. } if id == job_id && cancelled_at.is_none() => {
+                handle.cancel(job_id);
+                cancelled_at = Some(Instant::now());
+            }
+            ScanMessage::Stats {
+                job_id: id,
+                stats: s,
+            } if id == job_id => stats = Some(s),
+            ScanMessage::Cancelled { job_id: id } if id == job_id => break,
+            _ => {}
+        }
+    }
+    let elapsed = cancelled_at.expect("entries were replayed").elapsed();
+    assert!(elapsed < Duration::from_secs(1), "took {elapsed:?}");
+    let stats = stats.expect("stats of the stopped scan");
+    assert!(
+        stats.cached_entries < 4000,
+        "replayed {}",
+        stats.cached_entries
+    );
+
+    // The stopped replay left the cache as it was.
+    assert_eq!(total(&ctx, "."), 40_000);
+    let (stats, _) = scan(&handle, &rx, &ctx);
+    assert_eq!(stats.cached_dirs, 21);
+}
+
+#[test]
+fn ownership_is_cached_replayed_and_summed_per_user_and_group() {
+    let root = PathBuf::from(ROOT);
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    filesystem.add_file(root.join("alice/notes.txt"), 100);
+    filesystem.add_file(root.join("alice/run.sh"), 20);
+    filesystem.add_file(root.join("bob/video.mp4"), 5000);
+    filesystem.add_file(root.join("shared/db.bin"), 300);
+    filesystem.set_owner(root.join("alice"), 1000, 100);
+    filesystem.set_owner(root.join("alice/notes.txt"), 1000, 100);
+    filesystem.set_owner(root.join("alice/run.sh"), 1000, 100);
+    filesystem.set_mode(root.join("alice/run.sh"), 0o4750);
+    filesystem.set_owner(root.join("bob/video.mp4"), 1001, 100);
+    filesystem.set_owner(root.join("shared/db.bin"), 1001, 50);
+    filesystem.set_mode(root.join("shared"), 0o1777);
+
+    let (ctx, _cache_dir) = make_cache(&root);
+    let (handle, rx) = setup(&filesystem);
+    let owners = |files: &[FileEntry]| -> Vec<Option<Ownership>> {
+        files.iter().map(|entry| entry.owner).collect()
+    };
+    let expected = vec![
+        Some(Ownership {
+            uid: 1000,
+            gid: 100,
+            mode: 0o644,
+        }),
+        Some(Ownership {
+            uid: 1000,
+            gid: 100,
+            mode: 0o4750,
+        }),
+        Some(Ownership {
+            uid: 1001,
+            gid: 100,
+            mode: 0o644,
+        }),
+        Some(Ownership {
+            uid: 1001,
+            gid: 50,
+            mode: 0o644,
+        }),
+    ];
+
+    let walked = scan_files(&handle, &rx, &ctx, root_query(&ctx));
+    assert_eq!(owners(&walked), expected);
+    let cached = ctx
+        .cache
+        .entry(ctx.root_id, Path::new("shared"))
+        .expect("query cache")
+        .expect("cached row");
+    assert_eq!(
+        cached.owner,
+        Some(Ownership {
+            uid: 0,
+            gid: 0,
+            mode: 0o1777
+        })
+    );
+    assert_eq!(cached.owner.unwrap().mode_string(), "rwxrwxrwt");
+    assert_eq!(expected[1].unwrap().mode_string(), "rwsr-x---");
+
+    // Replayed entries keep what the walk recorded.
+    let replayed = scan_files(&handle, &rx, &ctx, root_query(&ctx));
+    assert_eq!(owners(&replayed), expected);
+
+    let result = scanner::scan(
+        &root,
+        ScanOptions::new()
+            .filesystem(filesystem.clone())
+            .cache(ctx.cache.clone()),
+    );
+    let usage = result
+        .tree
+        .owner_usage(&root.join("alice"), SizeMetric::Apparent);
+    assert_eq!(usage.top_users(), vec![(1000, 120)]);
+    let usage = result.tree.owner_usage(&root, SizeMetric::Apparent);
+    assert_eq!(usage.unknown, 0);
+    // The root and the other folders are root's, and folders themselves take no bytes here.
+    assert_eq!(usage.top_users(), vec![(1001, 5300), (1000, 120), (0, 0)]);
+    assert_eq!(usage.top_groups(), vec![(100, 5120), (50, 300), (0, 0)]);
+}
+
+#[test]
+fn file_types_are_sniffed_and_archives_listed_through_the_filesystem() {
+    let root = PathBuf::from(ROOT);
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    let mut archive = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    archive
+        .start_file("docs/readme.txt", options)
+        .expect("start zip member");
+    io::Write::write_all(&mut archive, &[b'r'; 300]).expect("write zip member");
+    let archive = archive.finish().expect("finish zip").into_inner();
+    filesystem.add_file_with_contents(root.join("bundle.zip"), archive);
+    filesystem.add_file_with_contents(root.join("tool"), b"\x7fELF\x02\x01\x01".to_vec());
+    filesystem.add_file_with_contents(root.join("locked"), b"\x7fELF\x02\x01\x01".to_vec());
+    filesystem.fail(
+        root.join("locked"),
+        FsOperation::Open,
+        io::ErrorKind::PermissionDenied,
+    );
+    filesystem.add_file(root.join("blank"), 64);
+
+    // None of these paths exist on the host, so anything found was read from `filesystem`.
+    let result = scanner::scan(
+        &root,
+        ScanOptions::new()
+            .filesystem(filesystem.clone())
+            .expand_archives(true),
+    );
+    let category = |name: &str| result.tree.get(&root.join(name)).unwrap().category;
+    assert_eq!(category("tool"), Some(FileCategory::Binary));
+    assert_eq!(category("locked"), Some(FileCategory::Other));
+    assert_eq!(category("blank"), Some(FileCategory::Other));
+    let member = result
+        .tree
+        .get(&root.join("bundle.zip/docs/readme.txt"))
+        .expect("archive member");
+    assert!(member.in_archive);
+    assert_eq!(member.direct_size, 300);
+    assert_eq!(result.stats.archives_listed, 1);
+    assert_eq!(
+        filesystem.read_head(&root.join("blank"), 16).expect("read"),
+        vec![0; 16]
+    );
+}
+
+#[test]
+fn ignore_files_are_read_through_the_filesystem() {
+    let root = PathBuf::from(ROOT);
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    let rules = "node_modules/\n*.log\n";
+    filesystem.add_file_with_contents(root.join(".duskignore"), rules);
+    filesystem.add_file(root.join("node_modules/dep/index.js"), 500);
+    filesystem.add_file(root.join("src/main.rs"), 12);
+    filesystem.add_file(root.join("src/debug.log"), 40);
+
+    let (ctx, _cache_dir) = make_cache(&root);
+    let (handle, rx) = setup(&filesystem);
+
+    let (stats, errors) = scan(&handle, &rx, &ctx);
+    assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+    assert_eq!(stats.excluded_dirs, 1);
+    assert_eq!(total(&ctx, "."), 12 + rules.len() as u64);
+    let marker = ctx
+        .cache
+        .entry(ctx.root_id, Path::new("node_modules"))
+        .expect("query marker")
+        .expect("excluded directory is cached as a marker");
+    assert_ne!(marker.flags & cache::FLAG_EXCLUDED, 0);
+    assert!(
+        ctx.cache
+            .entry(ctx.root_id, Path::new("src/debug.log"))
+            .expect("query log")
+            .is_none()
+    );
+}
@@ -0,0 +1,113 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use disk_space_inspect::cache::Cache;
+use disk_space_inspect::filesystem::{FsOperation, MemoryFileSystem};
+use disk_space_inspect::fs::ScanErrorKind;
+use disk_space_inspect::scanner::{self, ScanEvent, ScanOptions, ScannerConfig};
+use disk_space_inspect::tree::SizeMetric;
+
+const ROOT: &str = "/dusk-embedded";
+
+#[test]
+fn blocking_scans_return_the_tree_stats_and_errors() {
+    let root = PathBuf::from(ROOT);
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    filesystem.add_file(root.join("logs/app.log"), 300);
+    filesystem.add_file(root.join("logs/old/app.1.log"), 200);
+    filesystem.add_file(root.join("data.bin"), 1000);
+    filesystem.add_file(root.join("private/key"), 5);
+    filesystem.fail(
+        root.join("private"),
+        FsOperation::ReadDir,
+        io::ErrorKind::PermissionDenied,
+    );
+    let cache_dir = tempfile::tempdir().expect("cache temp");
+    let cache = Cache::open_in_path(cache_dir.path().join("cache.sqlite")).expect("open cache");
+    let options = ScanOptions::new()
+        .filesystem(filesystem.clone())
+        .walker_threads(2)
+        .cache(cache.clone());
+
+    let result = scanner::scan(&root, options.clone());
+    assert_eq!(result.root, root);
+    assert_eq!(result.stats.files_scanned, 3);
+    assert_eq!(result.total_size(SizeMetric::Apparent), 1500);
+    assert_eq!(
+        result.tree.children(&root.join("logs")),
+        vec![root.join("logs/app.log"), root.join("logs/old")]
+    );
+    assert_eq!(
+        result.tree.get(&root.join("data.bin")).unwrap().name,
+        "data.bin"
+    );
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].path, root.join("private"));
+    assert_eq!(result.errors[0].kind, ScanErrorKind::PermissionDenied);
+
+    // The second scan replays the cache and still reports the stored error.
+    let result = scanner::scan(&root, options);
+    assert_eq!(result.stats.files_scanned, 0);
+    assert!(result.stats.cached_dirs > 0);
+    assert_eq!(result.total_size(SizeMetric::Apparent), 1500);
+    assert_eq!(result.errors.len(), 1);
+
+    // Filters apply as in the app's search box.
+    let result = scanner::scan(
+        &root,
+        ScanOptions::new()
+            .filesystem(filesystem)
+            .pattern("**/*.log"),
+    );
+    assert!(result.tree.get(&root.join("data.bin")).is_none());
+    assert!(result.tree.get(&root.join("logs/old/app.1.log")).is_some());
+}
+
+#[test]
+fn streams_end_with_finished_and_stop_when_cancelled() {
+    let root = PathBuf::from(ROOT);
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    for dir in 0..10 {
+        for file in 0..100 {
+            filesystem.add_file(root.join(format!("d{dir}/f{file}.bin")), 1);
+        }
+    }
+    let options = ScanOptions::new().config(ScannerConfig {
+        walker_threads: 2,
+        batch_size: 8,
+        channel_capacity: 1,
+        filesystem,
+        ..ScannerConfig::default()
+    });
+
+    let events: Vec<ScanEvent> = scanner::scan_stream(&root, options.clone()).collect();
+    let entries: usize = events
+        .iter()
+        .map(|event| match event {
+            ScanEvent::Entries(entries) => entries.len(),
+            _ => 0,
+        })
+        .sum();
+    assert_eq!(entries, 1011);
+    match events.last() {
+        Some(ScanEvent::Finished(stats)) => assert_eq!(stats.files_scanned, 1000),
+        other => panic!("unexpected last event: {other:?}"),
+    }
+
+    let mut stream = scanner::scan_stream(Path::new(ROOT), options);
+    let mut stats = None;
+    while let Some(event) = stream.next() {
+        match event {
+            ScanEvent::Entries(_) => stream.cancel(),
+            ScanEvent::Finished(finished) => stats = Some(finished),
+            _ => {}
+        }
+    }
+    let stats = stats.expect("finished after cancelling");
+    assert!(
+        stats.files_scanned < 1000,
+        "scanned {}",
+        stats.files_scanned
+    );
+}
@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use disk_space_inspect::filesystem::{FileSystem, MemoryFileSystem, RealFileSystem};
+use disk_space_inspect::query::SearchQuery;
+use disk_space_inspect::scanner::{self, ScanMessage, ScannerConfig};
+use disk_space_inspect::throttle::{LowImpact, Throttle};
+
+#[test]
+fn throttle_backs_off_while_operations_are_slow_and_recovers() {
+    let throttle = Throttle::new(LowImpact {
+        max_ops_per_sec: 1000,
+        latency_target: Duration::from_millis(5),
+    });
+    assert_eq!(throttle.rate(), 1000.0);
+
+    for _ in 0..5 {
+        throttle.record(1, Duration::from_millis(50));
+    }
+    let backed_off = throttle.rate();
+    assert!(backed_off < 500.0, "rate still {backed_off}");
+    // Never below the floor, however slow the disk gets.
+    for _ in 0..200 {
+        throttle.record(10, Duration::from_secs(1));
+    }
+    assert_eq!(throttle.rate(), 50.0);
+
+    for _ in 0..200 {
+        throttle.record(100, Duration::from_millis(1));
+    }
+    assert_eq!(throttle.rate(), 1000.0);
+}
+
+#[test]
+fn low_impact_scans_are_paced_and_report_their_rate() {
+    let root = PathBuf::from("/dusk-throttle");
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    for dir in ["a", "b", "c"] {
+        for index in 0..100 {
+            filesystem.add_file(root.join(dir).join(format!("{index}.bin")), 10);
+        }
+    }
+    let (handle, rx) = scanner::spawn_with_config(ScannerConfig {
+        walker_threads: 4,
+        filesystem,
+        progress_interval: Duration::from_millis(10),
+        low_impact: Some(LowImpact {
+            max_ops_per_sec: 1000,
+            latency_target: Duration::from_secs(1),
+        }),
+        ..ScannerConfig::default()
+    });
+
+    let started = Instant::now();
+    let job_id = handle.request_scan(
+        SearchQuery {
+            root: root.clone(),
+            raw: root.display().to_string(),
+            ..SearchQuery::default()
+        },
+        None,
+    );
+    let mut files = 0;
+    let mut rates = Vec::new();
+    while let Ok(message) = rx.recv_timeout(Duration::from_secs(5)) {
+        match message {
+            ScanMessage::Stats { job_id: id, stats } if id == job_id => files = stats.files_scanned,
+            ScanMessage::Progress {
+                job_id: id,
+                progress,
+            } if id == job_id => rates.push(progress.throttled_rate),
+            ScanMessage::Complete { job_id: id } if id == job_id => break,
+            _ => {}
+        }
+    }
+
+    assert_eq!(files, 300);
+    // 304 operations at 1000 per second, however many walker threads share them.
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(250), "took {elapsed:?}");
+    assert!(!rates.is_empty());
+    assert!(rates.iter().all(|rate| *rate == Some(1000.0)), "{rates:?}");
+}
+
+#[test]
+fn operations_booked_beyond_the_rate_are_carried_forward() {
+    let throttle = Throttle::new(LowImpact {
+        max_ops_per_sec: 200,
+        latency_target: Duration::from_secs(1),
+    });
+    let started = Instant::now();
+    throttle.acquire(500);
+    assert!(started.elapsed() < Duration::from_millis(500));
+    // 500 operations at 200 per second take 2.5s; none of that is forgiven.
+    throttle.acquire(1);
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(2400), "took {elapsed:?}");
+}
+
+#[test]
+fn large_directories_are_stated_in_batches() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    for index in 0..150 {
+        std::fs::write(temp.path().join(format!("{index}.bin")), b"x").expect("write file");
+    }
+    let mut batches = Vec::new();
+    let entries = RealFileSystem
+        .read_dir_paced(temp.path(), &mut |count| batches.push(count))
+        .expect("list directory");
+    assert_eq!(entries.len(), 150);
+    assert!(entries.iter().all(|entry| entry.metadata.is_ok()));
+    assert_eq!(batches, vec![64, 64, 22]);
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use disk_space_inspect::fs::{Allocation, FileEntry, FileKind};
+use disk_space_inspect::tree::TreeStore;
+
+fn file(path: &str, len: u64, allocated: u64) -> FileEntry {
+    let path = PathBuf::from(path);
+    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+    let mut entry = FileEntry::new(path, name, FileKind::File, len, allocated, None, None);
+    entry.allocation = Allocation::detect(len, allocated);
+    entry
+}
+
+#[test]
+fn upserting_an_existing_path_replaces_its_allocation() {
+    const MIB: u64 = 1 << 20;
+    let path = PathBuf::from("/dusk-tree/disk.img");
+    let mut tree = TreeStore::default();
+
+    tree.upsert(file("/dusk-tree/disk.img", 64 * MIB, 4 * MIB));
+    assert_eq!(
+        tree.get(&path).unwrap().allocation,
+        Some(Allocation::Sparse)
+    );
+
+    // A refresh after the image was filled in must drop the badge rather than keep the old one.
+    tree.upsert(file("/dusk-tree/disk.img", 64 * MIB, 64 * MIB));
+    assert_eq!(tree.get(&path).unwrap().allocation, None);
+
+    tree.upsert(file("/dusk-tree/disk.img", 4 * MIB, 64 * MIB));
+    assert_eq!(
+        tree.get(&path).unwrap().allocation,
+        Some(Allocation::Preallocated)
+    );
+}
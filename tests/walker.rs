@@ -0,0 +1,194 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use disk_space_inspect::filesystem::{
+    DirEntry, FileReader, FileSystem, FsMetadata, MemoryFileSystem,
+};
+use disk_space_inspect::walker::{ParallelWalker, READ_AHEAD_DIRS};
+
+fn create_file(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).expect("create parent");
+    fs::write(path, contents).expect("write file");
+}
+
+#[test]
+fn parallel_walker_yields_parents_before_children() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let root = temp.path().canonicalize().expect("canonical root");
+    for dir in ["a", "a/b", "a/b/c", "d", "e/f"] {
+        for file in ["one.txt", "two.txt"] {
+            create_file(&root.join(dir).join(file), dir);
+        }
+    }
+
+    for threads in [1, 4] {
+        let mut seen = BTreeSet::new();
+        let walker = ParallelWalker::new(&root, threads, |_: &Path, _: &FsMetadata| true);
+        for entry in walker {
+            let entry = entry.expect("walk entry");
+            let relative = entry.path.strip_prefix(&root).expect("under root");
+            assert_eq!(
+                entry.depth,
+                relative.components().count(),
+                "depth for {}",
+                entry.path.display()
+            );
+            if entry.depth > 0 {
+                let parent = entry.path.parent().expect("parent").to_path_buf();
+                assert!(
+                    seen.contains(&parent),
+                    "{} yielded before its parent",
+                    entry.path.display()
+                );
+            }
+            seen.insert(entry.path);
+        }
+        // Root, 6 directories and 10 files.
+        assert_eq!(seen.len(), 17, "entry count with {threads} threads");
+    }
+}
+
+#[test]
+fn parallel_walker_skips_current_dir() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let root = temp.path().canonicalize().expect("canonical root");
+    create_file(&root.join("keep/file.txt"), "keep");
+    create_file(&root.join("skip/nested/file.txt"), "skip");
+
+    let skip = root.join("skip");
+    let mut walker = ParallelWalker::new(&root, 2, |_: &Path, _: &FsMetadata| false);
+    let mut seen: Vec<PathBuf> = Vec::new();
+    while let Some(entry) = walker.next() {
+        let entry = entry.expect("walk entry");
+        if entry.path == skip {
+            walker.skip_current_dir();
+        }
+        seen.push(entry.path);
+    }
+
+    assert!(seen.contains(&root.join("keep/file.txt")));
+    assert!(seen.contains(&skip));
+    assert!(
+        !seen
+            .iter()
+            .any(|path| path.starts_with(skip.join("nested")))
+    );
+}
+
+/// Counts the directories listed through it.
+#[derive(Debug)]
+struct CountingFileSystem {
+    inner: MemoryFileSystem,
+    listed: AtomicUsize,
+}
+
+impl FileSystem for CountingFileSystem {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        self.inner.symlink_metadata(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        self.inner.metadata(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.listed.fetch_add(1, Ordering::SeqCst);
+        self.inner.read_dir(path)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.read_link(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn FileReader>> {
+        self.inner.open(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_dir_all(path)
+    }
+}
+
+#[test]
+fn parallel_walker_reads_a_bounded_window_ahead() {
+    let root = PathBuf::from("/dusk-walker");
+    let filesystem = Arc::new(CountingFileSystem {
+        inner: MemoryFileSystem::new(),
+        listed: AtomicUsize::new(0),
+    });
+    let dirs = 4 * READ_AHEAD_DIRS;
+    for index in 0..dirs {
+        filesystem.inner.add_dir(root.join(format!("d{index:05}")));
+    }
+
+    let mut walker = ParallelWalker::with_filesystem(
+        &root,
+        4,
+        filesystem.clone(),
+        |_: &Path, _: &FsMetadata| true,
+    );
+    // The root and its first child; the consumer then stalls while workers read ahead.
+    walker.next().expect("root").expect("root entry");
+    walker
+        .next()
+        .expect("first child")
+        .expect("first child entry");
+    thread::sleep(Duration::from_millis(300));
+    let listed = filesystem.listed.load(Ordering::SeqCst);
+    assert!(
+        listed <= READ_AHEAD_DIRS + 2,
+        "{listed} directories listed ahead of a stalled consumer"
+    );
+
+    // Directories left out of the window are still walked once the consumer reaches them.
+    let mut rest = 0;
+    for entry in walker {
+        entry.expect("walk entry");
+        rest += 1;
+    }
+    assert_eq!(2 + rest, 1 + dirs);
+    assert_eq!(filesystem.listed.load(Ordering::SeqCst), 1 + dirs);
+}
+
+#[test]
+fn parallel_walker_releases_read_ahead_of_skipped_dirs() {
+    let root = PathBuf::from("/dusk-walker");
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    for index in 0..32 {
+        for nested in ["a/b/c", "d/e", "f"] {
+            filesystem.add_dir(root.join(format!("d{index:02}")).join(nested));
+        }
+    }
+
+    let mut walker =
+        ParallelWalker::with_filesystem(&root, 4, filesystem, |_: &Path, _: &FsMetadata| true);
+    walker.next().expect("root").expect("root entry");
+    let mut skipped = 0;
+    while let Some(entry) = walker.next() {
+        let entry = entry.expect("walk entry");
+        if skipped == 0 {
+            // Let workers read well into the subtrees about to be skipped.
+            thread::sleep(Duration::from_millis(100));
+        }
+        assert_eq!(entry.depth, 1, "{} was not skipped", entry.path.display());
+        walker.skip_current_dir();
+        skipped += 1;
+    }
+
+    assert_eq!(skipped, 32);
+    assert_eq!(walker.read_ahead_in_use(), 0);
+}
@@ -1,9 +1,9 @@
-use std::time::{Duration, Instant};
-
 #[cfg(target_os = "macos")]
 #[test]
 fn watcher_reports_dirty_event() {
     use disk_space_inspect::watcher;
+    use std::time::{Duration, Instant};
+
     let temp = tempfile::tempdir().expect("temp dir");
     let root = temp.path().to_path_buf();
 